@@ -1,101 +1,892 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use encoder_log::{
+  normalize_path, Audio, Bitrate, EncoderLogParser, Entry, ParseError, ParseErrorKind, ParseMode,
+  QueueState, Status, Video,
+};
 use serde_json::{Map, Value};
-use std;
-use std::fs::File;
-use std::io::prelude::*;
+use timezone::{format_date_time, LogTimezone};
+
+const US_DATE_FORMAT: &str = "%m/%d/%Y %I:%M:%S %p";
+const EUROPEAN_DATE_FORMAT: &str = "%d/%m/%Y %H:%M:%S";
+const LOG_HEADER: &str = "Log File Created: ";
+
+/// Formats tried by the auto-detection, by order of preference
+const DATE_FORMATS: [&str; 7] = [
+  US_DATE_FORMAT,
+  EUROPEAN_DATE_FORMAT,
+  "%d/%m/%Y %I:%M:%S %p",
+  "%m/%d/%Y %H:%M:%S",
+  "%d.%m.%Y %H:%M:%S",
+  "%Y-%m-%d %H:%M:%S",
+  "%Y/%m/%d %H:%M:%S",
+];
+
+/// Format of the timestamps, which depends on the locale of the workstation
+#[derive(Debug, Clone, PartialEq)]
+pub enum DateFormat {
+  /// Detected from the `Log File Created:` header and the first unambiguous dates
+  Auto,
+  /// `02/16/2018 10:11:56 PM`
+  UnitedStates,
+  /// `16/02/2018 22:11:56`
+  European,
+  /// Custom chrono format, like `%d.%m.%Y %H:%M:%S`
+  Custom(String),
+}
+
+impl<'a> From<&'a str> for DateFormat {
+  fn from(value: &'a str) -> Self {
+    match value {
+      "" | "auto" => DateFormat::Auto,
+      "us" => DateFormat::UnitedStates,
+      "eu" | "european" => DateFormat::European,
+      format => DateFormat::Custom(format.to_string()),
+    }
+  }
+}
+
+impl DateFormat {
+  fn candidates(&self) -> Vec<String> {
+    match *self {
+      DateFormat::Auto => DATE_FORMATS.iter().map(|format| format.to_string()).collect(),
+      DateFormat::UnitedStates => vec![US_DATE_FORMAT.to_string()],
+      DateFormat::European => vec![EUROPEAN_DATE_FORMAT.to_string()],
+      DateFormat::Custom(ref format) => vec![format.clone()],
+    }
+  }
+}
 
 #[derive(Debug)]
 pub struct AdobeMediaEncoderLog {
+  pub mode: ParseMode,
   pub entries: Vec<Entry>,
+  pub sessions: Vec<QueueSession>,
+  /// Errors met while parsing, in lenient mode the faulty entries are kept
+  /// with the fields which could be read
+  pub diagnostics: Vec<ParseError>,
+  /// Last line of the file, not terminated yet
+  partial_line: String,
+  /// The partial line has already been parsed, its line ending is still expected
+  partial_line_parsed: bool,
+  /// Date formats matching all the timestamps read so far, the first one is used
+  date_formats: Vec<String>,
+  timezone: LogTimezone,
+  /// Lines of the record being read, with their line number
+  record: Vec<(usize, String)>,
+  line_number: usize,
+  blank_lines: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QueueEvent {
+  Started,
+  Paused,
+  Stopped,
 }
 
+const QUEUE_EVENTS: [(&str, QueueEvent); 3] = [
+  (" : Queue Started", QueueEvent::Started),
+  (" : Queue Paused", QueueEvent::Paused),
+  (" : Queue Stopped", QueueEvent::Stopped),
+];
+
+/// One run of the AME queue, from `Queue Started` to `Queue Stopped`
 #[derive(Debug, Serialize)]
-pub struct Entry {
-  pub date_time: NaiveDateTime,
-  pub input_filename: Option<String>,
-  pub output_filename: Option<String>,
-  pub preset: Option<String>,
+pub struct QueueSession {
+  pub started: DateTime<FixedOffset>,
+  pub stopped: Option<DateTime<FixedOffset>>,
+  pub paused_since: Option<DateTime<FixedOffset>>,
+  /// Cumulated pause time in seconds
+  pub paused_duration: i64,
+  /// Start, resume or end of the last encode of the run
+  pub last_activity: DateTime<FixedOffset>,
+  /// Indexes in `AdobeMediaEncoderLog::entries` of the encodes done during this run
+  pub entries: Vec<usize>,
 }
 
-impl From<Entry> for Value {
-  fn from(entry: Entry) -> Self {
+impl QueueSession {
+  fn new(started: DateTime<FixedOffset>) -> Self {
+    QueueSession {
+      started,
+      stopped: None,
+      paused_since: None,
+      paused_duration: 0,
+      last_activity: started,
+      entries: vec![],
+    }
+  }
+
+  fn is_open(&self) -> bool {
+    self.stopped.is_none()
+  }
+
+  fn pause(&mut self, date_time: DateTime<FixedOffset>) {
+    if self.paused_since.is_none() {
+      self.paused_since = Some(date_time);
+    }
+  }
+
+  fn resume(&mut self, date_time: DateTime<FixedOffset>) {
+    if let Some(paused_since) = self.paused_since.take() {
+      self.paused_duration += (date_time - paused_since).num_seconds();
+      self.last_activity = date_time;
+    }
+  }
+
+  fn stop(&mut self, date_time: DateTime<FixedOffset>) {
+    self.resume(date_time);
+    self.stopped = Some(date_time);
+  }
+
+  pub fn state(&self) -> QueueState {
+    if !self.is_open() {
+      QueueState::Idle
+    } else if self.paused_since.is_some() {
+      QueueState::Paused
+    } else {
+      QueueState::Encoding
+    }
+  }
+}
+
+impl<'a> From<&'a QueueSession> for Value {
+  fn from(session: &'a QueueSession) -> Self {
     let mut m = Map::new();
-    let date_time = format!("{}", entry.date_time.format("%Y-%m-%dT%H:%M:%S"));
-    // println!("{:?}", date_time);
-    m.insert("date_time".to_owned(), date_time.into());
-    if let Some(output_filename) = entry.output_filename {
-      m.insert("output_filename".to_owned(), output_filename.into());
+    m.insert("started".to_owned(), format_date_time(&session.started).into());
+    if let Some(ref stopped) = session.stopped {
+      m.insert("stopped".to_owned(), format_date_time(stopped).into());
     }
-    if let Some(preset) = entry.preset {
-      m.insert("preset".to_owned(), preset.into());
+    if let Some(ref paused_since) = session.paused_since {
+      m.insert("paused_since".to_owned(), format_date_time(paused_since).into());
     }
+    m.insert("paused_duration".to_owned(), session.paused_duration.into());
+    m.insert("last_activity".to_owned(), format_date_time(&session.last_activity).into());
+    m.insert("encodes".to_owned(), session.entries.len().into());
     m.into()
   }
 }
 
-fn to_u16(s8: &mut [u8]) -> &mut [u16] {
-  unsafe { std::slice::from_raw_parts_mut(s8.as_mut_ptr() as *mut u16, s8.len() / 2) }
+const OUTCOMES: [(&str, Status); 3] = [
+  (" : File Successfully Encoded", Status::Success),
+  (" : File Encoded with warning", Status::Warning),
+  (" : Encoding Failed", Status::Failed),
+];
+
+const INTERRUPTED_MESSAGE: &str = "The Operation was interrupted by user";
+
+const ENCODER: &str = "adobe_media_encoder";
+
+fn parse_entry(
+  date_time: DateTime<FixedOffset>,
+  status: Status,
+  messages: Vec<String>,
+  details: &[(usize, &str)],
+  errors: &mut Vec<ParseError>,
+) -> Entry {
+  let mut entry = Entry::new(ENCODER, date_time, status);
+  entry.messages = messages;
+
+  for &(number, line) in details {
+    if line.starts_with(" - Output File: ") {
+      entry.output_filename = Some(normalize_path(&line.replace(" - Output File: ", "")));
+    }
+    // older AME releases wrote ` - Input File: `
+    if line.starts_with(" - Source File: ") || line.starts_with(" - Input File: ") {
+      entry.set_source_filename(
+        line
+          .trim_start_matches(" - Source File: ")
+          .trim_start_matches(" - Input File: "),
+      );
+    }
+    if line.starts_with(" - Preset Used: ") {
+      entry.preset = Some(line.replace(" - Preset Used: ", "").replace("\\", "/"));
+    }
+    if line.starts_with(" - Video: ") {
+      entry.video = Video::parse(&line.replace(" - Video: ", ""));
+      if entry.video.is_none() {
+        errors.push(ParseError::new(number, line, ParseErrorKind::InvalidVideo));
+      }
+    }
+    if line.starts_with(" - Audio: ") {
+      entry.audio = Audio::parse(&line.replace(" - Audio: ", ""));
+      if entry.audio.is_none() {
+        errors.push(ParseError::new(number, line, ParseErrorKind::InvalidAudio));
+      }
+    }
+    if line.starts_with(" - Bitrate: ") {
+      // the bitrate is left empty for uncompressed formats
+      let value = line.replace(" - Bitrate: ", "");
+      entry.bitrate = Bitrate::parse(&value);
+      if entry.bitrate.is_none() && !value.trim().is_empty() {
+        errors.push(ParseError::new(number, line, ParseErrorKind::InvalidBitrate));
+      }
+    }
+    if line.starts_with(" - Encoding Time: ") {
+      entry.encoding_time = parse_duration(&line.replace(" - Encoding Time: ", ""));
+      if entry.encoding_time.is_none() {
+        errors.push(ParseError::new(number, line, ParseErrorKind::InvalidEncodingTime));
+      }
+    }
+  }
+  entry
+}
+
+impl Video {
+  fn parse(line: &str) -> Option<Self> {
+    let parts: Vec<&str> = line.split(", ").collect();
+
+    let (width, height, pixel_aspect_ratio) = {
+      let mut resolution = parts[0].splitn(2, ' ');
+      let mut size = resolution.next()?.splitn(2, 'x');
+      let width = size.next()?.parse::<u32>().ok()?;
+      let height = size.next()?.parse::<u32>().ok()?;
+      let pixel_aspect_ratio = resolution
+        .next()
+        .map(|par| par.trim_matches(|c| c == '(' || c == ')').parse::<f64>());
+      let pixel_aspect_ratio = match pixel_aspect_ratio {
+        Some(par) => Some(par.ok()?),
+        None => None,
+      };
+      (width, height, pixel_aspect_ratio)
+    };
+
+    let frame_rate = match parts.get(1) {
+      Some(fps) => Some(fps.trim_end_matches(" fps").parse::<f64>().ok()?),
+      None => None,
+    };
+    let scan_type = parts.get(2).map(|scan_type| scan_type.to_string());
+    let timecode = parts.get(3).map(|timecode| timecode.to_string());
+    let duration = match (timecode.as_ref(), frame_rate) {
+      (Some(timecode), Some(frame_rate)) => timecode_to_seconds(timecode, frame_rate),
+      _ => None,
+    };
+
+    Some(Video {
+      width,
+      height,
+      pixel_aspect_ratio,
+      frame_rate,
+      scan_type,
+      timecode,
+      duration,
+    })
+  }
+}
+
+impl Audio {
+  fn parse(line: &str) -> Option<Self> {
+    if line.trim().is_empty() {
+      return None;
+    }
+
+    let mut audio = Audio {
+      codec: None,
+      bitrate: None,
+      sample_rate: None,
+      channel_layout: None,
+      bit_depth: None,
+    };
+
+    for (index, part) in line.split(", ").enumerate() {
+      if part.ends_with(" kbps") {
+        audio.bitrate = Some(part.trim_end_matches(" kbps").parse::<u32>().ok()?);
+      } else if part.ends_with(" kHz") {
+        let khz = part.trim_end_matches(" kHz").parse::<f64>().ok()?;
+        audio.sample_rate = Some((khz * 1000.0) as u32);
+      } else if part.ends_with(" Hz") {
+        audio.sample_rate = Some(part.trim_end_matches(" Hz").parse::<u32>().ok()?);
+      } else if part.ends_with(" bit") {
+        audio.bit_depth = Some(part.trim_end_matches(" bit").parse::<u32>().ok()?);
+      } else if index == 0 {
+        audio.codec = Some(part.to_string());
+      } else if !part.is_empty() {
+        audio.channel_layout = Some(part.to_string());
+      }
+    }
+
+    Some(audio)
+  }
+}
+
+impl Bitrate {
+  fn parse(line: &str) -> Option<Self> {
+    if line.trim().is_empty() {
+      return None;
+    }
+
+    let mut bitrate = Bitrate {
+      mode: None,
+      passes: None,
+      target: None,
+      max: None,
+    };
+
+    for part in line.split(", ") {
+      if part.ends_with(" pass") {
+        bitrate.passes = Some(part.trim_end_matches(" pass").parse::<u32>().ok()?);
+      } else if part.starts_with("Target ") {
+        bitrate.target = Some(parse_mbps(part.trim_start_matches("Target "))?);
+      } else if part.starts_with("Max ") {
+        bitrate.max = Some(parse_mbps(part.trim_start_matches("Max "))?);
+      } else if part.ends_with(" Mbps") {
+        bitrate.target = Some(parse_mbps(part)?);
+      } else if !part.is_empty() {
+        bitrate.mode = Some(part.to_string());
+      }
+    }
+
+    Some(bitrate)
+  }
+}
+
+/// Parse the date written before the ` : ` suffix of an event line
+fn parse_date_time(
+  date_formats: &mut Vec<String>,
+  number: usize,
+  line: &str,
+  suffix: &str,
+) -> Result<NaiveDateTime, ParseError> {
+  let dt = line.replace(suffix, "");
+  narrow_date_formats(date_formats, &dt);
+  NaiveDateTime::parse_from_str(&dt, &date_formats[0])
+    .map_err(|_| ParseError::new(number, line, ParseErrorKind::InvalidDate))
+}
+
+/// Keep the formats able to parse this date, unless none of them can
+fn narrow_date_formats(date_formats: &mut Vec<String>, date_time: &str) {
+  if date_formats.len() < 2 {
+    return;
+  }
+
+  let matching: Vec<String> = date_formats
+    .iter()
+    .filter(|format| NaiveDateTime::parse_from_str(date_time, format).is_ok())
+    .cloned()
+    .collect();
+
+  if !matching.is_empty() && matching.len() < date_formats.len() {
+    *date_formats = matching;
+    if date_formats.len() == 1 {
+      info!("Adobe Media Encoder log date format detected: {}", date_formats[0]);
+    }
+  }
 }
 
-fn load_file(filename: &str) -> Result<String, String> {
-  let mut f = File::open(filename).map_err(|e| e.to_string())?;
+/// Date written in a header or an event line
+fn sample_date(line: &str) -> Option<&str> {
+  if let Some(date_time) = line.strip_prefix(LOG_HEADER) {
+    return Some(date_time);
+  }
 
-  let mut contents = Vec::new();
-  f.read_to_end(&mut contents)
-    .expect("something went wrong reading the file");
+  let known = QUEUE_EVENTS.iter().map(|&(suffix, _)| suffix)
+    .chain(OUTCOMES.iter().map(|&(suffix, _)| suffix))
+    .find(|suffix| line.ends_with(suffix));
+  known.map(|suffix| &line[..line.len() - suffix.len()])
+}
+
+/// Whether a record at the end of the file has been entirely written:
+/// a queue event, or an encode outcome followed by its closed message block.
+fn is_complete_record(lines: &[&str]) -> bool {
+  let last = match lines.last() {
+    Some(last) => last,
+    None => return false,
+  };
+
+  if lines.len() == 1 && QUEUE_EVENTS.iter().any(|&(suffix, _)| last.ends_with(suffix)) {
+    return true;
+  }
+
+  let outcome = lines
+    .iter()
+    .position(|line| OUTCOMES.iter().any(|&(suffix, _)| line.ends_with(suffix)));
+
+  match outcome {
+    Some(index) => {
+      let messages = &lines[index + 1..];
+      match messages.len() {
+        0 => line_status(lines[index]) == Some(Status::Success),
+        1 => false,
+        // the closing separator has the same length as the opening one,
+        // a shorter one is still being written
+        _ => is_separator(messages[0]) && messages[0] == *last,
+      }
+    }
+    None => false,
+  }
+}
+
+fn line_status(line: &str) -> Option<Status> {
+  OUTCOMES
+    .iter()
+    .find(|&&(suffix, _)| line.ends_with(suffix))
+    .map(|&(_, status)| status)
+}
+
+/// Lines between the `-----` separators following an outcome line
+fn parse_messages(lines: &[&str]) -> Vec<String> {
+  let mut messages = vec![];
+  let mut iter = lines.iter();
+
+  match iter.next() {
+    Some(line) if is_separator(line) => {}
+    _ => return messages,
+  }
+
+  for line in iter {
+    if is_separator(line) {
+      break;
+    }
+    if !line.trim().is_empty() {
+      messages.push(line.trim().to_string());
+    }
+  }
+  messages
+}
+
+fn is_separator(line: &str) -> bool {
+  !line.is_empty() && line.chars().all(|c| c == '-')
+}
+
+fn parse_mbps(value: &str) -> Option<f64> {
+  value.trim_end_matches(" Mbps").parse::<f64>().ok()
+}
+
+/// Parse `HH:MM:SS` (drop-frame timecodes use `;` as separator)
+fn parse_duration(value: &str) -> Option<u64> {
+  let parts: Vec<&str> = value.split(&[':', ';'][..]).collect();
+  if parts.len() != 3 {
+    return None;
+  }
+
+  let mut seconds = 0;
+  for part in parts {
+    seconds = seconds * 60 + part.parse::<u64>().ok()?;
+  }
+  Some(seconds)
+}
 
-  String::from_utf16(to_u16(contents.as_mut_slice())).map_err(|e| e.to_string())
+/// Convert a `HH:MM:SS:FF` timecode to seconds
+fn timecode_to_seconds(timecode: &str, frame_rate: f64) -> Option<f64> {
+  let separator = timecode.rfind(&[':', ';'][..])?;
+  let seconds = parse_duration(&timecode[..separator])?;
+  let frames = timecode[separator + 1..].parse::<f64>().ok()?;
+  if frame_rate <= 0.0 {
+    return Some(seconds as f64);
+  }
+  Some(seconds as f64 + frames / frame_rate)
 }
 
 impl AdobeMediaEncoderLog {
-  pub fn open(filename: &str) -> Result<Self, String> {
-    let mut entries = vec![];
-    let content = load_file(filename)?;
+  pub fn new(mode: ParseMode, date_format: DateFormat, timezone: LogTimezone) -> Self {
+    AdobeMediaEncoderLog {
+      mode,
+      date_formats: date_format.candidates(),
+      timezone,
+      entries: vec![],
+      sessions: vec![],
+      diagnostics: vec![],
+      partial_line: String::new(),
+      partial_line_parsed: false,
+      record: vec![],
+      line_number: 0,
+      blank_lines: 0,
+    }
+  }
 
-    let lines: Vec<&str> = content.split("\r\n").collect();
-    for (index, line) in lines.iter().enumerate() {
-      if *line == "".to_string() {
-        continue;
+  /// Look at the dates in the text before parsing it, so that ambiguous
+  /// dates (like `02/03/2018`) are parsed with a format which also fits
+  /// the unambiguous ones.
+  fn detect_date_format(&mut self, text: &str) {
+    for line in text.lines() {
+      if self.date_formats.len() < 2 {
+        return;
+      }
+      if let Some(date_time) = sample_date(line.trim_end_matches('\r')) {
+        narrow_date_formats(&mut self.date_formats, date_time);
+      }
+    }
+  }
+
+  fn push_line(&mut self, line: &str) {
+    self.line_number += 1;
+
+    if line.trim().is_empty() {
+      self.blank_lines += 1;
+      // records are separated by two blank lines
+      if self.blank_lines == 2 {
+        self.parse_record();
       }
+      return;
+    }
 
-      if line.ends_with(" : File Successfully Encoded") {
-        let dt = line.replace(" : File Successfully Encoded", "");
-        let date_time = NaiveDateTime::parse_from_str(&dt, "%m/%d/%Y %I:%M:%S %p").unwrap();
+    self.blank_lines = 0;
+    self.record.push((self.line_number, line.to_string()));
+  }
 
-        let mut index_back = 1;
-        let mut input_filename = None;
-        let mut output_filename = None;
-        let mut preset = None;
+  fn parse_record(&mut self) {
+    let record: Vec<(usize, String)> = self.record.drain(..).collect();
+    let lines: Vec<(usize, &str)> = record
+      .iter()
+      .map(|&(number, ref line)| (number, line.as_str()))
+      .collect();
 
-        loop {
-          if index_back > index {
-            break;
+    let (first_number, first_line) = match lines.first() {
+      Some(&first) => first,
+      None => return,
+    };
+    if first_line.starts_with(LOG_HEADER) {
+      return;
+    }
+
+    let mut errors = vec![];
+    let mut recognized = false;
+
+    for (index, &(number, line)) in lines.iter().enumerate() {
+      let queue_event = QUEUE_EVENTS
+        .iter()
+        .find(|&&(suffix, _)| line.ends_with(suffix));
+
+      if let Some(&(suffix, event)) = queue_event {
+        recognized = true;
+        match parse_date_time(&mut self.date_formats, number, line, suffix) {
+          Ok(date_time) => {
+            let date_time = self.timezone.localize(date_time);
+            update_sessions(&mut self.sessions, event, date_time)
           }
+          Err(error) => errors.push(error),
+        }
+        continue;
+      }
 
-          let line = lines[index - index_back];
-          if *line == "".to_string() {
-            entries.push(Entry {
-              date_time,
-              input_filename,
-              output_filename,
-              preset,
-            });
+      let outcome = OUTCOMES
+        .iter()
+        .find(|&&(suffix, _)| line.ends_with(suffix));
+
+      if let Some(&(suffix, status)) = outcome {
+        recognized = true;
+        let date_time = match parse_date_time(&mut self.date_formats, number, line, suffix) {
+          Ok(date_time) => self.timezone.localize(date_time),
+          Err(error) => {
+            errors.push(error);
             break;
           }
-          if line.starts_with(" - Output File: ") {
-            output_filename = Some(line.replace(" - Output File: ", "").replace("\\", "/"));
-          }
-          if line.starts_with(" - Input File: ") {
-            output_filename = Some(line.replace(" - Input File: ", "").replace("\\", "/"));
-          }
-          if line.starts_with(" - Preset Used: ") {
-            preset = Some(line.replace(" - Preset Used: ", "").replace("\\", "/"));
-          }
-          index_back += 1;
+        };
+
+        let following: Vec<&str> = lines[index + 1..].iter().map(|&(_, line)| line).collect();
+        let messages = parse_messages(&following);
+        let status =
+          if status == Status::Failed && messages.iter().any(|m| m == INTERRUPTED_MESSAGE) {
+            Status::Interrupted
+          } else {
+            status
+          };
+
+        let mut entry = parse_entry(date_time, status, messages, &lines[..index], &mut errors);
+        entry.identify(number, line);
+        if self.mode == ParseMode::Lenient || errors.is_empty() {
+          attach_entry(&mut self.sessions, self.entries.len(), date_time);
+          self.entries.push(entry);
         }
+        break;
+      }
+    }
+
+    if !recognized {
+      let kind =
+        if lines.iter().any(|&(_, line)| line.starts_with(" - ")) {
+          ParseErrorKind::MissingOutcome
+        } else {
+          ParseErrorKind::UnexpectedLine
+        };
+      errors.push(ParseError::new(first_number, first_line, kind));
+    }
+
+    for error in &errors {
+      warn!("Adobe Media Encoder log, {}", error);
+    }
+    self.diagnostics.append(&mut errors);
+  }
+
+  pub fn current_session(&self) -> Option<&QueueSession> {
+    self.sessions.last()
+  }
+
+  pub fn queue_state(&self) -> QueueState {
+    self
+      .current_session()
+      .map(|session| session.state())
+      .unwrap_or(QueueState::Idle)
+  }
+}
+
+impl EncoderLogParser for AdobeMediaEncoderLog {
+  fn name(&self) -> &'static str {
+    ENCODER
+  }
+
+  /// A record still being written by AME is kept until it is complete.
+  fn update(&mut self, text: &str) -> Result<usize, ParseError> {
+    let count = self.entries.len();
+    let diagnostics = self.diagnostics.len();
+    self.detect_date_format(text);
+    self.partial_line.push_str(text);
+
+    while let Some(position) = self.partial_line.find('\n') {
+      let line: String = self.partial_line.drain(..position + 1).collect();
+      let line = line.trim_end_matches(&['\r', '\n'][..]);
+      if self.partial_line_parsed {
+        self.partial_line_parsed = false;
+        if line.is_empty() {
+          continue;
+        }
+      }
+      self.push_line(line);
+    }
+
+    let mut record: Vec<&str> = self.record.iter().map(|(_, line)| line.as_str()).collect();
+    let with_partial_line = !self.partial_line.is_empty() && !self.partial_line_parsed;
+    if with_partial_line {
+      record.push(&self.partial_line);
+    }
+
+    if is_complete_record(&record) {
+      if with_partial_line {
+        let line = self.partial_line.split_off(0);
+        self.push_line(&line);
+        self.partial_line_parsed = true;
+      }
+      self.parse_record();
+    }
+
+    if self.mode == ParseMode::Strict {
+      if let Some(error) = self.diagnostics.get(diagnostics) {
+        return Err(error.clone());
+      }
+    }
+    Ok(self.entries.len() - count)
+  }
+
+  fn entries(&self) -> &[Entry] {
+    &self.entries
+  }
+
+  fn diagnostics(&self) -> &[ParseError] {
+    &self.diagnostics
+  }
+
+  /// Payload describing the live state of the queue
+  fn queue_status(&self) -> Option<(QueueState, Value)> {
+    let mut m = Map::new();
+    m.insert("state".to_owned(), self.queue_state().as_str().into());
+    if let Some(session) = self.current_session() {
+      m.insert("session".to_owned(), session.into());
+    }
+    Some((self.queue_state(), m.into()))
+  }
+
+  fn queue_activity(&self) -> Option<DateTime<FixedOffset>> {
+    match self.current_session() {
+      Some(session) if session.state() == QueueState::Encoding => Some(session.last_activity),
+      _ => None,
+    }
+  }
+}
+
+fn update_sessions(
+  sessions: &mut Vec<QueueSession>,
+  event: QueueEvent,
+  date_time: DateTime<FixedOffset>,
+) {
+  let is_open = sessions.last().map(|session| session.is_open()).unwrap_or(false);
+
+  match (event, is_open) {
+    (QueueEvent::Started, true) => {
+      let session = sessions.last_mut().unwrap();
+      if session.paused_since.is_some() {
+        session.resume(date_time);
+      } else {
+        // AME has been restarted without stopping the previous run
+        sessions.push(QueueSession::new(date_time));
       }
     }
+    (QueueEvent::Started, false) => sessions.push(QueueSession::new(date_time)),
+    (QueueEvent::Paused, true) => sessions.last_mut().unwrap().pause(date_time),
+    (QueueEvent::Stopped, true) => sessions.last_mut().unwrap().stop(date_time),
+    (_, false) => {}
+  }
+}
+
+/// AME writes `Queue Stopped` before the outcome of an interrupted encode,
+/// so an entry finishing at the exact stop time still belongs to that run.
+fn attach_entry(sessions: &mut [QueueSession], index: usize, date_time: DateTime<FixedOffset>) {
+  if let Some(session) = sessions.last_mut() {
+    if session.is_open() || session.stopped == Some(date_time) {
+      session.entries.push(index);
+      session.last_activity = session.last_activity.max(date_time);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use encoder_log::{parse_file, SourceKind};
+  use log_reader::{LogReader, LogUpdate};
+
+  const SAMPLE_LOG: &str = "tests/AMEEncodingLog.txt";
+
+  fn parse_sample() -> Box<dyn EncoderLogParser> {
+    parse_file(ENCODER, SAMPLE_LOG, ParseMode::Lenient, "auto", LogTimezone::Utc).unwrap()
+  }
+
+  #[test]
+  fn sample_log_entries() {
+    assert_eq!(parse_sample().entries().len(), 143);
+  }
+
+  #[test]
+  fn entry_fields() {
+    let parser = parse_sample();
+    let entry = &parser.entries()[0];
+
+    assert_eq!(format_date_time(&entry.date_time), "2018-02-19T19:09:56+00:00");
+    assert_eq!(entry.status, Status::Success);
+    assert_eq!(entry.output_filename, Some("H:/DRS/Export/DRS2018_Baxter.mp4".to_owned()));
+    assert_eq!(entry.preset, Some("TandI_MP4Streamer_720p25_10Mbps_-15LUFS".to_owned()));
+    assert_eq!(entry.encoding_time, Some(813));
+
+    let video = entry.video.as_ref().unwrap();
+    assert_eq!((video.width, video.height), (1280, 720));
+    assert_eq!(video.pixel_aspect_ratio, Some(1.0));
+    assert_eq!(video.frame_rate, Some(25.0));
+    assert_eq!(video.scan_type, Some("Progressive".to_owned()));
+    assert_eq!(video.timecode, Some("00:30:24:24".to_owned()));
+    assert_eq!(video.duration, Some(1824.96));
+
+    let audio = entry.audio.as_ref().unwrap();
+    assert_eq!(audio.codec, Some("AAC".to_owned()));
+    assert_eq!(audio.bitrate, Some(160));
+    assert_eq!(audio.sample_rate, Some(48000));
+    assert_eq!(audio.channel_layout, Some("Mono".to_owned()));
+
+    let bitrate = entry.bitrate.as_ref().unwrap();
+    assert_eq!(bitrate.mode, Some("VBR".to_owned()));
+    assert_eq!(bitrate.passes, Some(2));
+    assert_eq!(bitrate.target, Some(5.0));
+    assert_eq!(bitrate.max, Some(10.0));
+  }
+
+  #[test]
+  fn entry_statuses() {
+    let parser = parse_sample();
+    let count = |status| parser.entries().iter().filter(|entry| entry.status == status).count();
+    assert_eq!(count(Status::Success), 128);
+    assert_eq!(count(Status::Warning), 13);
+    assert_eq!(count(Status::Failed), 0);
+    assert_eq!(count(Status::Interrupted), 2);
+
+    let interrupted = parser.entries().iter().find(|entry| entry.status == Status::Interrupted).unwrap();
+    assert_eq!(interrupted.messages, vec![INTERRUPTED_MESSAGE.to_owned()]);
+    assert_eq!(interrupted.output_filename, Some("D:/MDNWorkshop2018/EXPORT/day1_Kim+Mike/Kim+Mike.mxf".to_owned()));
+  }
+
+  #[test]
+  fn chunked_update() {
+    let full = parse_sample();
+    let text = match LogReader::new(SAMPLE_LOG).read().unwrap() {
+      LogUpdate::Appended(text) | LogUpdate::Reset(text) => text,
+    };
+
+    // the text is appended in pieces cutting lines and records anywhere
+    let chars: Vec<char> = text.chars().collect();
+    let mut parser = AdobeMediaEncoderLog::new(ParseMode::Lenient, DateFormat::Auto, LogTimezone::Utc);
+    for chunk in chars.chunks(97) {
+      parser.update(&chunk.iter().collect::<String>()).unwrap();
+    }
+    parser.update("\n").unwrap();
+
+    let ids = |entries: &[Entry]| entries.iter().map(|entry| entry.id.clone()).collect::<Vec<_>>();
+    assert_eq!(ids(parser.entries()), ids(full.entries()));
+    assert_eq!(parser.diagnostics().len(), full.diagnostics().len());
+  }
+
+  #[test]
+  fn sample_log_diagnostics() {
+    let parser = parse_sample();
+    assert_eq!(parser.diagnostics().len(), 5);
+    assert!(parser.diagnostics().iter().all(|error| error.kind == ParseErrorKind::MissingOutcome));
+  }
+
+  const INVALID_VIDEO: &str = concat!(
+    " - Output File: D:\\out.mp4\r\n",
+    " - Video: unknown\r\n",
+    " - Encoding Time: 00:00:10\r\n",
+    "02/19/2018 07:09:56 PM : File Successfully Encoded\r\n",
+    "\r\n",
+    "\r\n",
+  );
+
+  #[test]
+  fn lenient_keeps_faulty_entries() {
+    let mut parser = AdobeMediaEncoderLog::new(ParseMode::Lenient, DateFormat::Auto, LogTimezone::Utc);
+    assert_eq!(parser.update(INVALID_VIDEO).unwrap(), 1);
+    assert_eq!(parser.entries[0].output_filename, Some("D:/out.mp4".to_owned()));
+    assert_eq!(parser.entries[0].encoding_time, Some(10));
+    assert!(parser.entries[0].video.is_none());
+    assert_eq!(parser.diagnostics.len(), 1);
+    assert_eq!(parser.diagnostics[0].kind, ParseErrorKind::InvalidVideo);
+    assert_eq!(parser.diagnostics[0].line, 2);
+  }
+
+  #[test]
+  fn strict_returns_the_error() {
+    let mut parser = AdobeMediaEncoderLog::new(ParseMode::Strict, DateFormat::Auto, LogTimezone::Utc);
+    let error = parser.update(INVALID_VIDEO).unwrap_err();
+    assert_eq!(error.kind, ParseErrorKind::InvalidVideo);
+    assert!(parser.entries.is_empty());
+  }
+
+  const EUROPEAN_LOG: &str = concat!(
+    "Log File Created: 16/02/2018 22:11:56\r\n",
+    "------------------------------------------\r\n",
+    "\r\n",
+    "\r\n",
+    " - Output File: D:\\out.mp4\r\n",
+    "02/03/2018 10:00:00 : File Successfully Encoded\r\n",
+    "\r\n",
+    "\r\n",
+  );
+
+  fn first_date(date_format: DateFormat, text: &str) -> String {
+    let mut parser = AdobeMediaEncoderLog::new(ParseMode::Strict, date_format, LogTimezone::Utc);
+    parser.update(text).unwrap();
+    format_date_time(&parser.entries[0].date_time)
+  }
+
+  #[test]
+  fn date_format_detected_from_the_header() {
+    assert_eq!(first_date(DateFormat::Auto, EUROPEAN_LOG), "2018-03-02T10:00:00+00:00");
+  }
+
+  #[test]
+  fn date_format_configured() {
+    let text = EUROPEAN_LOG.replace("16/02/2018 22:11:56", "02/16/2018 10:11:56 PM");
+    assert_eq!(first_date(DateFormat::from("eu"), EUROPEAN_LOG), "2018-03-02T10:00:00+00:00");
+    assert_eq!(first_date(DateFormat::from("%m/%d/%Y %H:%M:%S"), &text), "2018-02-03T10:00:00+00:00");
+  }
+
+  #[test]
+  fn source_file() {
+    let parser = parse_sample();
+    let entry = &parser.entries()[0];
+    assert_eq!(entry.source_filename, Some("C:/Users/Resolve/AppData/Local/Temp/DRS2018.prproj".to_owned()));
+    assert_eq!(entry.source_kind, Some(SourceKind::PremiereProject));
 
-    Ok(AdobeMediaEncoderLog { entries: entries })
+    // older releases wrote the source as the input file
+    let text = EUROPEAN_LOG.replace(" - Output File:", " - Input File: \\\\nas\\comp.aep\r\n - Output File:");
+    let mut parser = AdobeMediaEncoderLog::new(ParseMode::Strict, DateFormat::Auto, LogTimezone::Utc);
+    parser.update(&text).unwrap();
+    assert_eq!(parser.entries[0].source_filename, Some("//nas/comp.aep".to_owned()));
+    assert_eq!(parser.entries[0].source_kind, Some(SourceKind::AfterEffectsProject));
   }
 }
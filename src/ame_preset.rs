@@ -0,0 +1,547 @@
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Ticks per second of the Premiere and Media Encoder time values
+const TICKS_PER_SECOND: f64 = 254_016_000_000.0;
+
+/// Export settings of an Adobe Media Encoder preset, read from its `.epr`
+/// file. The settings are the `ExporterParam` elements of the preset, found
+/// by their `ParamIdentifier`, the ones missing from the file are `None`.
+#[derive(Debug, Clone)]
+pub struct Preset {
+  pub name: String,
+  pub filename: String,
+  pub comments: Option<String>,
+  /// Name of the exporter, like `H.264` or `QuickTime`
+  pub format: Option<String>,
+  pub video: Option<VideoSettings>,
+  pub audio: Option<AudioSettings>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VideoSettings {
+  pub codec: Option<String>,
+  pub width: Option<u32>,
+  pub height: Option<u32>,
+  pub frame_rate: Option<f64>,
+  pub bitrate_encoding: Option<String>,
+  /// Megabits per second
+  pub target_bitrate: Option<f64>,
+  /// Megabits per second
+  pub max_bitrate: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AudioSettings {
+  pub codec: Option<String>,
+  pub sample_rate: Option<u32>,
+  pub channels: Option<u32>,
+  /// Kilobits per second
+  pub bitrate: Option<u32>,
+}
+
+impl Preset {
+  /// Read the `.epr` file of a preset
+  pub fn load(filename: &str) -> Result<Self, String> {
+    let content = fs::read(filename).map_err(|e| e.to_string())?;
+    Preset::parse(&String::from_utf8_lossy(&content), filename)
+  }
+
+  pub fn parse(content: &str, filename: &str) -> Result<Self, String> {
+    let document = parse_xml(content)?;
+
+    let mut params = HashMap::new();
+    let mut elements = vec![];
+    document.descendants("ExporterParam", &mut elements);
+    for element in elements {
+      if let (Some(identifier), Some(value)) = (element.child_text("ParamIdentifier"), element.child_text("ParamValue")) {
+        params.entry(identifier).or_insert(value);
+      }
+    }
+
+    let name = document
+      .find("PresetName")
+      .map(|element| element.text.trim().to_owned())
+      .filter(|name| !name.is_empty())
+      .or_else(|| {
+        Path::new(filename)
+          .file_stem()
+          .map(|stem| stem.to_string_lossy().into_owned())
+      })
+      .ok_or_else(|| "preset without name".to_owned())?;
+
+    let video = VideoSettings {
+      codec: params.get("ADBEVideoCodec").map(|codec| codec_name(codec)),
+      width: number(&params, "ADBEVideoWidth").map(|width| width as u32),
+      height: number(&params, "ADBEVideoHeight").map(|height| height as u32),
+      frame_rate: number(&params, "ADBEVideoFPS")
+        .filter(|&ticks| ticks > 0.0)
+        .map(|ticks| (TICKS_PER_SECOND / ticks * 1000.0).round() / 1000.0),
+      bitrate_encoding: number(&params, "ADBEVideoBitrateEncoding").map(|encoding| bitrate_encoding(encoding as u32)),
+      target_bitrate: number(&params, "ADBEVideoTargetBitrate"),
+      max_bitrate: number(&params, "ADBEVideoMaxBitrate"),
+    };
+    let audio = AudioSettings {
+      codec: params.get("ADBEAudioCodec").map(|codec| codec_name(codec)),
+      sample_rate: number(&params, "ADBEAudioRatePerSecond").map(|rate| rate as u32),
+      channels: number(&params, "ADBEAudioNumChannels").map(|channels| channels as u32),
+      bitrate: number(&params, "ADBEAudioBitrate").map(|bitrate| bitrate as u32),
+    };
+
+    Ok(Preset {
+      name,
+      filename: filename.to_owned(),
+      comments: document
+        .find("PresetComments")
+        .map(|element| element.text.trim().to_owned())
+        .filter(|comments| !comments.is_empty()),
+      format: document
+        .find("ExporterName")
+        .map(|element| element.text.trim().to_owned())
+        .filter(|format| !format.is_empty()),
+      video: if video.is_empty() { None } else { Some(video) },
+      audio: if audio.is_empty() { None } else { Some(audio) },
+    })
+  }
+}
+
+impl VideoSettings {
+  fn is_empty(&self) -> bool {
+    self.codec.is_none() && self.width.is_none() && self.height.is_none() && self.frame_rate.is_none()
+      && self.bitrate_encoding.is_none() && self.target_bitrate.is_none() && self.max_bitrate.is_none()
+  }
+}
+
+impl AudioSettings {
+  fn is_empty(&self) -> bool {
+    self.codec.is_none() && self.sample_rate.is_none() && self.channels.is_none() && self.bitrate.is_none()
+  }
+}
+
+impl<'a> From<&'a Preset> for Value {
+  fn from(preset: &'a Preset) -> Self {
+    let mut m = Map::new();
+    m.insert("name".to_owned(), preset.name.clone().into());
+    m.insert("filename".to_owned(), preset.filename.clone().into());
+    if let Some(ref comments) = preset.comments {
+      m.insert("comments".to_owned(), comments.clone().into());
+    }
+    if let Some(ref format) = preset.format {
+      m.insert("format".to_owned(), format.clone().into());
+    }
+    if let Some(ref video) = preset.video {
+      let mut v = Map::new();
+      if let Some(ref codec) = video.codec {
+        v.insert("codec".to_owned(), codec.clone().into());
+      }
+      if let Some(width) = video.width {
+        v.insert("width".to_owned(), width.into());
+      }
+      if let Some(height) = video.height {
+        v.insert("height".to_owned(), height.into());
+      }
+      if let Some(frame_rate) = video.frame_rate {
+        v.insert("frame_rate".to_owned(), frame_rate.into());
+      }
+      if let Some(ref bitrate_encoding) = video.bitrate_encoding {
+        v.insert("bitrate_encoding".to_owned(), bitrate_encoding.clone().into());
+      }
+      if let Some(target_bitrate) = video.target_bitrate {
+        v.insert("target_bitrate".to_owned(), target_bitrate.into());
+      }
+      if let Some(max_bitrate) = video.max_bitrate {
+        v.insert("max_bitrate".to_owned(), max_bitrate.into());
+      }
+      m.insert("video".to_owned(), v.into());
+    }
+    if let Some(ref audio) = preset.audio {
+      let mut a = Map::new();
+      if let Some(ref codec) = audio.codec {
+        a.insert("codec".to_owned(), codec.clone().into());
+      }
+      if let Some(sample_rate) = audio.sample_rate {
+        a.insert("sample_rate".to_owned(), sample_rate.into());
+      }
+      if let Some(channels) = audio.channels {
+        a.insert("channels".to_owned(), channels.into());
+      }
+      if let Some(bitrate) = audio.bitrate {
+        a.insert("bitrate".to_owned(), bitrate.into());
+      }
+      m.insert("audio".to_owned(), a.into());
+    }
+    m.into()
+  }
+}
+
+/// Presets found in a directory and its subdirectories, looked up by the
+/// name logged by the encoder.
+#[derive(Debug)]
+pub struct PresetCatalog {
+  pub directory: String,
+  presets: Vec<Preset>,
+  /// Files which could not be read, with the reason
+  errors: Vec<(String, String)>,
+}
+
+impl PresetCatalog {
+  pub fn new(directory: &str) -> Self {
+    PresetCatalog {
+      directory: directory.to_owned(),
+      presets: vec![],
+      errors: vec![],
+    }
+  }
+
+  /// Read again the presets of the directory
+  pub fn scan(&mut self) {
+    self.presets.clear();
+    self.errors.clear();
+    let directory = self.directory.clone();
+    self.scan_directory(Path::new(&directory));
+    self.presets.sort_by(|a, b| a.name.cmp(&b.name));
+    info!("{} presets found in {}", self.presets.len(), self.directory);
+  }
+
+  fn scan_directory(&mut self, directory: &Path) {
+    let entries = match fs::read_dir(directory) {
+      Ok(entries) => entries,
+      Err(msg) => {
+        error!("unable to read preset directory {}: {}", directory.display(), msg);
+        self.errors.push((directory.to_string_lossy().into_owned(), msg.to_string()));
+        return;
+      }
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+      let path = entry.path();
+      // the links to directories are not followed, they may form a cycle
+      if entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false) {
+        self.scan_directory(&path);
+        continue;
+      }
+      let is_preset = match path.extension() {
+        Some(extension) => extension.to_string_lossy().eq_ignore_ascii_case("epr"),
+        None => false,
+      };
+      if !is_preset {
+        continue;
+      }
+      let filename = path.to_string_lossy().into_owned();
+      match Preset::load(&filename) {
+        Ok(preset) => self.presets.push(preset),
+        Err(msg) => {
+          warn!("unable to parse preset {}: {}", filename, msg);
+          self.errors.push((filename, msg));
+        }
+      }
+    }
+  }
+
+  /// Preset with this name, or whose file has this name, ignoring the case.
+  /// The encoders may log the path of the preset, only its last component
+  /// is compared.
+  pub fn find(&self, name: &str) -> Option<&Preset> {
+    let name = name.rsplit('/').next().unwrap_or(name).trim();
+    let named = self.presets.iter().find(|preset| preset.name.eq_ignore_ascii_case(name));
+    named.or_else(|| {
+      self.presets.iter().find(|preset| {
+        match Path::new(&preset.filename).file_stem() {
+          Some(stem) => stem.to_string_lossy().eq_ignore_ascii_case(name),
+          None => false,
+        }
+      })
+    })
+  }
+}
+
+impl<'a> From<&'a PresetCatalog> for Value {
+  fn from(catalog: &'a PresetCatalog) -> Self {
+    let presets: Vec<Value> = catalog.presets.iter().map(|preset| preset.into()).collect();
+    let errors: Vec<Value> = catalog
+      .errors
+      .iter()
+      .map(|(filename, error)| json!({ "filename": filename, "error": error }))
+      .collect();
+    let mut m = Map::new();
+    m.insert("directory".to_owned(), catalog.directory.clone().into());
+    m.insert("presets".to_owned(), presets.into());
+    m.insert("errors".to_owned(), errors.into());
+    m.into()
+  }
+}
+
+fn number(params: &HashMap<String, String>, identifier: &str) -> Option<f64> {
+  params
+    .get(identifier)
+    .and_then(|value| value.trim().parse().ok())
+}
+
+/// Codecs are stored as a four character code in an integer, like
+/// `1635148593` for `avc1`
+fn codec_name(value: &str) -> String {
+  let code = match value.trim().parse::<u32>() {
+    Ok(code) => code,
+    Err(_) => return value.trim().to_owned(),
+  };
+  let bytes = [(code >> 24) as u8, (code >> 16) as u8, (code >> 8) as u8, code as u8];
+  if bytes.iter().all(|&byte| byte == b' ' || byte.is_ascii_graphic()) {
+    String::from_utf8_lossy(&bytes).trim().to_owned()
+  } else {
+    value.trim().to_owned()
+  }
+}
+
+fn bitrate_encoding(value: u32) -> String {
+  match value {
+    0 => "cbr".to_owned(),
+    1 => "vbr_1_pass".to_owned(),
+    2 => "vbr_2_pass".to_owned(),
+    _ => value.to_string(),
+  }
+}
+
+/// Element of an XML document, the attributes are ignored and the text of
+/// mixed content is concatenated.
+#[derive(Debug, Default)]
+struct Element {
+  name: String,
+  text: String,
+  children: Vec<Element>,
+}
+
+impl Element {
+  fn child_text(&self, name: &str) -> Option<String> {
+    self
+      .children
+      .iter()
+      .find(|child| child.name == name)
+      .map(|child| child.text.trim().to_owned())
+  }
+
+  /// First element with this name, depth first
+  fn find(&self, name: &str) -> Option<&Element> {
+    for child in &self.children {
+      if child.name == name {
+        return Some(child);
+      }
+      if let Some(element) = child.find(name) {
+        return Some(element);
+      }
+    }
+    None
+  }
+
+  fn descendants<'a>(&'a self, name: &str, elements: &mut Vec<&'a Element>) {
+    for child in &self.children {
+      if child.name == name {
+        elements.push(child);
+      }
+      child.descendants(name, elements);
+    }
+  }
+}
+
+/// Read the elements and the text of an XML document, enough for the preset
+/// files which have no DTD.
+fn parse_xml(content: &str) -> Result<Element, String> {
+  // the document itself is the bottom of the stack of open elements
+  let mut stack = vec![Element::default()];
+  let mut rest = content.trim_start_matches('\u{feff}');
+
+  while let Some(start) = rest.find('<') {
+    push_text(&mut stack, &rest[..start]);
+    rest = &rest[start..];
+
+    if rest.starts_with("<!--") {
+      rest = skip_after(rest, "-->")?;
+    } else if rest.starts_with("<![CDATA[") {
+      let end = rest.find("]]>").ok_or_else(|| "unterminated CDATA section".to_owned())?;
+      if let Some(element) = stack.last_mut() {
+        element.text.push_str(&rest[9..end]);
+      }
+      rest = &rest[end + 3..];
+    } else if rest.starts_with("<?") {
+      rest = skip_after(rest, "?>")?;
+    } else if rest.starts_with("<!") {
+      rest = skip_after(rest, ">")?;
+    } else if rest.starts_with("</") {
+      let end = rest.find('>').ok_or_else(|| "unterminated end tag".to_owned())?;
+      let name = rest[2..end].trim();
+      if stack.len() < 2 || stack[stack.len() - 1].name != name {
+        return Err(format!("unexpected end tag </{}>", name));
+      }
+      let element = stack.pop().unwrap();
+      if let Some(parent) = stack.last_mut() {
+        parent.children.push(element);
+      }
+      rest = &rest[end + 1..];
+    } else {
+      let end = tag_end(rest).ok_or_else(|| "unterminated tag".to_owned())?;
+      let tag = &rest[1..end];
+      let empty = tag.ends_with('/');
+      let name = tag
+        .split(|c: char| c.is_whitespace() || c == '/')
+        .next()
+        .unwrap_or("");
+      if name.is_empty() {
+        return Err("tag without name".to_owned());
+      }
+      let element = Element {
+        name: name.to_owned(),
+        ..Element::default()
+      };
+      if empty {
+        if let Some(parent) = stack.last_mut() {
+          parent.children.push(element);
+        }
+      } else {
+        stack.push(element);
+      }
+      rest = &rest[end + 1..];
+    }
+  }
+
+  if stack.len() > 1 {
+    return Err(format!("unclosed element <{}>", stack[stack.len() - 1].name));
+  }
+  let document = stack.pop().unwrap_or_default();
+  if document.children.is_empty() {
+    return Err("no XML element".to_owned());
+  }
+  Ok(document)
+}
+
+fn push_text(stack: &mut [Element], text: &str) {
+  if stack.len() < 2 || text.is_empty() {
+    return;
+  }
+  if let Some(element) = stack.last_mut() {
+    element.text.push_str(&unescape(text));
+  }
+}
+
+fn skip_after<'a>(text: &'a str, end: &str) -> Result<&'a str, String> {
+  match text.find(end) {
+    Some(position) => Ok(&text[position + end.len()..]),
+    None => Err(format!("missing {}", end)),
+  }
+}
+
+/// Position of the `>` closing the tag, ignoring the ones in the quoted
+/// attribute values
+fn tag_end(text: &str) -> Option<usize> {
+  let mut quote = None;
+  for (position, c) in text.char_indices() {
+    match (quote, c) {
+      (None, '"') | (None, '\'') => quote = Some(c),
+      (Some(q), _) if q == c => quote = None,
+      (None, '>') => return Some(position),
+      _ => {}
+    }
+  }
+  None
+}
+
+fn unescape(text: &str) -> String {
+  if !text.contains('&') {
+    return text.to_owned();
+  }
+  let mut result = String::with_capacity(text.len());
+  let mut rest = text;
+  while let Some(start) = rest.find('&') {
+    result.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let end = match rest.find(';') {
+      Some(end) => end,
+      None => break,
+    };
+    let entity = &rest[1..end];
+    let character = match entity {
+      "lt" => Some('<'),
+      "gt" => Some('>'),
+      "amp" => Some('&'),
+      "quot" => Some('"'),
+      "apos" => Some('\''),
+      _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(::std::char::from_u32),
+      _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(::std::char::from_u32),
+      _ => None,
+    };
+    match character {
+      Some(character) => {
+        result.push(character);
+        rest = &rest[end + 1..];
+      }
+      None => {
+        result.push('&');
+        rest = &rest[1..];
+      }
+    }
+  }
+  result.push_str(rest);
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SAMPLE_PRESET: &str = "tests/presets/H264_1080p25.epr";
+
+  #[test]
+  fn preset_file() {
+    let preset = Preset::load(SAMPLE_PRESET).unwrap();
+    assert_eq!(preset.name, "H.264 1080p 25");
+    assert_eq!(preset.comments, Some("Delivery <review> & archive".to_owned()));
+    assert_eq!(preset.format, Some("H.264".to_owned()));
+
+    let video = preset.video.unwrap();
+    assert_eq!(video.codec, Some("avc1".to_owned()));
+    assert_eq!((video.width, video.height), (Some(1920), Some(1080)));
+    assert_eq!(video.frame_rate, Some(25.0));
+    assert_eq!(video.bitrate_encoding, Some("vbr_2_pass".to_owned()));
+    assert_eq!((video.target_bitrate, video.max_bitrate), (Some(10.0), Some(12.0)));
+
+    let audio = preset.audio.unwrap();
+    assert_eq!(audio.codec, Some("aac".to_owned()));
+    assert_eq!((audio.sample_rate, audio.channels, audio.bitrate), (Some(48000), Some(2), Some(320)));
+  }
+
+  #[test]
+  fn xml_documents() {
+    let preset = Preset::parse("<ExportPreset><ExporterName>QuickTime</ExporterName></ExportPreset>", "/presets/ProRes HQ.epr").unwrap();
+    assert_eq!(preset.name, "ProRes HQ");
+    assert!(preset.video.is_none() && preset.audio.is_none());
+
+    let document = parse_xml("<a x='1 > 0'><b/>text &#x41;&#66;&unknown;<c>nested</c></a>").unwrap();
+    let a = &document.children[0];
+    assert_eq!(a.text, "text AB&unknown;");
+    let names: Vec<&str> = a.children.iter().map(|child| child.name.as_str()).collect();
+    assert_eq!(names, vec!["b", "c"]);
+
+    assert_eq!(parse_xml("<a><b></a>").unwrap_err(), "unexpected end tag </a>");
+    assert_eq!(parse_xml("<a>").unwrap_err(), "unclosed element <a>");
+    assert_eq!(parse_xml("<!-- only a comment -->").unwrap_err(), "no XML element");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn directory_cycles() {
+    use std::env;
+    use std::os::unix::fs::symlink;
+
+    let directory = env::temp_dir().join(format!("skia_presets_{}", ::std::process::id()));
+    fs::create_dir_all(directory.join("H.264")).unwrap();
+    fs::copy(SAMPLE_PRESET, directory.join("H.264").join("H264_1080p25.epr")).unwrap();
+    symlink(&directory, directory.join("H.264").join("loop")).unwrap();
+
+    let mut catalog = PresetCatalog::new(&directory.to_string_lossy());
+    catalog.scan();
+    fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(catalog.presets.len(), 1);
+    assert!(catalog.errors.is_empty());
+    assert!(catalog.find("h.264 1080P 25").is_some());
+    assert!(catalog.find("C:/Presets/H264_1080p25").is_some());
+  }
+}
@@ -1,52 +1,192 @@
+use chrono::{DateTime, Utc};
+use listing::{ListingOptions, Position};
+use mp4_probe;
+use path_resolver::PathError;
+use timezone::format_date_time;
+use volume::{Volume, Volumes};
 use phoenix::event::Event;
 use phoenix::message::Message;
 use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
+use std::path::Path;
 
+/// Request of the backend on the browser channel. The `request_id` of its
+/// body is echoed in the replies, so that concurrent requests can be told
+/// apart.
 #[derive(Debug)]
-struct FileSystem {
-  path: String,
-}
-
-impl FileSystem {
-  fn from(content: Value) -> Option<Self> {
-    if let Value::Object(map) = content {
-      if let Some(value) = map.get("body") {
-        if let &Value::Object(ref map) = value {
-          if let Some(path) = map.get("path") {
-            if let &Value::String(ref string_path) = path {
-              return Some(FileSystem {
-                path: string_path.to_owned(),
-              });
-            }
-          }
-        }
-      }
+struct Request {
+  event: Option<String>,
+  id: Option<Value>,
+  path: Option<String>,
+  body: Value,
+}
+
+impl Request {
+  fn from(message: Message) -> Self {
+    let event = match message.event {
+      Event::Custom(event) => Some(event),
+      _ => None,
+    };
+    let body = match message.payload {
+      Value::Object(mut map) => map.remove("body").unwrap_or(Value::Null),
+      _ => Value::Null,
+    };
+    Request::new(event, body)
+  }
+
+  fn new(event: Option<String>, body: Value) -> Self {
+    let id = match body.get("request_id") {
+      Some(id) if id.is_string() || id.is_number() => Some(id.clone()),
+      _ => None,
+    };
+    let path = body.get("path").and_then(|path| path.as_str()).map(|path| path.to_owned());
+
+    Request {
+      event,
+      id,
+      path,
+      body,
     }
+  }
 
-    None
+  fn path(&self) -> Result<&str, RequestError> {
+    match self.path {
+      Some(ref path) => Ok(path),
+      None => Err(RequestError::new("invalid_request", "the request has no path".to_owned())),
+    }
+  }
+
+  /// Add to a reply what identifies the request
+  fn reply(&self, mut response: Value, status: &str) -> Value {
+    if let Some(ref id) = self.id {
+      response["request_id"] = id.clone();
+    }
+    if let Some(ref event) = self.event {
+      response["event"] = event.clone().into();
+    }
+    if let Some(ref path) = self.path {
+      response["path"] = path.clone().into();
+    }
+    response["status"] = status.into();
+    response
+  }
+
+  fn failed(&self, error: &RequestError) -> Value {
+    self.reply(json!({ "error": error.message, "code": error.code }), "error")
   }
 }
 
+/// Failure of a request, with a code the UI can rely on: the codes of
+/// `PathError`, `invalid_request`, `unknown_event`, `not_a_directory`,
+/// `unsupported_media` or `probe_failed`.
+#[derive(Debug)]
+pub struct RequestError {
+  pub code: &'static str,
+  pub message: String,
+}
+
+impl RequestError {
+  fn new(code: &'static str, message: String) -> Self {
+    RequestError { code, message }
+  }
+}
+
+impl From<PathError> for RequestError {
+  fn from(error: PathError) -> Self {
+    RequestError::new(error.code(), error.to_string())
+  }
+}
+
+impl fmt::Display for RequestError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} ({})", self.message, self.code)
+  }
+}
+
+/// A page of a directory listing
 #[derive(Debug, Serialize)]
 pub struct FileSystemResponse {
   pub entries: Vec<FileSystemEntry>,
+  /// Entries matching the filters, in all the pages
+  pub total: usize,
+  /// Index of the first entry of the page, after the cursor when one is given
+  pub offset: usize,
+  /// To send back as `cursor` to get the next page
+  pub next_cursor: Option<String>,
 }
 
 impl From<FileSystemResponse> for Value {
   fn from(response: FileSystemResponse) -> Self {
     let mut m = Map::new();
     m.insert("entries".to_string(), response.entries.into());
+    m.insert("total".to_string(), response.total.into());
+    m.insert("offset".to_string(), response.offset.into());
+    m.insert("next_cursor".to_string(), response.next_cursor.into());
     m.into()
   }
 }
 
+/// Message of a streamed listing, the last one has no entries and tells the
+/// listing is done
+#[derive(Debug)]
+pub struct ListingChunk {
+  pub sequence: usize,
+  pub entries: Vec<FileSystemEntry>,
+  /// Total and next cursor, on the last message
+  pub end: Option<(usize, Option<String>)>,
+}
+
+impl From<ListingChunk> for Value {
+  fn from(chunk: ListingChunk) -> Self {
+    let mut m = Map::new();
+    m.insert("sequence".to_string(), chunk.sequence.into());
+    m.insert("done".to_string(), chunk.end.is_some().into());
+    match chunk.end {
+      Some((total, next_cursor)) => {
+        m.insert("total".to_string(), total.into());
+        m.insert("next_cursor".to_string(), next_cursor.into());
+      }
+      None => {
+        m.insert("entries".to_string(), chunk.entries.into());
+      }
+    }
+    m.into()
+  }
+}
+
+/// Entry of a directory listing. A symbolic link is described by its target
+/// when the browsing root allows to follow it, by itself otherwise.
 #[derive(Debug, Serialize)]
 pub struct FileSystemEntry {
   pub root: String,
   pub filename: String,
   pub is_dir: bool,
   pub is_file: bool,
+  pub size: u64,
+  pub modified: Option<DateTime<Utc>>,
+  pub created: Option<DateTime<Utc>>,
+  pub is_symlink: bool,
+  pub symlink_target: Option<String>,
+  pub is_hidden: bool,
+  /// Read-only file, or file of a read-only volume
+  pub readonly: bool,
+  /// Unix permission bits
+  pub mode: Option<u32>,
+  pub owner: Option<Account>,
+  pub group: Option<Account>,
+  /// Guessed from the extension of the filename
+  pub mime_type: String,
+}
+
+/// User or group owning a file, the name is unknown when the id is not
+/// listed on the host
+#[derive(Debug, Clone, Serialize)]
+pub struct Account {
+  pub id: u32,
+  pub name: Option<String>,
 }
 
 impl From<FileSystemEntry> for Value {
@@ -58,37 +198,435 @@ impl From<FileSystemEntry> for Value {
     m.insert("is_dir".to_string(), response.is_dir.into());
     m.insert("is_file".to_string(), response.is_file.into());
     m.insert("abs_path".to_string(), abs_path.into());
+    m.insert("size".to_string(), response.size.into());
+    if let Some(modified) = response.modified {
+      m.insert("modified".to_string(), format_date_time(&modified).into());
+    }
+    if let Some(created) = response.created {
+      m.insert("created".to_string(), format_date_time(&created).into());
+    }
+    m.insert("is_symlink".to_string(), response.is_symlink.into());
+    if let Some(symlink_target) = response.symlink_target {
+      m.insert("symlink_target".to_string(), symlink_target.into());
+    }
+    m.insert("is_hidden".to_string(), response.is_hidden.into());
+    m.insert("readonly".to_string(), response.readonly.into());
+    if let Some(mode) = response.mode {
+      m.insert("mode".to_string(), format!("{:04o}", mode).into());
+    }
+    if let Some(owner) = response.owner {
+      m.insert("owner".to_string(), owner.into());
+    }
+    if let Some(group) = response.group {
+      m.insert("group".to_string(), group.into());
+    }
+    m.insert("mime_type".to_string(), response.mime_type.into());
+    m.into()
+  }
+}
+
+impl From<Account> for Value {
+  fn from(account: Account) -> Self {
+    let mut m = Map::new();
+    m.insert("id".to_string(), account.id.into());
+    if let Some(name) = account.name {
+      m.insert("name".to_string(), name.into());
+    }
     m.into()
   }
 }
 
-pub fn process(message: Message, root_path: &str) -> FileSystemResponse {
-  let mut result = vec![];
-  if let Event::Custom(event) = message.event {
-    match event.as_str() {
-      "file_system" => {
-        if let Some(order) = FileSystem::from(message.payload) {
-          let full_path = root_path.to_owned() + "/"+ &order.path;
-          info!("Browse: {}", full_path);
-
-          if let Ok(paths) = fs::read_dir(full_path.clone()) {
-            for path in paths {
-              if let Ok(entry) = path {
-                if let Ok(metadata) = entry.metadata() {
-                  result.push(FileSystemEntry {
-                    filename: entry.file_name().to_str().unwrap().to_string(),
-                    root: full_path.to_owned(),
-                    is_dir: metadata.is_dir(),
-                    is_file: metadata.is_file(),
-                  })
-                }
-              }
-            }
-          }
-        }
+impl FileSystemEntry {
+  fn read(entry: &fs::DirEntry, root: &str, volume: &Volume, accounts: &Accounts) -> Option<Self> {
+    let path = entry.path();
+    let filename = entry.file_name().to_string_lossy().into_owned();
+    let link_metadata = entry.metadata().ok()?;
+    let is_symlink = link_metadata.file_type().is_symlink();
+
+    // the target of a link is only read when it could be browsed, so that
+    // nothing is told about the files outside of the root
+    let target_metadata =
+      if is_symlink && volume.resolver.resolve_absolute(&path.to_string_lossy()).is_ok() {
+        fs::metadata(&path).ok()
+      } else {
+        None
+      };
+    let symlink_target =
+      match target_metadata {
+        Some(_) => fs::read_link(&path).ok().map(|target| target.to_string_lossy().into_owned()),
+        None => None,
+      };
+    let metadata = target_metadata.unwrap_or(link_metadata);
+
+    let mime_type =
+      if metadata.is_dir() {
+        "inode/directory"
+      } else if metadata.file_type().is_symlink() {
+        "inode/symlink"
+      } else {
+        mime_type(&filename)
+      };
+    let (mode, owner, group) = ownership(&metadata);
+
+    Some(FileSystemEntry {
+      root: root.to_owned(),
+      is_dir: metadata.is_dir(),
+      is_file: metadata.is_file(),
+      size: metadata.len(),
+      modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+      created: metadata.created().ok().map(DateTime::<Utc>::from),
+      is_symlink,
+      symlink_target,
+      is_hidden: is_hidden(&filename, &metadata),
+      readonly: volume.read_only || metadata.permissions().readonly(),
+      mode,
+      owner: owner.map(|id| accounts.user(id)),
+      group: group.map(|id| accounts.group(id)),
+      mime_type: mime_type.to_owned(),
+      filename,
+    })
+  }
+}
+
+/// Names of the users and groups of the host, looked up once per listing
+/// through the name service so that LDAP or AD accounts are named too
+#[derive(Debug, Default)]
+struct Accounts {
+  users: RefCell<HashMap<u32, Option<String>>>,
+  groups: RefCell<HashMap<u32, Option<String>>>,
+}
+
+impl Accounts {
+  fn load() -> Self {
+    Accounts::default()
+  }
+
+  fn user(&self, id: u32) -> Account {
+    let name = self.users.borrow_mut().entry(id).or_insert_with(|| user_name(id)).clone();
+    Account { id, name }
+  }
+
+  fn group(&self, id: u32) -> Account {
+    let name = self.groups.borrow_mut().entry(id).or_insert_with(|| group_name(id)).clone();
+    Account { id, name }
+  }
+}
+
+/// Call a reentrant `get*id_r` function of the C library, growing its
+/// buffer while it is too small
+#[cfg(unix)]
+fn lookup_name<T, L, N>(lookup: L, name: N) -> Option<String>
+where
+  L: Fn(&mut T, &mut [libc::c_char], &mut *mut T) -> libc::c_int,
+  N: Fn(&T) -> *const libc::c_char,
+{
+  use std::ffi::CStr;
+  use std::mem;
+  use std::ptr;
+
+  let mut entry: T = unsafe { mem::zeroed() };
+  let mut buffer = vec![0; 1024];
+  loop {
+    let mut result = ptr::null_mut();
+    match lookup(&mut entry, &mut buffer, &mut result) {
+      libc::ERANGE if buffer.len() < 1024 * 1024 => {
+        let size = buffer.len() * 2;
+        buffer.resize(size, 0);
+      }
+      0 if !result.is_null() => {
+        return Some(unsafe { CStr::from_ptr(name(&entry)) }.to_string_lossy().into_owned());
+      }
+      _ => return None,
+    }
+  }
+}
+
+#[cfg(unix)]
+fn user_name(id: u32) -> Option<String> {
+  use libc;
+  lookup_name(
+    |passwd, buffer, result| unsafe { libc::getpwuid_r(id, passwd, buffer.as_mut_ptr(), buffer.len(), result) },
+    |passwd: &libc::passwd| passwd.pw_name,
+  )
+}
+
+#[cfg(unix)]
+fn group_name(id: u32) -> Option<String> {
+  use libc;
+  lookup_name(
+    |group, buffer, result| unsafe { libc::getgrgid_r(id, group, buffer.as_mut_ptr(), buffer.len(), result) },
+    |group: &libc::group| group.gr_name,
+  )
+}
+
+#[cfg(not(unix))]
+fn user_name(_id: u32) -> Option<String> {
+  None
+}
+
+#[cfg(not(unix))]
+fn group_name(_id: u32) -> Option<String> {
+  None
+}
+
+#[cfg(unix)]
+fn ownership(metadata: &fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+  use std::os::unix::fs::MetadataExt;
+  (Some(metadata.mode() & 0o7777), Some(metadata.uid()), Some(metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn ownership(_metadata: &fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+  (None, None, None)
+}
+
+#[cfg(windows)]
+fn is_hidden(filename: &str, metadata: &fs::Metadata) -> bool {
+  use std::os::windows::fs::MetadataExt;
+  const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+  filename.starts_with('.') || metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+}
+
+#[cfg(not(windows))]
+fn is_hidden(filename: &str, _metadata: &fs::Metadata) -> bool {
+  filename.starts_with('.')
+}
+
+/// MIME type of a file from its extension, mostly the media handled by the
+/// encoders
+fn mime_type(filename: &str) -> &'static str {
+  let extension = Path::new(filename)
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_lowercase())
+    .unwrap_or_default();
+  match extension.as_str() {
+    "mp4" => "video/mp4",
+    "m4v" => "video/x-m4v",
+    "mov" => "video/quicktime",
+    "mxf" => "application/mxf",
+    "mkv" => "video/x-matroska",
+    "webm" => "video/webm",
+    "avi" => "video/x-msvideo",
+    "mpg" | "mpeg" => "video/mpeg",
+    "ts" | "m2ts" => "video/mp2t",
+    "wmv" => "video/x-ms-wmv",
+    "flv" => "video/x-flv",
+    "wav" => "audio/wav",
+    "aif" | "aiff" => "audio/aiff",
+    "mp3" => "audio/mpeg",
+    "m4a" => "audio/mp4",
+    "aac" => "audio/aac",
+    "flac" => "audio/flac",
+    "ogg" => "audio/ogg",
+    "jpg" | "jpeg" => "image/jpeg",
+    "png" => "image/png",
+    "gif" => "image/gif",
+    "tif" | "tiff" => "image/tiff",
+    "bmp" => "image/bmp",
+    "svg" => "image/svg+xml",
+    "dpx" => "image/x-dpx",
+    "exr" => "image/x-exr",
+    "srt" => "application/x-subrip",
+    "vtt" => "text/vtt",
+    "scc" => "text/x-scc",
+    "txt" | "log" => "text/plain",
+    "csv" => "text/csv",
+    "json" => "application/json",
+    "xml" | "epr" => "application/xml",
+    "html" | "htm" => "text/html",
+    "pdf" => "application/pdf",
+    "zip" => "application/zip",
+    _ => "application/octet-stream",
+  }
+}
+
+/// Reply to a request of the backend, a streamed listing replies several
+/// times. A failed request gets a single reply with its `error` and `code`.
+pub fn process<F: FnMut(Value)>(message: Message, volumes: &Volumes, reply: F) {
+  answer(&Request::from(message), volumes, reply)
+}
+
+fn answer<F: FnMut(Value)>(request: &Request, volumes: &Volumes, mut reply: F) {
+  let result =
+    match request.event {
+      Some(ref event) if event == "probe" => {
+        probe(request, volumes).map(|media| reply(request.reply(media, "ok")))
+      }
+      Some(ref event) if event == "file_system" => {
+        list(request, volumes, |response| reply(request.reply(response, "ok")))
       }
-      _ => {}
+      Some(ref event) if event == "list_volumes" => {
+        let volumes: Vec<Value> = volumes.volumes.iter().map(Value::from).collect();
+        reply(request.reply(json!({ "volumes": volumes }), "ok"));
+        Ok(())
+      }
+      Some(ref event) => Err(RequestError::new("unknown_event", format!("unknown event {}", event))),
+      None => Err(RequestError::new("unknown_event", "the request has no event".to_owned())),
+    };
+
+  if let Err(error) = result {
+    warn!("browser request {:?} failed: {}", request.id, error);
+    reply(request.failed(&error));
+  }
+}
+
+/// Technical metadata of a media file
+fn probe(request: &Request, volumes: &Volumes) -> Result<Value, RequestError> {
+  let path = request.path()?;
+  let (_, full_path) = volumes.resolve(path)?;
+  let full_path = full_path.to_string_lossy().into_owned();
+  info!("Probe: {}", full_path);
+
+  if !mp4_probe::is_supported(&full_path) {
+    return Err(RequestError::new("unsupported_media", format!("{} is not a MP4 or QuickTime file", path)));
+  }
+  match mp4_probe::probe(&full_path) {
+    Ok(media) => Ok(json!({ "media": Value::from(media) })),
+    Err(msg) => Err(RequestError::new("probe_failed", msg)),
+  }
+}
+
+fn list<F: FnMut(Value)>(request: &Request, volumes: &Volumes, mut reply: F) -> Result<(), RequestError> {
+  let options = ListingOptions::from_payload(&request.body).map_err(|msg| RequestError::new("invalid_request", msg))?;
+  let path = request.path()?;
+  let (volume, full_path) = volumes.resolve(path)?;
+  let mut reply = |mut response: Value| {
+    response["volume"] = volume.name.clone().into();
+    reply(response)
+  };
+  if !full_path.is_dir() {
+    return Err(RequestError::new("not_a_directory", format!("{} is not a directory", path)));
+  }
+  let full_path = full_path.to_string_lossy().into_owned() + "/";
+  info!("Browse: {}", full_path);
+
+  let paths = fs::read_dir(&full_path).map_err(|e| PathError::from_io(path, &e))?;
+  // only the names, and the metadata the sort needs, are read from the whole
+  // directory, the entries are described for the selected page
+  let positions = paths
+    .filter_map(|path| path.ok())
+    .filter(|entry| options.matches(&entry.file_name().to_string_lossy()))
+    .map(|entry| (Position::read(&entry, options.sort), entry))
+    .collect();
+  let page = options.select(positions);
+
+  let accounts = Accounts::load();
+  let describe = |entries: &[fs::DirEntry]| -> Vec<FileSystemEntry> {
+    entries
+      .iter()
+      .filter_map(|entry| FileSystemEntry::read(entry, &full_path, volume, &accounts))
+      .collect()
+  };
+
+  match options.chunk_size {
+    Some(chunk_size) => {
+      let mut sequence = 0;
+      for entries in page.items.chunks(chunk_size) {
+        reply(ListingChunk {
+          sequence,
+          entries: describe(entries),
+          end: None,
+        }.into());
+        sequence += 1;
+      }
+      reply(ListingChunk {
+        sequence,
+        entries: vec![],
+        end: Some((page.total, page.next_cursor)),
+      }.into());
     }
+    None => reply(FileSystemResponse {
+      entries: describe(&page.items),
+      total: page.total,
+      offset: page.start,
+      next_cursor: page.next_cursor,
+    }.into()),
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use path_resolver::SymlinkPolicy;
+  use std::env;
+  use std::process;
+
+  /// Replies of a request on a root with `clip.mp4` and `archive/`
+  fn replies_to(name: &str, event: Option<&str>, body: Value) -> Vec<Value> {
+    let root = env::temp_dir().join(format!("skia_browser_{}_{}", name, process::id()));
+    fs::create_dir_all(root.join("archive")).unwrap();
+    fs::write(root.join("clip.mp4"), "clip").unwrap();
+    let volumes = Volumes::single(&root.to_string_lossy(), "", SymlinkPolicy::WithinRoot);
+
+    let mut replies = vec![];
+    let request = Request::new(event.map(|event| event.to_owned()), body);
+    answer(&request, &volumes, |reply| replies.push(reply));
+    fs::remove_dir_all(&root).unwrap();
+    replies
+  }
+
+  fn error_code(name: &str, event: Option<&str>, body: Value) -> Value {
+    let replies = replies_to(name, event, body);
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0]["status"], "error");
+    assert!(replies[0]["error"].is_string());
+    replies[0]["code"].clone()
+  }
+
+  #[test]
+  fn listing_reply() {
+    let replies = replies_to("listing", Some("file_system"), json!({ "request_id": "r-1", "path": "/" }));
+    assert_eq!(replies.len(), 1);
+    let reply = &replies[0];
+    assert_eq!(reply["request_id"], "r-1");
+    assert_eq!(reply["event"], "file_system");
+    assert_eq!(reply["path"], "/");
+    assert_eq!(reply["status"], "ok");
+    assert_eq!(reply["volume"], "default");
+    assert_eq!((reply["total"].clone(), reply["offset"].clone()), (json!(2), json!(0)));
+    let names: Vec<&Value> = reply["entries"].as_array().unwrap().iter().map(|entry| &entry["filename"]).collect();
+    assert_eq!(names, vec!["archive", "clip.mp4"]);
+    assert!(reply.get("code").is_none());
+  }
+
+  #[test]
+  fn request_ids() {
+    let replies = replies_to("ids", Some("list_volumes"), json!({ "request_id": 42 }));
+    assert_eq!(replies[0]["request_id"], 42);
+    assert_eq!(replies[0]["status"], "ok");
+    assert_eq!(replies[0]["volumes"][0]["name"], "default");
+    assert!(replies[0].get("path").is_none());
+
+    let replies = replies_to("error_ids", Some("file_system"), json!({ "request_id": "r-2", "path": "missing" }));
+    assert_eq!(replies[0]["request_id"], "r-2");
+    assert_eq!(replies[0]["path"], "missing");
+    assert_eq!(replies[0]["event"], "file_system");
+
+    // only a string or a number identifies a request
+    let replies = replies_to("object_ids", Some("list_volumes"), json!({ "request_id": { "id": 1 } }));
+    assert!(replies[0].get("request_id").is_none());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn account_names() {
+    let accounts = Accounts::load();
+    assert_eq!(accounts.user(0).name, Some("root".to_owned()));
+    assert_eq!(accounts.group(0).name, Some("root".to_owned()));
+    assert_eq!(accounts.user(0xfff_fff0).name, None);
+    assert!(accounts.users.borrow().contains_key(&0xfff_fff0));
+  }
+
+  #[test]
+  fn error_codes() {
+    assert_eq!(error_code("not_found", Some("file_system"), json!({ "path": "missing" })), "not_found");
+    assert_eq!(error_code("outside", Some("file_system"), json!({ "path": "../" })), "outside_root");
+    assert_eq!(error_code("file", Some("file_system"), json!({ "path": "clip.mp4" })), "not_a_directory");
+    assert_eq!(error_code("event", Some("rename"), json!({ "path": "clip.mp4" })), "unknown_event");
+    assert_eq!(error_code("no_event", None, json!({ "path": "clip.mp4" })), "unknown_event");
+    assert_eq!(error_code("no_path", Some("file_system"), json!({})), "invalid_request");
+    assert_eq!(error_code("sort", Some("file_system"), json!({ "path": "/", "sort": "date" })), "invalid_request");
+    assert_eq!(error_code("probe", Some("probe"), json!({ "path": "archive" })), "unsupported_media");
   }
-  FileSystemResponse { entries: result }
 }
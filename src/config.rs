@@ -45,6 +45,18 @@ pub fn get_root_path_browsing(arg: Option<&str>) -> String {
   get_env_value!("ROOT_PATH_BROWSING", arg, "/tmp/")
 }
 
+/// Symbolic links met while browsing: `within_root` follows the ones whose
+/// target is under the root, `follow` all of them and `deny` none
+pub fn get_browsing_symlinks(arg: Option<&str>) -> String {
+  get_env_value!("BROWSING_SYMLINKS", arg, "within_root")
+}
+
+/// JSON file of the volumes offered to the browser, see `volume::Volumes`.
+/// The browsing root is the only volume when empty.
+pub fn get_browsing_volumes_filename(arg: Option<&str>) -> String {
+  get_env_value!("BROWSING_VOLUMES_FILENAME", arg, "")
+}
+
 pub fn get_mounted_name_path_browsing(arg: Option<&str>) -> String {
   get_env_value!("MOUNTED_NAME_PATH_BROWSING", arg, "H:/NTS2018 mp4s/")
 }
@@ -60,3 +72,114 @@ pub fn get_adobe_media_encoder_log_filename(arg: Option<&str>) -> String {
     "tests/AMEEncodingLog.txt"
   )
 }
+
+pub fn get_adobe_media_encoder_date_format(arg: Option<&str>) -> String {
+  get_env_value!("ADOBE_MEDIA_ENCODER_DATE_FORMAT", arg, "auto")
+}
+
+/// Directory of the `.epr` presets published to the backend, none when empty
+pub fn get_adobe_media_encoder_preset_directory(arg: Option<&str>) -> String {
+  get_env_value!("ADOBE_MEDIA_ENCODER_PRESET_DIRECTORY", arg, "")
+}
+
+/// Comma separated names of the parsers run by the watcher, see `encoder_log::PARSER_NAMES`
+pub fn get_encoder_log_parsers(arg: Option<&str>) -> String {
+  get_env_value!("ENCODER_LOG_PARSERS", arg, "adobe_media_encoder")
+}
+
+/// JSON file listing the watched logs, see `log_source::LogSource`. When it
+/// is empty, one log is watched per parser of `get_encoder_log_parsers`.
+pub fn get_watched_logs_filename(arg: Option<&str>) -> String {
+  get_env_value!("WATCHED_LOGS_FILENAME", arg, "")
+}
+
+pub fn get_ffmpeg_log_filename(arg: Option<&str>) -> String {
+  get_env_value!("FFMPEG_LOG_FILENAME", arg, "ffmpeg.log")
+}
+
+pub fn get_ffmpeg_progress_filename(arg: Option<&str>) -> String {
+  get_env_value!("FFMPEG_PROGRESS_FILENAME", arg, "ffmpeg_progress.log")
+}
+
+pub fn get_handbrake_log_filename(arg: Option<&str>) -> String {
+  get_env_value!("HANDBRAKE_LOG_FILENAME", arg, "activity_log.txt")
+}
+
+pub fn get_resolve_log_filename(arg: Option<&str>) -> String {
+  get_env_value!("RESOLVE_LOG_FILENAME", arg, "rendlog.txt")
+}
+
+/// Log filename of the parser named `parser`
+pub fn get_encoder_log_filename(parser: &str, arg: Option<&str>) -> String {
+  match parser {
+    "ffmpeg" => get_ffmpeg_log_filename(arg),
+    "ffmpeg_progress" => get_ffmpeg_progress_filename(arg),
+    "handbrake" => get_handbrake_log_filename(arg),
+    "davinci_resolve" => get_resolve_log_filename(arg),
+    _ => get_adobe_media_encoder_log_filename(arg),
+  }
+}
+
+pub fn get_reported_entries_filename(arg: Option<&str>) -> String {
+  get_env_value!("REPORTED_ENTRIES_FILENAME", arg, "reported_entries.json")
+}
+
+/// Timezone of the encoder logs: `local`, `utc` or an offset like `+02:00`
+pub fn get_log_timezone(arg: Option<&str>) -> String {
+  get_env_value!("LOG_TIMEZONE", arg, "local")
+}
+
+/// Check the outputs on disk before announcing the successful encodes
+pub fn get_verify_outputs(arg: Option<&str>) -> String {
+  get_env_value!("VERIFY_OUTPUTS", arg, "true")
+}
+
+/// Milliseconds during which the size of an output must not change
+pub fn get_verification_delay(arg: Option<&str>) -> String {
+  get_env_value!("VERIFICATION_DELAY", arg, "2000")
+}
+
+/// Seconds after which an output still missing or being written fails its
+/// verification
+pub fn get_verification_timeout(arg: Option<&str>) -> String {
+  get_env_value!("VERIFICATION_TIMEOUT", arg, "300")
+}
+
+/// An encode is reported as slow when it takes this many times the usual
+/// encoding time of its preset
+pub fn get_encode_slow_factor(arg: Option<&str>) -> String {
+  get_env_value!("ENCODE_SLOW_FACTOR", arg, "3")
+}
+
+/// Number of encodes of a preset needed to know its usual encoding time
+pub fn get_encode_slow_min_samples(arg: Option<&str>) -> String {
+  get_env_value!("ENCODE_SLOW_MIN_SAMPLES", arg, "5")
+}
+
+/// Seconds without progress after which an encoding queue is stalled
+pub fn get_queue_stalled_timeout(arg: Option<&str>) -> String {
+  get_env_value!("QUEUE_STALLED_TIMEOUT", arg, "7200")
+}
+
+/// Follow the logs with filesystem notifications, the logs for which they
+/// are not available are polled
+pub fn get_log_notifications(arg: Option<&str>) -> String {
+  get_env_value!("LOG_NOTIFICATIONS", arg, "true")
+}
+
+/// Seconds between two reads of a log, the notified logs are also read at
+/// this interval in case a notification is lost. At least 1.
+pub fn get_log_polling_interval(arg: Option<&str>) -> String {
+  get_env_value!("LOG_POLLING_INTERVAL", arg, "10")
+}
+
+/// Seconds before reading again a log which could not be read, at least 1
+pub fn get_log_read_error_delay(arg: Option<&str>) -> String {
+  get_env_value!("LOG_READ_ERROR_DELAY", arg, "30")
+}
+
+/// Milliseconds without change after which a notified log is read, at
+/// least 50
+pub fn get_log_debounce_delay(arg: Option<&str>) -> String {
+  get_env_value!("LOG_DEBOUNCE_DELAY", arg, "500")
+}
@@ -0,0 +1,261 @@
+use std::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use std::mem;
+use std::str;
+
+/// Number of bytes inspected to guess the encoding of a file without BOM
+const DETECTION_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+  Utf8,
+  Utf16Le,
+  Utf16Be,
+}
+
+/// Streaming text decoder, the encoding is detected on the first bytes.
+/// Invalid sequences are replaced by U+FFFD instead of failing, and an
+/// incomplete sequence at the end of a chunk is kept for the next one.
+#[derive(Debug, Default)]
+pub struct Decoder {
+  pub encoding: Option<Encoding>,
+  pending: Vec<u8>,
+  /// Number of invalid sequences replaced since the creation of the decoder
+  pub replacements: usize,
+}
+
+impl Decoder {
+  pub fn new() -> Self {
+    Decoder::default()
+  }
+
+  pub fn decode(&mut self, bytes: &[u8]) -> String {
+    let mut data = vec![];
+    data.append(&mut self.pending);
+    data.extend_from_slice(bytes);
+
+    let encoding = match self.encoding {
+      Some(encoding) => encoding,
+      None => match detect_encoding(&data) {
+        Some((encoding, bom_length)) => {
+          debug!("detected {:?} encoding", encoding);
+          data.drain(..bom_length);
+          self.encoding = Some(encoding);
+          encoding
+        }
+        None => {
+          self.pending = data;
+          return String::new();
+        }
+      },
+    };
+
+    match encoding {
+      Encoding::Utf8 => self.decode_utf8(data),
+      Encoding::Utf16Le => self.decode_utf16(data, u16::from_le_bytes),
+      Encoding::Utf16Be => self.decode_utf16(data, u16::from_be_bytes),
+    }
+  }
+
+  /// Flush the bytes kept for the next chunk once the input is complete, an
+  /// incomplete sequence is replaced by U+FFFD
+  pub fn finish(&mut self) -> String {
+    let mut text = match self.encoding {
+      // too short for the encoding to be detected
+      None => {
+        self.encoding = Some(Encoding::Utf8);
+        let pending = mem::take(&mut self.pending);
+        self.decode_utf8(pending)
+      }
+      Some(_) => String::new(),
+    };
+
+    // only the start of the last character can be left, the start of a
+    // UTF-8 sequence or of a UTF-16 code unit or surrogate pair
+    if !self.pending.is_empty() {
+      self.pending.clear();
+      text.push(REPLACEMENT_CHARACTER);
+      self.replacements += 1;
+    }
+    text
+  }
+
+  fn decode_utf8(&mut self, data: Vec<u8>) -> String {
+    let mut text = String::new();
+    let mut remaining = &data[..];
+
+    loop {
+      match str::from_utf8(remaining) {
+        Ok(valid) => {
+          text.push_str(valid);
+          break;
+        }
+        Err(error) => {
+          let (valid, invalid) = remaining.split_at(error.valid_up_to());
+          text.push_str(str::from_utf8(valid).unwrap());
+
+          match error.error_len() {
+            Some(length) => {
+              text.push(REPLACEMENT_CHARACTER);
+              self.replacements += 1;
+              remaining = &invalid[length..];
+            }
+            None => {
+              self.pending = invalid.to_vec();
+              break;
+            }
+          }
+        }
+      }
+    }
+    text
+  }
+
+  fn decode_utf16(&mut self, mut data: Vec<u8>, to_unit: fn([u8; 2]) -> u16) -> String {
+    if data.len() % 2 == 1 {
+      self.pending.push(data.pop().unwrap());
+    }
+
+    let mut units: Vec<u16> = data
+      .chunks(2)
+      .map(|pair| to_unit([pair[0], pair[1]]))
+      .collect();
+
+    if let Some(&last) = units.last() {
+      if (0xD800..=0xDBFF).contains(&last) {
+        units.pop();
+        let length = data.len();
+        let mut surrogate = data[length - 2..].to_vec();
+        surrogate.append(&mut self.pending);
+        self.pending = surrogate;
+      }
+    }
+
+    let mut replacements = 0;
+    let text = decode_utf16(units)
+      .map(|c| {
+        c.unwrap_or_else(|_| {
+          replacements += 1;
+          REPLACEMENT_CHARACTER
+        })
+      })
+      .collect();
+    self.replacements += replacements;
+    text
+  }
+}
+
+/// Return the encoding and the length of its BOM, or `None` when more bytes are needed
+fn detect_encoding(data: &[u8]) -> Option<(Encoding, usize)> {
+  if data.starts_with(&[0xEF, 0xBB, 0xBF]) {
+    return Some((Encoding::Utf8, 3));
+  }
+  if data.starts_with(&[0xFF, 0xFE]) {
+    return Some((Encoding::Utf16Le, 2));
+  }
+  if data.starts_with(&[0xFE, 0xFF]) {
+    return Some((Encoding::Utf16Be, 2));
+  }
+  if data.len() < 3 && [0xEF, 0xBB, 0xBF].starts_with(data) {
+    return None;
+  }
+  if data.len() < 2 {
+    return None;
+  }
+
+  // ASCII text encoded in UTF-16 has a zero byte in each code unit
+  let sample = &data[..data.len().min(DETECTION_SIZE) / 2 * 2];
+  let even_zeros = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+  let odd_zeros = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+  let units = sample.len() / 2;
+
+  if odd_zeros * 2 > units && odd_zeros > even_zeros {
+    Some((Encoding::Utf16Le, 0))
+  } else if even_zeros * 2 > units && even_zeros > odd_zeros {
+    Some((Encoding::Utf16Be, 0))
+  } else {
+    Some((Encoding::Utf8, 0))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn utf16le(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(|unit| vec![unit as u8, (unit >> 8) as u8]).collect()
+  }
+
+  #[test]
+  fn utf8_with_bom() {
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.decode(b"\xEF\xBB\xBFcaf\xC3\xA9\n"), "café\n");
+    assert_eq!(decoder.encoding, Some(Encoding::Utf8));
+  }
+
+  #[test]
+  fn utf8_split_sequence() {
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.decode(b"caf\xC3"), "caf");
+    assert_eq!(decoder.decode(b"\xA9!"), "é!");
+    assert_eq!(decoder.replacements, 0);
+  }
+
+  #[test]
+  fn utf8_invalid_sequence() {
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.decode(b"a\xFFb"), "a\u{FFFD}b");
+    assert_eq!(decoder.replacements, 1);
+  }
+
+  #[test]
+  fn truncated_last_character() {
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.decode(b"caf\xC3"), "caf");
+    assert_eq!(decoder.finish(), "\u{FFFD}");
+    assert_eq!(decoder.replacements, 1);
+    assert_eq!(decoder.finish(), "");
+
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend(utf16le("clip 𝄞"));
+    for missing in 1..4 {
+      let mut decoder = Decoder::new();
+      let text = decoder.decode(&bytes[..bytes.len() - missing]) + &decoder.finish();
+      assert_eq!(text, "clip \u{FFFD}", "{} bytes missing", missing);
+      assert_eq!(decoder.replacements, 1);
+    }
+
+    // too short for the encoding to be detected
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.decode(b"a") + &decoder.finish(), "a");
+    assert_eq!(decoder.replacements, 0);
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.decode(b"\xEF\xBB") + &decoder.finish(), "\u{FFFD}");
+  }
+
+  #[test]
+  fn utf16_with_bom_split_anywhere() {
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend(utf16le("Queue Started é 𝄞\r\n"));
+
+    // every split of the bytes, BOM, code units and surrogate pairs included
+    for split in 1..bytes.len() {
+      let mut decoder = Decoder::new();
+      let text = decoder.decode(&bytes[..split]) + &decoder.decode(&bytes[split..]);
+      assert_eq!(text, "Queue Started é 𝄞\r\n", "split at {}", split);
+      assert_eq!(decoder.encoding, Some(Encoding::Utf16Le));
+      assert_eq!(decoder.replacements, 0);
+    }
+  }
+
+  #[test]
+  fn utf16_without_bom() {
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.decode(&utf16le("Log File Created")), "Log File Created");
+    assert_eq!(decoder.encoding, Some(Encoding::Utf16Le));
+
+    let bytes: Vec<u8> = "Log File Created".encode_utf16().flat_map(|unit| vec![(unit >> 8) as u8, unit as u8]).collect();
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.decode(&bytes), "Log File Created");
+    assert_eq!(decoder.encoding, Some(Encoding::Utf16Be));
+  }
+}
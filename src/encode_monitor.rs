@@ -0,0 +1,381 @@
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use encoder_log::{Entry, Status};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use timezone::format_date_time;
+
+/// Number of encodes of a preset kept to compute its expected encoding time
+const HISTORY_SIZE: usize = 50;
+
+/// The entries older than the last observed one by more than this are
+/// forgotten, like the reported entries
+const OBSERVED_MARGIN_HOURS: i64 = 24;
+
+/// Thresholds of the alerts, see `config::get_encode_slow_factor`
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+  /// An encode is slow when it takes this many times its expected time
+  pub slow_factor: f64,
+  /// Encodes of a preset needed before its encodes are judged
+  pub min_samples: usize,
+  /// Time without progress after which an encoding queue is stalled
+  pub stalled_timeout: Duration,
+}
+
+/// Encoding times of the last successful encodes with a preset
+#[derive(Debug, Default)]
+struct PresetHistory {
+  encoding_times: VecDeque<u64>,
+  /// Seconds of encoding per second of media
+  speeds: VecDeque<f64>,
+}
+
+impl PresetHistory {
+  fn push(&mut self, entry: &Entry, encoding_time: u64) {
+    push_bounded(&mut self.encoding_times, encoding_time);
+    if let Some(duration) = media_duration(entry) {
+      push_bounded(&mut self.speeds, encoding_time as f64 / duration);
+    }
+  }
+
+  fn median_encoding_time(&self) -> Option<f64> {
+    median(self.encoding_times.iter().map(|&time| time as f64))
+  }
+
+  /// Expected encoding time of an entry, from its media duration when the
+  /// history has enough encodes giving theirs.
+  fn expected(&self, entry: &Entry, min_samples: usize) -> Option<f64> {
+    if let Some(duration) = media_duration(entry) {
+      if self.speeds.len() >= min_samples {
+        return median(self.speeds.iter().cloned()).map(|speed| speed * duration);
+      }
+    }
+    if self.encoding_times.len() >= min_samples {
+      return self.median_encoding_time();
+    }
+    None
+  }
+}
+
+/// Encode which took much longer than the previous ones with its preset
+#[derive(Debug)]
+pub struct SlowEncode {
+  pub id: String,
+  pub encoder: &'static str,
+  pub preset: Option<String>,
+  pub output_filename: Option<String>,
+  pub encoding_time: u64,
+  pub expected_encoding_time: f64,
+}
+
+impl From<SlowEncode> for Value {
+  fn from(slow: SlowEncode) -> Self {
+    let mut m = Map::new();
+    m.insert("id".to_owned(), slow.id.into());
+    m.insert("encoder".to_owned(), slow.encoder.into());
+    if let Some(preset) = slow.preset {
+      m.insert("preset".to_owned(), preset.into());
+    }
+    if let Some(output_filename) = slow.output_filename {
+      m.insert("output_filename".to_owned(), output_filename.into());
+    }
+    m.insert("encoding_time".to_owned(), slow.encoding_time.into());
+    let expected = (slow.expected_encoding_time * 10.0).round() / 10.0;
+    m.insert("expected_encoding_time".to_owned(), expected.into());
+    m.into()
+  }
+}
+
+/// Encoding queue without progress for longer than its threshold
+#[derive(Debug)]
+pub struct QueueStalled {
+  pub filename: String,
+  pub encoder: &'static str,
+  pub since: DateTime<FixedOffset>,
+  /// Time without progress, in seconds
+  pub stalled_for: i64,
+  /// Threshold crossed, in seconds
+  pub threshold: i64,
+}
+
+impl From<QueueStalled> for Value {
+  fn from(stalled: QueueStalled) -> Self {
+    let mut m = Map::new();
+    m.insert("filename".to_owned(), stalled.filename.into());
+    m.insert("encoder".to_owned(), stalled.encoder.into());
+    m.insert("since".to_owned(), format_date_time(&stalled.since).into());
+    m.insert("stalled_for".to_owned(), stalled.stalled_for.into());
+    m.insert("threshold".to_owned(), stalled.threshold.into());
+    m.into()
+  }
+}
+
+/// Model of the expected encoding time per preset, learnt from the entries
+/// of the watched logs, raising alerts for slow encodes and stalled queues.
+#[derive(Debug)]
+pub struct EncodeMonitor {
+  thresholds: Thresholds,
+  presets: HashMap<(&'static str, Option<String>), PresetHistory>,
+  /// Date of the entries observed, see `prune`
+  observed: HashMap<String, DateTime<FixedOffset>>,
+  /// The entries older than this are forgotten, they are not observed again
+  horizon: Option<DateTime<FixedOffset>>,
+  /// Last activity of each log already reported as stalled
+  stalled: HashMap<String, DateTime<FixedOffset>>,
+}
+
+impl EncodeMonitor {
+  pub fn new(thresholds: Thresholds) -> Self {
+    EncodeMonitor {
+      thresholds,
+      presets: HashMap::new(),
+      observed: HashMap::new(),
+      horizon: None,
+      stalled: HashMap::new(),
+    }
+  }
+
+  /// Compare the entry with the previous encodes of its preset, then add it
+  /// to the history. An entry already observed is ignored, so a log read
+  /// again does not count its encodes twice.
+  pub fn observe(&mut self, entry: &Entry) -> Option<SlowEncode> {
+    let forgotten = self.horizon.map(|horizon| entry.date_time < horizon).unwrap_or(false);
+    if forgotten || self.observed.contains_key(&entry.id) {
+      return None;
+    }
+    self.observed.insert(entry.id.clone(), entry.date_time);
+    if entry.status != Status::Success && entry.status != Status::Warning {
+      return None;
+    }
+    let encoding_time = entry.encoding_time?;
+
+    let history = self
+      .presets
+      .entry((entry.encoder, entry.preset.clone()))
+      .or_default();
+    let expected = history.expected(entry, self.thresholds.min_samples);
+    history.push(entry, encoding_time);
+
+    match expected {
+      Some(expected) if expected > 0.0 && encoding_time as f64 > expected * self.thresholds.slow_factor => {
+        Some(SlowEncode {
+          id: entry.id.clone(),
+          encoder: entry.encoder,
+          preset: entry.preset.clone(),
+          output_filename: entry.output_filename.clone(),
+          encoding_time,
+          expected_encoding_time: expected,
+        })
+      }
+      _ => None,
+    }
+  }
+
+  /// Forget the entries observed more than the margin before the last one,
+  /// called once the logs are read so that their backlogs are all observed
+  pub fn prune(&mut self) {
+    let latest = match self.observed.values().max() {
+      Some(latest) => *latest,
+      None => return,
+    };
+    let horizon = latest - Duration::hours(OBSERVED_MARGIN_HOURS);
+    self.observed.retain(|_, date_time| *date_time >= horizon);
+    self.horizon = Some(horizon);
+  }
+
+  /// Time without progress after which a queue is stalled: the configured
+  /// timeout, or longer when the slowest preset usually takes more.
+  pub fn stalled_threshold(&self) -> Duration {
+    let longest = self
+      .presets
+      .values()
+      .filter(|history| history.encoding_times.len() >= self.thresholds.min_samples)
+      .filter_map(|history| history.median_encoding_time())
+      .fold(0.0, f64::max);
+    let expected = Duration::seconds((longest * self.thresholds.slow_factor) as i64);
+    self.thresholds.stalled_timeout.max(expected)
+  }
+
+  /// Check the last activity of the queue of a log, a stall is only
+  /// reported once until the queue progresses again.
+  pub fn check_queue(
+    &mut self,
+    filename: &str,
+    encoder: &'static str,
+    activity: Option<DateTime<FixedOffset>>,
+  ) -> Option<QueueStalled> {
+    let since = match activity {
+      Some(since) => since,
+      None => {
+        self.stalled.remove(filename);
+        return None;
+      }
+    };
+    if self.stalled.get(filename) == Some(&since) {
+      return None;
+    }
+
+    let stalled_for = Utc::now().signed_duration_since(since);
+    let threshold = self.stalled_threshold();
+    if stalled_for <= threshold {
+      return None;
+    }
+    self.stalled.insert(filename.to_owned(), since);
+    Some(QueueStalled {
+      filename: filename.to_owned(),
+      encoder,
+      since,
+      stalled_for: stalled_for.num_seconds(),
+      threshold: threshold.num_seconds(),
+    })
+  }
+}
+
+fn media_duration(entry: &Entry) -> Option<f64> {
+  entry
+    .video
+    .as_ref()
+    .and_then(|video| video.duration)
+    .filter(|&duration| duration > 0.0)
+}
+
+fn push_bounded<T>(values: &mut VecDeque<T>, value: T) {
+  if values.len() == HISTORY_SIZE {
+    values.pop_front();
+  }
+  values.push_back(value);
+}
+
+fn median<I: Iterator<Item = f64>>(values: I) -> Option<f64> {
+  let mut sorted: Vec<f64> = values.collect();
+  if sorted.is_empty() {
+    return None;
+  }
+  sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+  // the middle value, or the mean of the two middle values
+  let length = sorted.len();
+  Some((sorted[(length - 1) / 2] + sorted[length / 2]) / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use encoder_log::Video;
+  use timezone::LogTimezone;
+
+  fn thresholds() -> Thresholds {
+    Thresholds {
+      slow_factor: 3.0,
+      min_samples: 3,
+      stalled_timeout: Duration::zero(),
+    }
+  }
+
+  fn entry(id: &str, hour: u32, encoding_time: u64) -> Entry {
+    let date_time = DateTime::parse_from_rfc3339(&format!("2018-06-12T{:02}:00:00+01:00", hour)).unwrap();
+    let mut entry = Entry::new("adobe_media_encoder", date_time, Status::Success);
+    entry.id = id.to_owned();
+    entry.preset = Some("H.264 1080p 25".to_owned());
+    entry.encoding_time = Some(encoding_time);
+    entry
+  }
+
+  fn with_duration(mut entry: Entry, duration: f64) -> Entry {
+    entry.video = Some(Video {
+      width: 1920,
+      height: 1080,
+      pixel_aspect_ratio: None,
+      frame_rate: None,
+      scan_type: None,
+      timecode: None,
+      duration: Some(duration),
+    });
+    entry
+  }
+
+  #[test]
+  fn slow_encodes() {
+    let mut monitor = EncodeMonitor::new(thresholds());
+    // not judged before the minimum of samples
+    assert!(monitor.observe(&entry("a", 1, 100)).is_none());
+    assert!(monitor.observe(&entry("b", 2, 120)).is_none());
+    assert!(monitor.observe(&entry("c", 3, 1000)).is_none());
+
+    // the median of 100, 120 and 1000 is 120
+    assert!(monitor.observe(&entry("d", 4, 360)).is_none());
+    // the median of 100, 120, 360 and 1000 is 240
+    let slow = monitor.observe(&entry("e", 5, 800)).unwrap();
+    assert_eq!(slow.id, "e");
+    assert_eq!(slow.expected_encoding_time, 240.0);
+
+    // an entry observed again is not counted twice
+    assert!(monitor.observe(&entry("e", 5, 800)).is_none());
+    let mut failed = entry("f", 6, 5000);
+    failed.status = Status::Failed;
+    assert!(monitor.observe(&failed).is_none());
+  }
+
+  #[test]
+  fn slow_encodes_of_their_media_duration() {
+    let mut monitor = EncodeMonitor::new(thresholds());
+    for (index, &id) in ["a", "b", "c"].iter().enumerate() {
+      assert!(monitor.observe(&with_duration(entry(id, index as u32, 60), 120.0)).is_none());
+    }
+    // a long media is expected to take longer
+    assert!(monitor.observe(&with_duration(entry("long", 4, 600), 1200.0)).is_none());
+    let slow = monitor.observe(&with_duration(entry("slow", 5, 200), 120.0)).unwrap();
+    assert_eq!(slow.expected_encoding_time, 60.0);
+  }
+
+  #[test]
+  fn minimum_of_samples() {
+    let mut monitor = EncodeMonitor::new(Thresholds {
+      min_samples: 5,
+      ..thresholds()
+    });
+    for (index, &id) in ["a", "b", "c", "d"].iter().enumerate() {
+      assert!(monitor.observe(&entry(id, index as u32, 10)).is_none());
+    }
+    assert!(monitor.observe(&entry("e", 5, 1000)).is_none());
+    assert!(monitor.observe(&entry("f", 6, 1000)).is_some());
+  }
+
+  #[test]
+  fn stall_reported_once() {
+    let mut monitor = EncodeMonitor::new(Thresholds {
+      stalled_timeout: Duration::hours(1),
+      ..thresholds()
+    });
+    let now = LogTimezone::Utc.now();
+    assert!(monitor.check_queue("AMEEncodingLog.txt", "adobe_media_encoder", Some(now)).is_none());
+
+    let since = now - Duration::hours(2);
+    let stalled = monitor.check_queue("AMEEncodingLog.txt", "adobe_media_encoder", Some(since)).unwrap();
+    assert_eq!(stalled.threshold, 3600);
+    assert!(stalled.stalled_for >= 7200);
+    assert!(monitor.check_queue("AMEEncodingLog.txt", "adobe_media_encoder", Some(since)).is_none());
+
+    // reported again once the queue has progressed then stalled again
+    assert!(monitor.check_queue("AMEEncodingLog.txt", "adobe_media_encoder", None).is_none());
+    assert!(monitor.check_queue("AMEEncodingLog.txt", "adobe_media_encoder", Some(since)).is_some());
+    let later = since + Duration::minutes(10);
+    assert!(monitor.check_queue("AMEEncodingLog.txt", "adobe_media_encoder", Some(later)).is_some());
+  }
+
+  #[test]
+  fn observed_entries_pruned() {
+    let mut monitor = EncodeMonitor::new(thresholds());
+    let mut old = entry("old", 1, 100);
+    old.date_time = DateTime::parse_from_rfc3339("2018-06-10T01:00:00+01:00").unwrap();
+    monitor.observe(&old);
+    monitor.observe(&entry("recent", 1, 100));
+    monitor.prune();
+    assert_eq!(monitor.observed.len(), 1);
+    assert!(monitor.observed.contains_key("recent"));
+
+    // an old entry read again is still not counted twice
+    monitor.observe(&old);
+    assert!(!monitor.observed.contains_key("old"));
+    assert_eq!(monitor.presets.values().map(|history| history.encoding_times.len()).sum::<usize>(), 2);
+  }
+}
@@ -0,0 +1,224 @@
+use chrono::NaiveDate;
+use encoder_log::{Entry, Status};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Encodes done with a preset on a day, in the timezone of the log
+#[derive(Debug, Clone)]
+pub struct EncodeStats {
+  pub preset: Option<String>,
+  pub day: NaiveDate,
+  pub encodes: u32,
+  pub warnings: u32,
+  pub failures: u32,
+  pub interrupted: u32,
+  /// Duration of the encoded media, in seconds
+  pub media_duration: f64,
+  /// Time spent encoding, in seconds
+  pub encoding_time: u64,
+  /// Durations of the encodes which give both, used for the realtime factor
+  timed_media_duration: f64,
+  timed_encoding_time: u64,
+}
+
+impl EncodeStats {
+  fn new(preset: Option<String>, day: NaiveDate) -> Self {
+    EncodeStats {
+      preset,
+      day,
+      encodes: 0,
+      warnings: 0,
+      failures: 0,
+      interrupted: 0,
+      media_duration: 0.0,
+      encoding_time: 0,
+      timed_media_duration: 0.0,
+      timed_encoding_time: 0,
+    }
+  }
+
+  fn add(&mut self, entry: &Entry) {
+    self.encodes += 1;
+    match entry.status {
+      Status::Warning => self.warnings += 1,
+      Status::Failed => self.failures += 1,
+      Status::Interrupted => self.interrupted += 1,
+      Status::Success => {}
+    }
+
+    let media_duration = entry.video.as_ref().and_then(|video| video.duration);
+    if let Some(media_duration) = media_duration {
+      self.media_duration += media_duration;
+    }
+    if let Some(encoding_time) = entry.encoding_time {
+      self.encoding_time += encoding_time;
+    }
+    if let (Some(media_duration), Some(encoding_time)) = (media_duration, entry.encoding_time) {
+      self.timed_media_duration += media_duration;
+      self.timed_encoding_time += encoding_time;
+    }
+  }
+
+  /// Seconds of media encoded per second, above 1 the encodes are faster
+  /// than realtime
+  pub fn realtime_factor(&self) -> Option<f64> {
+    if self.timed_encoding_time == 0 {
+      return None;
+    }
+    Some(round(self.timed_media_duration / self.timed_encoding_time as f64))
+  }
+
+  pub fn failure_rate(&self) -> f64 {
+    round(f64::from(self.failures) / f64::from(self.encodes))
+  }
+
+  pub fn warning_rate(&self) -> f64 {
+    round(f64::from(self.warnings) / f64::from(self.encodes))
+  }
+}
+
+impl From<EncodeStats> for Value {
+  fn from(stats: EncodeStats) -> Self {
+    let mut m = Map::new();
+    if let Some(realtime_factor) = stats.realtime_factor() {
+      m.insert("realtime_factor".to_owned(), realtime_factor.into());
+    }
+    m.insert("failure_rate".to_owned(), stats.failure_rate().into());
+    m.insert("warning_rate".to_owned(), stats.warning_rate().into());
+    if let Some(preset) = stats.preset {
+      m.insert("preset".to_owned(), preset.into());
+    }
+    m.insert("day".to_owned(), stats.day.format("%Y-%m-%d").to_string().into());
+    m.insert("encodes".to_owned(), stats.encodes.into());
+    m.insert("warnings".to_owned(), stats.warnings.into());
+    m.insert("failures".to_owned(), stats.failures.into());
+    m.insert("interrupted".to_owned(), stats.interrupted.into());
+    m.insert("media_duration".to_owned(), round(stats.media_duration).into());
+    m.insert("encoding_time".to_owned(), stats.encoding_time.into());
+    m.into()
+  }
+}
+
+/// Group the entries per preset and per day, sorted by preset then day. The
+/// entries without a date are left out, their day is not known.
+pub fn aggregate<'a, I>(entries: I) -> Vec<EncodeStats>
+where
+  I: IntoIterator<Item = &'a Entry>,
+{
+  let mut groups: BTreeMap<(Option<String>, NaiveDate), EncodeStats> = BTreeMap::new();
+  for entry in entries.into_iter().filter(|entry| !entry.date_estimated) {
+    let day = entry.date_time.naive_local().date();
+    groups
+      .entry((entry.preset.clone(), day))
+      .or_insert_with(|| EncodeStats::new(entry.preset.clone(), day))
+      .add(entry);
+  }
+  groups.values().cloned().collect()
+}
+
+fn round(value: f64) -> f64 {
+  (value * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use encoder_log::{parse_file, EncoderLogParser, ParseMode};
+  use timezone::LogTimezone;
+
+  const SAMPLE_LOG: &str = "tests/AMEEncodingLog.txt";
+  const STREAMER: &str = "TandI_MP4Streamer_720p25_10Mbps_-15LUFS";
+  const MDN: &str = "MDN2018_mp4encodingprofle_720p25_5_Max10Mbps";
+
+  fn parse_sample() -> Box<dyn EncoderLogParser> {
+    parse_file("adobe_media_encoder", SAMPLE_LOG, ParseMode::Lenient, "auto", LogTimezone::Utc).unwrap()
+  }
+
+  fn find<'a>(stats: &'a [EncodeStats], preset: &str, day: &str) -> &'a EncodeStats {
+    let day = NaiveDate::parse_from_str(day, "%Y-%m-%d").unwrap();
+    stats
+      .iter()
+      .find(|stats| stats.preset.as_deref() == Some(preset) && stats.day == day)
+      .unwrap()
+  }
+
+  #[test]
+  fn grouped_per_preset_and_day() {
+    let parser = parse_sample();
+    let stats = aggregate(parser.entries());
+    assert_eq!(stats.len(), 14);
+    assert_eq!(stats.iter().map(|stats| stats.encodes).sum::<u32>(), 143);
+    let keys: Vec<_> = stats.iter().map(|stats| (stats.preset.clone(), stats.day)).collect();
+    let mut sorted = keys.clone();
+    sorted.sort();
+    assert_eq!(keys, sorted);
+
+    let days: Vec<String> =
+      stats
+      .iter()
+      .filter(|stats| stats.preset.as_deref() == Some(STREAMER))
+      .map(|stats| stats.day.format("%Y-%m-%d").to_string())
+      .collect();
+    assert_eq!(days, vec!["2018-02-19", "2018-02-23", "2018-06-22", "2018-06-25", "2018-06-26"]);
+    let first = find(&stats, STREAMER, "2018-02-19");
+    assert_eq!(first.encodes, 18);
+    assert_eq!(first.encoding_time, 6970);
+
+    // an entry without a known date has no day
+    let mut undated = parser.entries()[0].clone();
+    undated.date_estimated = true;
+    let stats = aggregate(parser.entries().iter().chain(Some(&undated)));
+    assert_eq!(find(&stats, STREAMER, "2018-02-19").encodes, 18);
+  }
+
+  #[test]
+  fn realtime_factor() {
+    let parser = parse_sample();
+    let stats = aggregate(parser.entries());
+    let first = find(&stats, STREAMER, "2018-02-19");
+    assert_eq!(first.realtime_factor(), Some(2.324));
+    assert_eq!(find(&stats, STREAMER, "2018-06-25").realtime_factor(), Some(0.909));
+
+    // the media of an encode without its encoding time is not in the factor
+    let mut untimed = parser.entries()[0].clone();
+    untimed.encoding_time = None;
+    let stats = aggregate(parser.entries().iter().chain(Some(&untimed)));
+    let first = find(&stats, STREAMER, "2018-02-19");
+    assert_eq!(first.encodes, 19);
+    assert_eq!(round(first.media_duration), round(16195.4 + 1824.96));
+    assert_eq!(first.realtime_factor(), Some(2.324));
+
+    untimed.preset = Some("untimed".to_owned());
+    let stats = aggregate(Some(&untimed));
+    assert_eq!(stats[0].realtime_factor(), None);
+    assert!(Value::from(stats[0].clone()).get("realtime_factor").is_none());
+  }
+
+  #[test]
+  fn success_and_failure_rates() {
+    let parser = parse_sample();
+    let stats = aggregate(parser.entries());
+    let warnings = find(&stats, STREAMER, "2018-06-22");
+    assert_eq!((warnings.encodes, warnings.warnings), (22, 11));
+    assert_eq!(warnings.warning_rate(), 0.5);
+    assert_eq!(warnings.failure_rate(), 0.0);
+
+    let mut failed = parser
+      .entries()
+      .iter()
+      .find(|entry| entry.preset.as_deref() == Some(MDN) && entry.date_time.format("%d").to_string() == "12")
+      .unwrap()
+      .clone();
+    failed.status = Status::Failed;
+    let stats = aggregate(parser.entries().iter().chain(vec![&failed, &failed]));
+    let mdn = find(&stats, MDN, "2018-06-12");
+    assert_eq!((mdn.encodes, mdn.warnings, mdn.failures, mdn.interrupted), (14, 2, 2, 1));
+    assert_eq!(mdn.failure_rate(), 0.143);
+    assert_eq!(mdn.warning_rate(), 0.143);
+
+    let value: Value = mdn.clone().into();
+    assert_eq!(value["day"], "2018-06-12");
+    assert_eq!(value["failure_rate"], 0.143);
+    assert_eq!(value["interrupted"], 1);
+  }
+}
@@ -0,0 +1,528 @@
+use adobe_media_encoder_log::{AdobeMediaEncoderLog, DateFormat};
+use chrono::{DateTime, FixedOffset};
+use ffmpeg_log::{FfmpegLog, FfmpegProgressLog};
+use handbrake_log::HandBrakeLog;
+use log_reader::{LogReader, LogUpdate};
+use resolve_log::ResolveLog;
+use serde_json::{Map, Value};
+use std::fmt;
+use timezone::{format_date_time, LogTimezone};
+
+/// FNV-1a parameters, the identity of the entries has to be stable across
+/// releases since it is persisted
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Names of the available parsers, as used in the configuration
+pub const PARSER_NAMES: [&str; 5] = [
+  "adobe_media_encoder",
+  "ffmpeg",
+  "ffmpeg_progress",
+  "handbrake",
+  "davinci_resolve",
+];
+
+/// Incremental parser of an encoder log, fed with the text appended to the
+/// log file and producing normalised entries.
+pub trait EncoderLogParser {
+  /// Name of the parser, one of `PARSER_NAMES`
+  fn name(&self) -> &'static str;
+
+  /// Parse text appended to the log, returns the number of new entries.
+  /// In strict mode, the first error met is returned.
+  fn update(&mut self, text: &str) -> Result<usize, ParseError>;
+
+  fn entries(&self) -> &[Entry];
+
+  fn diagnostics(&self) -> &[ParseError];
+
+  /// State of the encoding queue and its payload, for encoders which log it
+  fn queue_status(&self) -> Option<(QueueState, Value)> {
+    None
+  }
+
+  /// Last progress of the queue while it is encoding, to detect a stalled
+  /// queue. `None` when it is paused, idle or not logged.
+  fn queue_activity(&self) -> Option<DateTime<FixedOffset>> {
+    None
+  }
+}
+
+/// Create the parser configured with `name`, the date format is only used
+/// by the parsers of logs with localized dates.
+pub fn new_parser(
+  name: &str,
+  mode: ParseMode,
+  date_format: &str,
+  timezone: LogTimezone,
+) -> Result<Box<dyn EncoderLogParser>, String> {
+  match name.trim() {
+    "adobe_media_encoder" => Ok(Box::new(AdobeMediaEncoderLog::new(
+      mode,
+      DateFormat::from(date_format),
+      timezone,
+    ))),
+    "ffmpeg" => Ok(Box::new(FfmpegLog::new(mode, timezone))),
+    "ffmpeg_progress" => Ok(Box::new(FfmpegProgressLog::new(mode, timezone))),
+    "handbrake" => Ok(Box::new(HandBrakeLog::new(mode, timezone))),
+    "davinci_resolve" => Ok(Box::new(ResolveLog::new(mode, timezone))),
+    name => Err(format!(
+      "unknown encoder log parser {:?}, expected one of {}",
+      name,
+      PARSER_NAMES.join(", ")
+    )),
+  }
+}
+
+/// Parse a whole log file at once, its last line included even without a
+/// line ending.
+pub fn parse_file(
+  name: &str,
+  filename: &str,
+  mode: ParseMode,
+  date_format: &str,
+  timezone: LogTimezone,
+) -> Result<Box<dyn EncoderLogParser>, String> {
+  let mut reader = LogReader::new(filename);
+  let mut text = match reader.read()? {
+    LogUpdate::Appended(text) | LogUpdate::Reset(text) => text,
+  };
+  text.push_str(&reader.finish());
+  if !text.ends_with('\n') {
+    text.push('\n');
+  }
+  let mut parser = new_parser(name, mode, date_format, timezone)?;
+  parser.update(&text).map_err(|e| e.to_string())?;
+  Ok(parser)
+}
+
+/// Split the text appended to a log into complete lines, the last line is
+/// kept until its line ending is written.
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+  partial_line: String,
+  line_number: usize,
+}
+
+impl LineBuffer {
+  pub fn new() -> Self {
+    LineBuffer::default()
+  }
+
+  /// Complete lines of the text, with their line number starting at 1
+  pub fn push(&mut self, text: &str) -> Vec<(usize, String)> {
+    self.partial_line.push_str(text);
+
+    let mut lines = vec![];
+    while let Some(position) = self.partial_line.find('\n') {
+      let line: String = self.partial_line.drain(..position + 1).collect();
+      self.line_number += 1;
+      lines.push((
+        self.line_number,
+        line.trim_end_matches(&['\r', '\n'][..]).to_string(),
+      ));
+    }
+    lines
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseMode {
+  /// Entries with an error are dropped and the error is returned
+  Strict,
+  /// Errors are collected in `diagnostics` and parsing goes on
+  Lenient,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ParseErrorKind {
+  InvalidDate,
+  InvalidVideo,
+  InvalidAudio,
+  InvalidBitrate,
+  InvalidEncodingTime,
+  /// Encode details without any outcome line
+  MissingOutcome,
+  UnexpectedLine,
+}
+
+impl ParseErrorKind {
+  pub fn as_str(&self) -> &'static str {
+    match *self {
+      ParseErrorKind::InvalidDate => "invalid_date",
+      ParseErrorKind::InvalidVideo => "invalid_video",
+      ParseErrorKind::InvalidAudio => "invalid_audio",
+      ParseErrorKind::InvalidBitrate => "invalid_bitrate",
+      ParseErrorKind::InvalidEncodingTime => "invalid_encoding_time",
+      ParseErrorKind::MissingOutcome => "missing_outcome",
+      ParseErrorKind::UnexpectedLine => "unexpected_line",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseError {
+  /// Line number in the log file, starting at 1
+  pub line: usize,
+  pub text: String,
+  pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+  pub fn new(line: usize, text: &str, kind: ParseErrorKind) -> Self {
+    ParseError {
+      line,
+      text: text.to_string(),
+      kind,
+    }
+  }
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "line {}: {} ({:?})", self.line, self.kind.as_str(), self.text)
+  }
+}
+
+impl<'a> From<&'a ParseError> for Value {
+  fn from(error: &'a ParseError) -> Self {
+    let mut m = Map::new();
+    m.insert("line".to_owned(), error.line.into());
+    m.insert("text".to_owned(), error.text.clone().into());
+    m.insert("kind".to_owned(), error.kind.as_str().into());
+    m.into()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum QueueState {
+  Encoding,
+  Paused,
+  Idle,
+}
+
+impl QueueState {
+  pub fn as_str(&self) -> &'static str {
+    match *self {
+      QueueState::Encoding => "encoding",
+      QueueState::Paused => "paused",
+      QueueState::Idle => "idle",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Status {
+  Success,
+  Warning,
+  Failed,
+  Interrupted,
+}
+
+impl Status {
+  /// Channel event used to report an entry with this status
+  pub fn event_name(&self) -> &'static str {
+    match *self {
+      Status::Success => "new_item",
+      Status::Warning => "new_item_with_warning",
+      Status::Failed => "encoding_failed",
+      Status::Interrupted => "encoding_interrupted",
+    }
+  }
+
+  fn as_str(&self) -> &'static str {
+    match *self {
+      Status::Success => "success",
+      Status::Warning => "warning",
+      Status::Failed => "failed",
+      Status::Interrupted => "interrupted",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+  /// Stable identity of the encode, see `Entry::identify`
+  pub id: String,
+  /// Name of the parser which read the entry, like `adobe_media_encoder`
+  pub encoder: &'static str,
+  /// End of the encode, with the offset of the timezone of the log
+  pub date_time: DateTime<FixedOffset>,
+  /// The encoder does not timestamp the encode, `date_time` is the time at
+  /// which its outcome was read
+  pub date_estimated: bool,
+  pub status: Status,
+  /// Warning or error lines written by the encoder about this encode
+  pub messages: Vec<String>,
+  pub source_filename: Option<String>,
+  pub source_kind: Option<SourceKind>,
+  pub output_filename: Option<String>,
+  pub preset: Option<String>,
+  pub video: Option<Video>,
+  pub audio: Option<Audio>,
+  pub bitrate: Option<Bitrate>,
+  /// Encoding time in seconds
+  pub encoding_time: Option<u64>,
+}
+
+impl Entry {
+  pub fn new(encoder: &'static str, date_time: DateTime<FixedOffset>, status: Status) -> Self {
+    Entry {
+      id: String::new(),
+      encoder,
+      date_time,
+      date_estimated: false,
+      status,
+      messages: vec![],
+      source_filename: None,
+      source_kind: None,
+      output_filename: None,
+      preset: None,
+      video: None,
+      audio: None,
+      bitrate: None,
+      encoding_time: None,
+    }
+  }
+
+  /// Date of the encode, `None` when it is only estimated
+  pub fn known_date_time(&self) -> Option<DateTime<FixedOffset>> {
+    if self.date_estimated {
+      None
+    } else {
+      Some(self.date_time)
+    }
+  }
+
+  /// Compute the identity of the entry from its position in the log and a
+  /// fingerprint of its outcome, which includes the timestamp written by
+  /// the encoder when there is one. Two encodes of the same output finishing
+  /// in the same second get different identities, and the identity does not
+  /// change when the log is parsed again.
+  pub fn identify(&mut self, line: usize, fingerprint: &str) {
+    let mut hash = FNV_OFFSET_BASIS;
+    let line = line.to_string();
+    let fields = [
+      self.encoder,
+      &line,
+      fingerprint,
+      self.output_filename.as_deref().unwrap_or(""),
+      self.preset.as_deref().unwrap_or(""),
+      self.source_filename.as_deref().unwrap_or(""),
+    ];
+    for field in fields.iter() {
+      for byte in field.bytes().chain(Some(0)) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+      }
+    }
+    self.id = format!("{:016x}", hash);
+  }
+
+  /// Set the source file and guess its kind from the extension
+  pub fn set_source_filename(&mut self, filename: &str) {
+    let source_filename = normalize_path(filename);
+    self.source_kind = Some(SourceKind::from_filename(&source_filename));
+    self.source_filename = Some(source_filename);
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum SourceKind {
+  PremiereProject,
+  AfterEffectsProject,
+  Media,
+}
+
+impl SourceKind {
+  pub fn from_filename(filename: &str) -> Self {
+    let extension = filename
+      .rsplit('/')
+      .next()
+      .and_then(|name| name.rsplit('.').next())
+      .unwrap_or("")
+      .to_lowercase();
+
+    match extension.as_str() {
+      "prproj" => SourceKind::PremiereProject,
+      "aep" | "aepx" => SourceKind::AfterEffectsProject,
+      _ => SourceKind::Media,
+    }
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match *self {
+      SourceKind::PremiereProject => "premiere_project",
+      SourceKind::AfterEffectsProject => "after_effects_project",
+      SourceKind::Media => "media",
+    }
+  }
+}
+
+/// Video properties of the output, AME writes them as
+/// ` - Video: 1280x720 (1.0), 25 fps, Progressive, 00:30:24:24`
+#[derive(Debug, Clone, Serialize)]
+pub struct Video {
+  pub width: u32,
+  pub height: u32,
+  pub pixel_aspect_ratio: Option<f64>,
+  pub frame_rate: Option<f64>,
+  /// `Progressive`, `Upper` or `Lower` (field first)
+  pub scan_type: Option<String>,
+  pub timecode: Option<String>,
+  /// Duration in seconds, computed from the timecode and the frame rate
+  pub duration: Option<f64>,
+}
+
+/// Audio properties of the output, AME writes them as
+/// ` - Audio: AAC, 160 kbps, 48 kHz, Mono`
+#[derive(Debug, Clone, Serialize)]
+pub struct Audio {
+  pub codec: Option<String>,
+  /// Bitrate in kbps
+  pub bitrate: Option<u32>,
+  /// Sample rate in Hz
+  pub sample_rate: Option<u32>,
+  pub channel_layout: Option<String>,
+  pub bit_depth: Option<u32>,
+}
+
+/// Bitrate settings of the output, AME writes them as
+/// ` - Bitrate: VBR, 2 pass, Target 5.00 Mbps, Max 10.00 Mbps`
+#[derive(Debug, Clone, Serialize)]
+pub struct Bitrate {
+  pub mode: Option<String>,
+  pub passes: Option<u32>,
+  /// Target bitrate in Mbps
+  pub target: Option<f64>,
+  /// Maximum bitrate in Mbps
+  pub max: Option<f64>,
+}
+
+impl From<Entry> for Value {
+  fn from(entry: Entry) -> Self {
+    let mut m = Map::new();
+    let date_time = format_date_time(&entry.date_time);
+    m.insert("id".to_owned(), entry.id.into());
+    m.insert("encoder".to_owned(), entry.encoder.into());
+    m.insert("date_time".to_owned(), date_time.into());
+    if entry.date_estimated {
+      m.insert("date_estimated".to_owned(), true.into());
+    }
+    m.insert("status".to_owned(), entry.status.as_str().into());
+    if !entry.messages.is_empty() {
+      m.insert("messages".to_owned(), entry.messages.into());
+    }
+    if let Some(source_filename) = entry.source_filename {
+      m.insert("source_filename".to_owned(), source_filename.into());
+    }
+    if let Some(source_kind) = entry.source_kind {
+      m.insert("source_kind".to_owned(), source_kind.as_str().into());
+    }
+    if let Some(output_filename) = entry.output_filename {
+      m.insert("output_filename".to_owned(), output_filename.into());
+    }
+    if let Some(preset) = entry.preset {
+      m.insert("preset".to_owned(), preset.into());
+    }
+    if let Some(video) = entry.video {
+      m.insert("video".to_owned(), video.into());
+    }
+    if let Some(audio) = entry.audio {
+      m.insert("audio".to_owned(), audio.into());
+    }
+    if let Some(bitrate) = entry.bitrate {
+      m.insert("bitrate".to_owned(), bitrate.into());
+    }
+    if let Some(encoding_time) = entry.encoding_time {
+      m.insert("encoding_time".to_owned(), encoding_time.into());
+    }
+    m.into()
+  }
+}
+
+impl From<Video> for Value {
+  fn from(video: Video) -> Self {
+    let mut m = Map::new();
+    m.insert("width".to_owned(), video.width.into());
+    m.insert("height".to_owned(), video.height.into());
+    if let Some(pixel_aspect_ratio) = video.pixel_aspect_ratio {
+      m.insert("pixel_aspect_ratio".to_owned(), pixel_aspect_ratio.into());
+    }
+    if let Some(frame_rate) = video.frame_rate {
+      m.insert("frame_rate".to_owned(), frame_rate.into());
+    }
+    if let Some(scan_type) = video.scan_type {
+      m.insert("scan_type".to_owned(), scan_type.into());
+    }
+    if let Some(timecode) = video.timecode {
+      m.insert("timecode".to_owned(), timecode.into());
+    }
+    if let Some(duration) = video.duration {
+      m.insert("duration".to_owned(), duration.into());
+    }
+    m.into()
+  }
+}
+
+impl From<Audio> for Value {
+  fn from(audio: Audio) -> Self {
+    let mut m = Map::new();
+    if let Some(codec) = audio.codec {
+      m.insert("codec".to_owned(), codec.into());
+    }
+    if let Some(bitrate) = audio.bitrate {
+      m.insert("bitrate".to_owned(), bitrate.into());
+    }
+    if let Some(sample_rate) = audio.sample_rate {
+      m.insert("sample_rate".to_owned(), sample_rate.into());
+    }
+    if let Some(channel_layout) = audio.channel_layout {
+      m.insert("channel_layout".to_owned(), channel_layout.into());
+    }
+    if let Some(bit_depth) = audio.bit_depth {
+      m.insert("bit_depth".to_owned(), bit_depth.into());
+    }
+    m.into()
+  }
+}
+
+impl From<Bitrate> for Value {
+  fn from(bitrate: Bitrate) -> Self {
+    let mut m = Map::new();
+    if let Some(mode) = bitrate.mode {
+      m.insert("mode".to_owned(), mode.into());
+    }
+    if let Some(passes) = bitrate.passes {
+      m.insert("passes".to_owned(), passes.into());
+    }
+    if let Some(target) = bitrate.target {
+      m.insert("target".to_owned(), target.into());
+    }
+    if let Some(max) = bitrate.max {
+      m.insert("max".to_owned(), max.into());
+    }
+    m.into()
+  }
+}
+
+/// Use `/` as separator and remove duplicated separators, except for the
+/// `//server/share` prefix of UNC paths
+pub fn normalize_path(path: &str) -> String {
+  let path = path.trim().replace("\\", "/");
+  let (prefix, rest) = match path.strip_prefix("//") {
+    Some(rest) => ("//", rest),
+    None => ("", path.as_str()),
+  };
+
+  let mut normalized = prefix.to_string();
+  let mut previous_separator = false;
+  for c in rest.chars() {
+    if c == '/' && previous_separator {
+      continue;
+    }
+    previous_separator = c == '/';
+    normalized.push(c);
+  }
+  normalized
+}
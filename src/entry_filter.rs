@@ -0,0 +1,132 @@
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate};
+use encoder_log::{normalize_path, Entry};
+use serde_json::Value;
+use timezone::LogTimezone;
+
+/// Selection of entries by date, preset and output, given on the command
+/// line or in the requests of the backend.
+#[derive(Debug)]
+pub struct EntryFilter {
+  pub from: Option<DateTime<FixedOffset>>,
+  pub to: Option<DateTime<FixedOffset>>,
+  /// Lowercase texts, one of them must be in the preset
+  pub presets: Vec<String>,
+  /// Lowercase patterns with `*` and `?`, one of them must match the output
+  pub outputs: Vec<String>,
+}
+
+impl EntryFilter {
+  /// Dates are `YYYY-MM-DD` for a whole day in the timezone of the log, or
+  /// RFC 3339, both bounds are included.
+  pub fn new(
+    from: Option<&str>,
+    to: Option<&str>,
+    presets: &[&str],
+    outputs: &[&str],
+    timezone: LogTimezone,
+  ) -> Result<Self, String> {
+    Ok(EntryFilter {
+      from: match from {
+        Some(from) => Some(parse_bound(from, timezone, false)?),
+        None => None,
+      },
+      to: match to {
+        Some(to) => Some(parse_bound(to, timezone, true)?),
+        None => None,
+      },
+      presets: presets.iter().map(|preset| preset.to_lowercase()).collect(),
+      outputs: outputs.iter().map(|output| normalize_path(output).to_lowercase()).collect(),
+    })
+  }
+
+  /// Read the `from`, `to`, `preset` and `output` fields of a request,
+  /// `preset` and `output` being a string or a list of strings.
+  pub fn from_payload(payload: &Value, timezone: LogTimezone) -> Result<Self, String> {
+    EntryFilter::new(
+      payload.get("from").and_then(|from| from.as_str()),
+      payload.get("to").and_then(|to| to.as_str()),
+      &strings(payload.get("preset")),
+      &strings(payload.get("output")),
+      timezone,
+    )
+  }
+
+  /// Presets match when they contain one of the filters, ignoring the case
+  pub fn matches(&self, entry: &Entry) -> bool {
+    match (self.from, self.to) {
+      (Some(from), _) if entry.date_time < from => return false,
+      (_, Some(to)) if entry.date_time > to => return false,
+      _ => {}
+    }
+    if !self.presets.is_empty() {
+      let preset = entry.preset.as_ref().map(|preset| preset.to_lowercase());
+      match preset {
+        Some(ref preset) if self.presets.iter().any(|filter| preset.contains(filter.as_str())) => {}
+        _ => return false,
+      }
+    }
+    if !self.outputs.is_empty() {
+      let output = entry.output_filename.as_ref().map(|output| output.to_lowercase());
+      match output {
+        Some(ref output) if self.outputs.iter().any(|pattern| matches_pattern(pattern, output)) => {}
+        _ => return false,
+      }
+    }
+    true
+  }
+}
+
+/// A string of a request, or its list of strings
+pub fn strings(value: Option<&Value>) -> Vec<&str> {
+  match value {
+    Some(Value::String(value)) => vec![value.as_str()],
+    Some(Value::Array(values)) => values.iter().filter_map(|value| value.as_str()).collect(),
+    _ => vec![],
+  }
+}
+
+/// Whether the whole text matches the pattern, where `*` is any text and
+/// `?` any character
+pub fn matches_pattern(pattern: &str, text: &str) -> bool {
+  let pattern: Vec<char> = pattern.chars().collect();
+  let text: Vec<char> = text.chars().collect();
+  let (mut p, mut t) = (0, 0);
+  // position of the last `*` and of the text it matched up to
+  let mut star: Option<(usize, usize)> = None;
+
+  while t < text.len() {
+    if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+      p += 1;
+      t += 1;
+    } else if p < pattern.len() && pattern[p] == '*' {
+      star = Some((p, t));
+      p += 1;
+    } else if let Some((star_p, star_t)) = star {
+      p = star_p + 1;
+      t = star_t + 1;
+      star = Some((star_p, star_t + 1));
+    } else {
+      return false;
+    }
+  }
+  pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn parse_bound(value: &str, timezone: LogTimezone, end: bool) -> Result<DateTime<FixedOffset>, String> {
+  if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+    return Ok(date_time);
+  }
+  let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+    .map_err(|_| format!("invalid date {:?}, expected YYYY-MM-DD or RFC 3339", value))?;
+  // the end of a day is just before the midnight of the next one
+  let day = if end { date.succ_opt() } else { Some(date) };
+  let midnight = day
+    .and_then(|day| day.and_hms_opt(0, 0, 0))
+    .ok_or_else(|| format!("invalid date {:?}", value))?;
+  let bound = timezone.localize(midnight);
+  if end {
+    Ok(bound - Duration::nanoseconds(1))
+  } else {
+    Ok(bound)
+  }
+}
@@ -0,0 +1,794 @@
+use chrono::{Duration, NaiveDateTime};
+use encoder_log::{
+  normalize_path, Audio, EncoderLogParser, Entry, LineBuffer, ParseError, ParseErrorKind,
+  ParseMode, Status, Video,
+};
+use timezone::LogTimezone;
+
+const ENCODER: &str = "ffmpeg";
+const PROGRESS_ENCODER: &str = "ffmpeg_progress";
+
+/// Header written with `-report`, like `ffmpeg started on 2018-02-16 at 22:11:56`
+const REPORT_HEADER: &str = "ffmpeg started on ";
+const REPORT_DATE_FORMAT: &str = "%Y-%m-%d at %H:%M:%S";
+const VERSION_HEADER: &str = "ffmpeg version ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+  Input,
+  Output,
+  Other,
+}
+
+/// Encode being read, from the header of the run to its outcome
+#[derive(Debug)]
+struct Job {
+  line: usize,
+  text: String,
+  started: Option<NaiveDateTime>,
+  with_version: bool,
+  section: Section,
+  source_filename: Option<String>,
+  output_filename: Option<String>,
+  video: Option<Video>,
+  audio: Option<Audio>,
+  /// Last `time=` of the progress lines, in seconds
+  time: Option<f64>,
+  /// Last `speed=` of the progress lines
+  speed: Option<f64>,
+  messages: Vec<String>,
+  errors: Vec<ParseError>,
+  interrupted: bool,
+}
+
+impl Job {
+  fn new(line: usize, text: &str) -> Self {
+    Job {
+      line,
+      text: text.to_string(),
+      started: None,
+      with_version: false,
+      section: Section::Other,
+      source_filename: None,
+      output_filename: None,
+      video: None,
+      audio: None,
+      time: None,
+      speed: None,
+      messages: vec![],
+      errors: vec![],
+      interrupted: false,
+    }
+  }
+}
+
+/// Parser of the messages written by FFmpeg on stderr, or in the file
+/// created with `-report`. One log can contain several runs.
+#[derive(Debug)]
+pub struct FfmpegLog {
+  pub mode: ParseMode,
+  pub entries: Vec<Entry>,
+  pub diagnostics: Vec<ParseError>,
+  timezone: LogTimezone,
+  lines: LineBuffer,
+  job: Option<Job>,
+}
+
+impl FfmpegLog {
+  pub fn new(mode: ParseMode, timezone: LogTimezone) -> Self {
+    FfmpegLog {
+      mode,
+      entries: vec![],
+      diagnostics: vec![],
+      timezone,
+      lines: LineBuffer::new(),
+      job: None,
+    }
+  }
+
+  fn parse_line(&mut self, number: usize, line: &str) {
+    if let Some(date_time) = line.strip_prefix(REPORT_HEADER) {
+      self.start_job(number, line);
+      match NaiveDateTime::parse_from_str(date_time.trim(), REPORT_DATE_FORMAT) {
+        Ok(date_time) => self.job.as_mut().unwrap().started = Some(date_time),
+        Err(_) => self.push_error(ParseError::new(number, line, ParseErrorKind::InvalidDate)),
+      }
+      return;
+    }
+
+    if line.starts_with(VERSION_HEADER) {
+      // with `-report`, the version follows the report header of the same run
+      let same_run = self.job.as_ref().map(|job| !job.with_version).unwrap_or(false);
+      if !same_run {
+        self.start_job(number, line);
+      }
+      self.job.as_mut().unwrap().with_version = true;
+      return;
+    }
+
+    let mut outcome = None;
+    let mut errors = vec![];
+
+    if let Some(ref mut job) = self.job {
+      // the progress line is rewritten with carriage returns
+      for segment in line.split('\r') {
+        if let Some(status) = parse_job_line(job, number, segment, &mut errors) {
+          outcome = Some((status, segment));
+          break;
+        }
+      }
+    }
+
+    for error in errors {
+      self.push_error(error);
+    }
+    if let Some((status, segment)) = outcome {
+      self.finish_job(status, number, segment);
+    }
+  }
+
+  fn start_job(&mut self, number: usize, line: &str) {
+    if let Some(job) = self.job.take() {
+      self.push_error(ParseError::new(job.line, &job.text, ParseErrorKind::MissingOutcome));
+    }
+    self.job = Some(Job::new(number, line));
+  }
+
+  fn finish_job(&mut self, status: Status, number: usize, line: &str) {
+    let job = match self.job.take() {
+      Some(job) => job,
+      None => return,
+    };
+
+    let encoding_time = match (job.time, job.speed) {
+      (Some(time), Some(speed)) if speed > 0.0 => Some((time / speed).round() as u64),
+      _ => None,
+    };
+    // FFmpeg does not timestamp its messages, the end of the run is estimated
+    // from the report header, or is unknown without one
+    let date_time = match (job.started, encoding_time) {
+      (Some(started), Some(encoding_time)) => {
+        Some(self.timezone.localize(started + Duration::seconds(encoding_time as i64)))
+      }
+      (Some(started), None) => Some(self.timezone.localize(started)),
+      (None, _) => None,
+    };
+
+    let status = if status == Status::Success && !job.messages.is_empty() {
+      Status::Warning
+    } else {
+      status
+    };
+
+    let mut entry = Entry::new(ENCODER, date_time.unwrap_or_else(|| self.timezone.now()), status);
+    entry.date_estimated = date_time.is_none();
+    if let Some(ref source_filename) = job.source_filename {
+      entry.set_source_filename(source_filename);
+    }
+    entry.output_filename = job.output_filename.map(|filename| normalize_path(&filename));
+    let duration = job.time;
+    entry.video = job.video.map(|mut video| {
+      video.duration = duration;
+      video
+    });
+    entry.audio = job.audio;
+    entry.messages = job.messages;
+    entry.encoding_time = encoding_time;
+    // the date is not always logged, the header and the final statistics
+    // of the run tell it apart from the other runs
+    let fingerprint = format!("{}\n{:?}\n{:?}\n{}", job.text, job.time, job.speed, line);
+    entry.identify(number, &fingerprint);
+
+    if self.mode == ParseMode::Lenient || job.errors.is_empty() {
+      self.entries.push(entry);
+    }
+  }
+
+  fn push_error(&mut self, error: ParseError) {
+    warn!("FFmpeg log, {}", error);
+    if let Some(ref mut job) = self.job {
+      job.errors.push(error.clone());
+    }
+    self.diagnostics.push(error);
+  }
+}
+
+impl EncoderLogParser for FfmpegLog {
+  fn name(&self) -> &'static str {
+    ENCODER
+  }
+
+  fn update(&mut self, text: &str) -> Result<usize, ParseError> {
+    let count = self.entries.len();
+    let diagnostics = self.diagnostics.len();
+
+    for (number, line) in self.lines.push(text) {
+      self.parse_line(number, &line);
+    }
+
+    if self.mode == ParseMode::Strict {
+      if let Some(error) = self.diagnostics.get(diagnostics) {
+        return Err(error.clone());
+      }
+    }
+    Ok(self.entries.len() - count)
+  }
+
+  fn entries(&self) -> &[Entry] {
+    &self.entries
+  }
+
+  fn diagnostics(&self) -> &[ParseError] {
+    &self.diagnostics
+  }
+}
+
+/// Read a line of a run, returns the outcome of the encode when the line ends it
+fn parse_job_line(
+  job: &mut Job,
+  number: usize,
+  line: &str,
+  errors: &mut Vec<ParseError>,
+) -> Option<Status> {
+  let trimmed = line.trim();
+
+  if line.starts_with("Input #") {
+    job.section = Section::Input;
+    if job.source_filename.is_none() {
+      job.source_filename = quoted_filename(line, " from '");
+    }
+    return None;
+  }
+  if line.starts_with("Output #") {
+    job.section = Section::Output;
+    if job.output_filename.is_none() {
+      job.output_filename = quoted_filename(line, " to '");
+    }
+    return None;
+  }
+  if line.starts_with("Stream mapping:") || line.starts_with("Press [q]") {
+    job.section = Section::Other;
+    return None;
+  }
+
+  if trimmed.starts_with("Stream #") {
+    if job.section == Section::Output {
+      if let Some(position) = trimmed.find(": Video: ") {
+        if job.video.is_none() {
+          job.video = parse_video(&trimmed[position + 9..]);
+          if job.video.is_none() {
+            errors.push(ParseError::new(number, line, ParseErrorKind::InvalidVideo));
+          }
+        }
+      } else if let Some(position) = trimmed.find(": Audio: ") {
+        if job.audio.is_none() {
+          job.audio = Some(parse_audio(&trimmed[position + 9..]));
+        }
+      }
+    }
+    return None;
+  }
+
+  if (line.starts_with("frame=") || line.starts_with("size=")) && line.contains("time=") {
+    if let Some(time) = progress_value(line, "time=").and_then(parse_time) {
+      job.time = Some(time);
+    }
+    if let Some(speed) = progress_value(line, "speed=") {
+      job.speed = speed.trim_end_matches('x').parse().ok();
+    }
+    return None;
+  }
+
+  // summary written at the end of a run, like `video:1000kB audio:200kB ...`
+  if line.starts_with("video:") && line.contains(" audio:") {
+    return Some(if job.interrupted {
+      Status::Interrupted
+    } else {
+      Status::Success
+    });
+  }
+  if line.starts_with("Conversion failed!") {
+    return Some(Status::Failed);
+  }
+  if line.starts_with("Received > 3 system signals") {
+    return Some(Status::Interrupted);
+  }
+  if line.starts_with("[q] command received") || line.starts_with("Exiting normally, received signal") {
+    job.interrupted = true;
+    return None;
+  }
+
+  if is_error_message(trimmed) {
+    job.messages.push(trimmed.to_string());
+  }
+  None
+}
+
+/// Start of the messages of the error level of FFmpeg
+const ERROR_PREFIXES: [&str; 6] = ["Error", "error ", "Invalid", "Could not", "Unknown encoder", "Unknown decoder"];
+/// End of the messages of FFmpeg about a file which can not be read or written
+const ERROR_SUFFIXES: [&str; 4] = [
+  ": No such file or directory",
+  ": Permission denied",
+  ": Invalid data found when processing input",
+  ": Invalid argument",
+];
+
+/// Error messages of FFmpeg, like `[libx264 @ 0x7f8] Error while opening
+/// encoder`. The filenames and the metadata may contain `error` as well.
+fn is_error_message(line: &str) -> bool {
+  // the message follows the `[component @ 0x7f8]` context, if any
+  let message = match line.find("] ") {
+    Some(position) if line.starts_with('[') => &line[position + 2..],
+    _ => line,
+  };
+  ERROR_PREFIXES.iter().any(|prefix| message.starts_with(prefix))
+    || ERROR_SUFFIXES.iter().any(|suffix| message.ends_with(suffix))
+}
+
+/// Filename in `Input #0, mov,mp4,m4a, from 'input.mov':`
+fn quoted_filename(line: &str, prefix: &str) -> Option<String> {
+  let start = line.find(prefix)? + prefix.len();
+  let end = line.rfind("':")?;
+  if end < start {
+    return None;
+  }
+  Some(line[start..end].to_string())
+}
+
+/// Value following `key` in a progress line, like `speed= 1.5x`
+fn progress_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+  let start = line.find(key)? + key.len();
+  line[start..].split_whitespace().next()
+}
+
+/// Parse a `00:01:23.45` duration, in seconds
+fn parse_time(time: &str) -> Option<f64> {
+  let parts: Vec<&str> = time.split(':').collect();
+  if parts.len() != 3 || time.starts_with('-') {
+    return None;
+  }
+
+  let hours: f64 = parts[0].parse().ok()?;
+  let minutes: f64 = parts[1].parse().ok()?;
+  let seconds: f64 = parts[2].parse().ok()?;
+  Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Split a stream description on the commas which are not between parentheses
+fn split_fields(description: &str) -> Vec<String> {
+  let mut fields = vec![];
+  let mut field = String::new();
+  let mut depth = 0;
+
+  for c in description.chars() {
+    match c {
+      '(' | '[' => depth += 1,
+      ')' | ']' => depth -= 1,
+      ',' if depth == 0 => {
+        fields.push(field.trim().to_string());
+        field.clear();
+        continue;
+      }
+      _ => {}
+    }
+    field.push(c);
+  }
+  fields.push(field.trim().to_string());
+  fields
+}
+
+/// Parse `h264 (libx264), yuv420p(progressive), 1280x720 [SAR 1:1 DAR 16:9], 25 fps, 12800 tbn`
+fn parse_video(description: &str) -> Option<Video> {
+  let fields = split_fields(description);
+  let mut video = None;
+  let mut frame_rate = None;
+  let mut tbr = None;
+  let mut scan_type = None;
+
+  for field in &fields {
+    let mut tokens = field.split_whitespace();
+    let first = tokens.next().unwrap_or("");
+
+    if video.is_none() {
+      let mut size = first.splitn(2, 'x');
+      if let (Some(Ok(width)), Some(Ok(height))) = (
+        size.next().map(|w| w.parse::<u32>()),
+        size.next().map(|h| h.parse::<u32>()),
+      ) {
+        let pixel_aspect_ratio = field
+          .find("[SAR ")
+          .and_then(|position| field[position + 5..].split_whitespace().next())
+          .and_then(parse_ratio);
+        video = Some((width, height, pixel_aspect_ratio));
+        continue;
+      }
+    }
+
+    match tokens.next() {
+      Some("fps") => frame_rate = parse_frame_rate(first),
+      Some("tbr") => tbr = parse_frame_rate(first),
+      _ => {}
+    }
+
+    if field.contains("top first") {
+      scan_type = Some("Upper".to_string());
+    } else if field.contains("bottom first") {
+      scan_type = Some("Lower".to_string());
+    } else if field.contains("progressive") {
+      scan_type = Some("Progressive".to_string());
+    }
+  }
+
+  let (width, height, pixel_aspect_ratio) = video?;
+  Some(Video {
+    width,
+    height,
+    pixel_aspect_ratio,
+    frame_rate: frame_rate.or(tbr),
+    scan_type,
+    timecode: None,
+    duration: None,
+  })
+}
+
+/// Parse `aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 128 kb/s (default)`
+fn parse_audio(description: &str) -> Audio {
+  let fields = split_fields(description);
+  let codec = fields
+    .first()
+    .and_then(|field| field.split_whitespace().next())
+    .map(|codec| codec.to_string());
+
+  let mut audio = Audio {
+    bit_depth: codec
+      .as_ref()
+      .filter(|codec| codec.starts_with("pcm_"))
+      .and_then(|codec| sample_format_bit_depth(codec)),
+    codec,
+    bitrate: None,
+    sample_rate: None,
+    channel_layout: None,
+  };
+
+  let mut after_sample_rate = 0;
+  for field in fields.iter().skip(1) {
+    let mut tokens = field.split_whitespace();
+    let value = tokens.next().unwrap_or("");
+
+    match tokens.next() {
+      Some("Hz") => {
+        audio.sample_rate = value.parse().ok();
+        after_sample_rate = 1;
+        continue;
+      }
+      Some("kb/s") => audio.bitrate = value.parse().ok(),
+      _ => {}
+    }
+
+    // the sample rate is followed by the channel layout and the sample format
+    match after_sample_rate {
+      1 => audio.channel_layout = Some(field.to_string()),
+      2 if audio.bit_depth.is_none() => audio.bit_depth = sample_format_bit_depth(field),
+      _ => {}
+    }
+    if after_sample_rate > 0 {
+      after_sample_rate += 1;
+    }
+  }
+  audio
+}
+
+/// Bit depth of integer sample formats, like `s16p` or `pcm_s24le`
+fn sample_format_bit_depth(format: &str) -> Option<u32> {
+  let format = format.trim_start_matches("pcm_");
+  if format == "u8" || format == "u8p" {
+    return Some(8);
+  }
+  if !format.starts_with('s') {
+    return None;
+  }
+  let digits: String = format[1..].chars().take_while(|c| c.is_ascii_digit()).collect();
+  digits.parse().ok()
+}
+
+fn parse_ratio(ratio: &str) -> Option<f64> {
+  let mut parts = ratio.trim_end_matches(']').splitn(2, ':');
+  let numerator: f64 = parts.next()?.parse().ok()?;
+  let denominator: f64 = parts.next()?.parse().ok()?;
+  if denominator == 0.0 {
+    return None;
+  }
+  Some(numerator / denominator)
+}
+
+/// Parse `25`, `29.97` or `30k` (thousands of frames per second)
+fn parse_frame_rate(value: &str) -> Option<f64> {
+  if let Some(thousands) = value.strip_suffix('k') {
+    return thousands.parse::<f64>().ok().map(|rate| rate * 1000.0);
+  }
+  value.parse().ok()
+}
+
+/// Parser of the key/value blocks written with `-progress`, each block ends
+/// with `progress=continue`, the last one of the run with `progress=end`.
+#[derive(Debug)]
+pub struct FfmpegProgressLog {
+  pub mode: ParseMode,
+  pub entries: Vec<Entry>,
+  pub diagnostics: Vec<ParseError>,
+  timezone: LogTimezone,
+  lines: LineBuffer,
+  values: Vec<(String, String)>,
+}
+
+impl FfmpegProgressLog {
+  pub fn new(mode: ParseMode, timezone: LogTimezone) -> Self {
+    FfmpegProgressLog {
+      mode,
+      entries: vec![],
+      diagnostics: vec![],
+      timezone,
+      lines: LineBuffer::new(),
+      values: vec![],
+    }
+  }
+
+  fn value(&self, key: &str) -> Option<&str> {
+    self
+      .values
+      .iter()
+      .rev()
+      .find(|(k, _)| k == key)
+      .map(|(_, value)| value.as_str())
+  }
+
+  fn parse_line(&mut self, number: usize, line: &str) {
+    if line.trim().is_empty() {
+      return;
+    }
+
+    let mut parts = line.splitn(2, '=');
+    let (key, value) = match (parts.next(), parts.next()) {
+      (Some(key), Some(value)) => (key.trim(), value.trim()),
+      _ => {
+        let error = ParseError::new(number, line, ParseErrorKind::UnexpectedLine);
+        warn!("FFmpeg progress, {}", error);
+        self.diagnostics.push(error);
+        return;
+      }
+    };
+
+    if key != "progress" {
+      self.values.push((key.to_string(), value.to_string()));
+      return;
+    }
+
+    if value == "end" {
+      self.finish(number);
+    }
+    self.values.clear();
+  }
+
+  fn finish(&mut self, number: usize) {
+    let time = self
+      .value("out_time_us")
+      .and_then(|us| us.parse::<i64>().ok())
+      .filter(|&us| us >= 0)
+      .map(|us| us as f64 / 1_000_000.0)
+      .or_else(|| self.value("out_time").and_then(parse_time));
+    let speed = self
+      .value("speed")
+      .and_then(|speed| speed.trim_end_matches('x').parse::<f64>().ok());
+
+    // the progress blocks are not timestamped, the date of the encode is unknown
+    let mut entry = Entry::new(PROGRESS_ENCODER, self.timezone.now(), Status::Success);
+    entry.date_estimated = true;
+    entry.encoding_time = match (time, speed) {
+      (Some(time), Some(speed)) if speed > 0.0 => Some((time / speed).round() as u64),
+      _ => None,
+    };
+    let fingerprint: Vec<String> = self
+      .values
+      .iter()
+      .map(|(key, value)| format!("{}={}", key, value))
+      .collect();
+    entry.identify(number, &fingerprint.join("\n"));
+    self.entries.push(entry);
+  }
+}
+
+impl EncoderLogParser for FfmpegProgressLog {
+  fn name(&self) -> &'static str {
+    PROGRESS_ENCODER
+  }
+
+  fn update(&mut self, text: &str) -> Result<usize, ParseError> {
+    let count = self.entries.len();
+    let diagnostics = self.diagnostics.len();
+
+    for (number, line) in self.lines.push(text) {
+      self.parse_line(number, &line);
+    }
+
+    if self.mode == ParseMode::Strict {
+      if let Some(error) = self.diagnostics.get(diagnostics) {
+        return Err(error.clone());
+      }
+    }
+    Ok(self.entries.len() - count)
+  }
+
+  fn entries(&self) -> &[Entry] {
+    &self.entries
+  }
+
+  fn diagnostics(&self) -> &[ParseError] {
+    &self.diagnostics
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const LOG: &str = concat!(
+    "ffmpeg started on 2018-02-16 at 22:11:56\n",
+    "Report written to \"ffmpeg-20180216-221156.log\"\n",
+    "ffmpeg version 3.4.1 Copyright (c) 2000-2017 the FFmpeg developers\n",
+    "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'D:\\Rushes\\interview.mov':\n",
+    "  Duration: 00:00:10.00, start: 0.000000, bitrate: 50000 kb/s\n",
+    "    Stream #0:0(und): Video: prores (apcn / 0x6E637061), yuv422p10le, 1920x1080, 25 fps\n",
+    "Output #0, mp4, to 'D:\\Renders\\interview.mp4':\n",
+    "    Stream #0:0(und): Video: h264 (libx264) (avc1 / 0x31637661), yuv420p(progressive), 1280x720 [SAR 1:1 DAR 16:9], q=-1--1, 25 fps, 12800 tbn, 25 tbc\n",
+    "    Stream #0:1(und): Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 128 kb/s\n",
+    "Stream mapping:\n",
+    "  Stream #0:0 -> #0:0 (prores (native) -> h264 (libx264))\n",
+    "Press [q] to stop, [?] for help\n",
+    "frame=  100 fps= 50 q=28.0 size=    2000kB time=00:00:04.00 bitrate=4096.0kbits/s speed=2.00x\r",
+    "frame=  250 fps= 50 q=-1.0 Lsize=    6000kB time=00:00:10.00 bitrate=4915.2kbits/s speed=2.00x\n",
+    "video:5800kB audio:160kB subtitle:0kB other streams:0kB global headers:0kB muxing overhead: 0.5%\n",
+    "ffmpeg version 3.4.1 Copyright (c) 2000-2017 the FFmpeg developers\n",
+    "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'broll.mov':\n",
+    "Output #0, mp4, to 'broll.mp4':\n",
+    "[libx264 @ 0x7f8] Error while opening encoder for output stream #0:0\n",
+    "Conversion failed!\n",
+    "ffmpeg version 3.4.1 Copyright (c) 2000-2017 the FFmpeg developers\n",
+    "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'long.mov':\n",
+    "Output #0, mp4, to 'long.mp4':\n",
+    "frame=  500 fps= 50 q=-1.0 Lsize=   12000kB time=00:00:20.00 bitrate=4915.2kbits/s speed=2.00x\n",
+    "[q] command received. Exiting.\n",
+    "video:11600kB audio:320kB subtitle:0kB other streams:0kB global headers:0kB muxing overhead: 0.5%\n",
+    "ffmpeg version 3.4.1 Copyright (c) 2000-2017 the FFmpeg developers\n",
+    "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'crash.mov':\n",
+  );
+
+  const PROGRESS_LOG: &str = "\
+frame=100
+out_time_us=4000000
+speed=2x
+progress=continue
+frame=250
+out_time_us=10000000
+out_time=00:00:10.000000
+speed=2x
+progress=end
+";
+
+  #[test]
+  fn runs() {
+    let mut parser = FfmpegLog::new(ParseMode::Lenient, LogTimezone::Utc);
+    assert_eq!(parser.update(LOG).unwrap(), 3);
+
+    let statuses: Vec<Status> = parser.entries.iter().map(|entry| entry.status).collect();
+    assert_eq!(statuses, vec![Status::Success, Status::Failed, Status::Interrupted]);
+
+    // the last run has no outcome yet
+    assert!(parser.diagnostics.is_empty());
+    parser.update("ffmpeg version 3.4.1\n").unwrap();
+    assert_eq!(parser.diagnostics[0].kind, ParseErrorKind::MissingOutcome);
+    assert_eq!(parser.diagnostics[0].line, 26);
+  }
+
+  #[test]
+  fn entry_fields() {
+    let mut parser = FfmpegLog::new(ParseMode::Strict, LogTimezone::Utc);
+    parser.update(LOG).unwrap();
+
+    let entry = &parser.entries[0];
+    assert_eq!(entry.date_time.to_rfc3339(), "2018-02-16T22:12:01+00:00");
+    assert!(!entry.date_estimated);
+    assert_eq!(entry.source_filename, Some("D:/Rushes/interview.mov".to_owned()));
+    assert_eq!(entry.output_filename, Some("D:/Renders/interview.mp4".to_owned()));
+    assert_eq!(entry.encoding_time, Some(5));
+
+    let video = entry.video.as_ref().unwrap();
+    assert_eq!((video.width, video.height), (1280, 720));
+    assert_eq!(video.pixel_aspect_ratio, Some(1.0));
+    assert_eq!(video.frame_rate, Some(25.0));
+    assert_eq!(video.scan_type, Some("Progressive".to_owned()));
+    assert_eq!(video.duration, Some(10.0));
+
+    let audio = entry.audio.as_ref().unwrap();
+    assert_eq!(audio.codec, Some("aac".to_owned()));
+    assert_eq!(audio.bitrate, Some(128));
+    assert_eq!(audio.sample_rate, Some(48000));
+    assert_eq!(audio.channel_layout, Some("stereo".to_owned()));
+    assert_eq!(audio.bit_depth, None);
+  }
+
+  #[test]
+  fn error_messages() {
+    let log = concat!(
+      "ffmpeg version 3.4.1 Copyright (c) 2000-2017 the FFmpeg developers\n",
+      "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'error_reel.mov':\n",
+      "  Metadata:\n",
+      "    comment         : invalid take, kept for the error reel\n",
+      "Output #0, mp4, to 'error_reel.mp4':\n",
+      "frame=  250 fps= 50 q=-1.0 Lsize=    6000kB time=00:00:10.00 bitrate=4915.2kbits/s speed=2.00x\n",
+      "video:5800kB audio:160kB subtitle:0kB other streams:0kB global headers:0kB muxing overhead: 0.5%\n",
+      "ffmpeg version 3.4.1 Copyright (c) 2000-2017 the FFmpeg developers\n",
+      "[h264 @ 0x7f8] error while decoding MB 10 20, bytestream -5\n",
+      "[mp4 @ 0x7f9] Invalid timestamps stream=0, pts=100, dts=120, size=3000\n",
+      "Output #0, mp4, to 'missing.mp4':\n",
+      "missing.mov: No such file or directory\n",
+      "video:5800kB audio:160kB subtitle:0kB other streams:0kB global headers:0kB muxing overhead: 0.5%\n",
+    );
+    let mut parser = FfmpegLog::new(ParseMode::Strict, LogTimezone::Utc);
+    assert_eq!(parser.update(log).unwrap(), 2);
+
+    assert_eq!(parser.entries[0].status, Status::Success);
+    assert!(parser.entries[0].messages.is_empty());
+    assert_eq!(parser.entries[1].status, Status::Warning);
+    assert_eq!(
+      parser.entries[1].messages,
+      vec![
+        "[h264 @ 0x7f8] error while decoding MB 10 20, bytestream -5".to_owned(),
+        "[mp4 @ 0x7f9] Invalid timestamps stream=0, pts=100, dts=120, size=3000".to_owned(),
+        "missing.mov: No such file or directory".to_owned(),
+      ]
+    );
+  }
+
+  #[test]
+  fn undated_runs() {
+    let mut parser = FfmpegLog::new(ParseMode::Strict, LogTimezone::Utc);
+    parser.update(LOG).unwrap();
+
+    let entry = &parser.entries[1];
+    assert!(entry.date_estimated);
+    assert_eq!(entry.known_date_time(), None);
+    assert_eq!(entry.messages, vec!["[libx264 @ 0x7f8] Error while opening encoder for output stream #0:0".to_owned()]);
+    assert!(parser.entries[2].date_estimated);
+  }
+
+  #[test]
+  fn progress_blocks() {
+    let mut parser = FfmpegProgressLog::new(ParseMode::Strict, LogTimezone::Utc);
+    assert_eq!(parser.update(PROGRESS_LOG).unwrap(), 1);
+
+    let entry = &parser.entries[0];
+    assert_eq!(entry.status, Status::Success);
+    assert_eq!(entry.encoding_time, Some(5));
+    assert!(entry.date_estimated);
+
+    let error = parser.update("frame\n").unwrap_err();
+    assert_eq!((error.line, error.kind), (10, ParseErrorKind::UnexpectedLine));
+  }
+
+  #[test]
+  fn stream_descriptions() {
+    let video = parse_video("mpeg2video (Main), yuv420p(tv, top first), 720x576 [SAR 16:15 DAR 4:3], 29.97 fps, 30k tbr").unwrap();
+    assert_eq!(video.scan_type, Some("Upper".to_owned()));
+    assert_eq!(video.frame_rate, Some(29.97));
+    assert_eq!(video.pixel_aspect_ratio, Some(16.0 / 15.0));
+
+    let audio = parse_audio("pcm_s24le ([1][0][0][0] / 0x0001), 48000 Hz, 2 channels, s32 (24 bit), 2304 kb/s");
+    assert_eq!(audio.codec, Some("pcm_s24le".to_owned()));
+    assert_eq!(audio.bit_depth, Some(24));
+    assert_eq!(audio.channel_layout, Some("2 channels".to_owned()));
+
+    assert_eq!(parse_time("01:02:03.50"), Some(3723.5));
+    assert_eq!(parse_time("-00:00:01.00"), None);
+  }
+}
@@ -0,0 +1,531 @@
+use chrono::{NaiveDateTime, NaiveTime};
+use encoder_log::{
+  normalize_path, Audio, Bitrate, EncoderLogParser, Entry, LineBuffer, ParseError,
+  ParseErrorKind, ParseMode, Status, Video,
+};
+use timezone::LogTimezone;
+
+const ENCODER: &str = "handbrake";
+
+/// `Starting work at: Fri Feb 16 22:12:00 2018`, the only line with a date
+const START_HEADER: &str = "Starting work at: ";
+const START_DATE_FORMAT: &str = "%a %b %d %H:%M:%S %Y";
+const WORK_RESULT: &str = "libhb: work result = ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+  Source,
+  Destination,
+  Video,
+  Audio,
+  Other,
+}
+
+/// Last `+ decoder:` or `+ encoder:` item of a track, the following
+/// `+ bitrate:` line belongs to it
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Item {
+  Decoder,
+  Encoder,
+  Other,
+}
+
+/// Encode being read, from its `job configuration:` to its work result
+#[derive(Debug)]
+struct Job {
+  line: usize,
+  text: String,
+  started: NaiveDateTime,
+  section: Section,
+  item: Item,
+  source_filename: Option<String>,
+  output_filename: Option<String>,
+  video: Option<Video>,
+  pixel_aspect_ratio: Option<f64>,
+  frame_rate: Option<f64>,
+  audio: Option<Audio>,
+  bitrate: Option<Bitrate>,
+  messages: Vec<String>,
+  errors: Vec<ParseError>,
+}
+
+/// Parser of the activity log of HandBrake (GUI or CLI). The lines are
+/// prefixed with `[22:12:00]`, the date is taken from `Starting work at:`.
+#[derive(Debug)]
+pub struct HandBrakeLog {
+  pub mode: ParseMode,
+  pub entries: Vec<Entry>,
+  pub diagnostics: Vec<ParseError>,
+  timezone: LogTimezone,
+  lines: LineBuffer,
+  /// Date and time of the last timestamped line
+  date_time: Option<NaiveDateTime>,
+  job: Option<Job>,
+}
+
+impl HandBrakeLog {
+  pub fn new(mode: ParseMode, timezone: LogTimezone) -> Self {
+    HandBrakeLog {
+      mode,
+      entries: vec![],
+      diagnostics: vec![],
+      timezone,
+      lines: LineBuffer::new(),
+      date_time: None,
+      job: None,
+    }
+  }
+
+  fn parse_line(&mut self, number: usize, line: &str) {
+    let (time, message) = match split_timestamp(line) {
+      Some(parts) => parts,
+      None => return,
+    };
+
+    if let Some(date) = message.strip_prefix(START_HEADER) {
+      let date = date.split_whitespace().collect::<Vec<&str>>().join(" ");
+      match NaiveDateTime::parse_from_str(&date, START_DATE_FORMAT) {
+        Ok(date_time) => self.date_time = Some(date_time),
+        Err(_) => self.push_error(ParseError::new(number, line, ParseErrorKind::InvalidDate)),
+      }
+      return;
+    }
+
+    let date_time = match self.date_time {
+      Some(previous) => {
+        // the timestamps only have the time, a day has passed when it goes backwards
+        let mut date = previous.date();
+        if time < previous.time() {
+          date = date.succ_opt().unwrap_or(date);
+        }
+        NaiveDateTime::new(date, time)
+      }
+      None => {
+        if message.starts_with(WORK_RESULT) {
+          self.push_error(ParseError::new(number, line, ParseErrorKind::InvalidDate));
+        }
+        return;
+      }
+    };
+    self.date_time = Some(date_time);
+
+    if message.trim() == "job configuration:" {
+      if let Some(job) = self.job.take() {
+        self.push_error(ParseError::new(job.line, &job.text, ParseErrorKind::MissingOutcome));
+      }
+      self.job = Some(Job {
+        line: number,
+        text: line.to_string(),
+        started: date_time,
+        section: Section::Other,
+        item: Item::Other,
+        source_filename: None,
+        output_filename: None,
+        video: None,
+        pixel_aspect_ratio: None,
+        frame_rate: None,
+        audio: None,
+        bitrate: None,
+        messages: vec![],
+        errors: vec![],
+      });
+      return;
+    }
+
+    if let Some(result) = message.strip_prefix(WORK_RESULT) {
+      match result.trim().parse::<u32>() {
+        Ok(result) => self.finish_job(number, date_time, result),
+        Err(_) => self.push_error(ParseError::new(number, line, ParseErrorKind::UnexpectedLine)),
+      }
+      return;
+    }
+
+    let mut errors = vec![];
+    if let Some(ref mut job) = self.job {
+      parse_job_line(job, number, line, message, &mut errors);
+    }
+    for error in errors {
+      self.push_error(error);
+    }
+  }
+
+  fn finish_job(&mut self, number: usize, date_time: NaiveDateTime, result: u32) {
+    let job = match self.job.take() {
+      Some(job) => job,
+      None => return,
+    };
+
+    // values of `hb_error_code`
+    let (status, message) = match result {
+      0 => (Status::Success, None),
+      1 => (Status::Interrupted, None),
+      2 => (Status::Failed, Some("wrong input")),
+      3 => (Status::Failed, Some("initialization error")),
+      5 => (Status::Failed, Some("read error")),
+      _ => (Status::Failed, Some("unknown error")),
+    };
+    let status = if status == Status::Success && !job.messages.is_empty() {
+      Status::Warning
+    } else {
+      status
+    };
+
+    let date_time = self.timezone.localize(date_time);
+    let mut entry = Entry::new(ENCODER, date_time, status);
+    entry.messages = job.messages;
+    if let Some(message) = message {
+      entry.messages.push(format!("work result = {}, {}", result, message));
+    }
+    if let Some(ref source_filename) = job.source_filename {
+      entry.set_source_filename(source_filename);
+    }
+    entry.output_filename = job.output_filename.map(|filename| normalize_path(&filename));
+    let (pixel_aspect_ratio, frame_rate) = (job.pixel_aspect_ratio, job.frame_rate);
+    entry.video = job.video.map(|mut video| {
+      video.pixel_aspect_ratio = pixel_aspect_ratio;
+      video.frame_rate = frame_rate;
+      video
+    });
+    entry.audio = job.audio;
+    entry.bitrate = job.bitrate;
+    let started = self.timezone.localize(job.started);
+    let encoding_time = date_time.signed_duration_since(started).num_seconds();
+    entry.encoding_time = Some(encoding_time.max(0) as u64);
+    entry.identify(number, &date_time.naive_local().to_string());
+
+    if self.mode == ParseMode::Lenient || job.errors.is_empty() {
+      self.entries.push(entry);
+    }
+  }
+
+  fn push_error(&mut self, error: ParseError) {
+    warn!("HandBrake log, {}", error);
+    if let Some(ref mut job) = self.job {
+      job.errors.push(error.clone());
+    }
+    self.diagnostics.push(error);
+  }
+}
+
+impl EncoderLogParser for HandBrakeLog {
+  fn name(&self) -> &'static str {
+    ENCODER
+  }
+
+  fn update(&mut self, text: &str) -> Result<usize, ParseError> {
+    let count = self.entries.len();
+    let diagnostics = self.diagnostics.len();
+
+    for (number, line) in self.lines.push(text) {
+      self.parse_line(number, &line);
+    }
+
+    if self.mode == ParseMode::Strict {
+      if let Some(error) = self.diagnostics.get(diagnostics) {
+        return Err(error.clone());
+      }
+    }
+    Ok(self.entries.len() - count)
+  }
+
+  fn entries(&self) -> &[Entry] {
+    &self.entries
+  }
+
+  fn diagnostics(&self) -> &[ParseError] {
+    &self.diagnostics
+  }
+}
+
+/// Split `[22:12:00] message` into its time and message
+fn split_timestamp(line: &str) -> Option<(NaiveTime, &str)> {
+  let rest = line.strip_prefix('[')?;
+  let end = rest.find(']')?;
+  let time = NaiveTime::parse_from_str(&rest[..end], "%H:%M:%S").ok()?;
+  Some((time, rest[end + 1..].trim_start_matches(' ')))
+}
+
+/// Read a line of the job configuration, like
+///
+/// ```text
+///  * video track
+///    + encoder: H.264 (libx264)
+///      + bitrate: 5000 kbps, pass: 0
+/// ```
+fn parse_job_line(
+  job: &mut Job,
+  number: usize,
+  line: &str,
+  message: &str,
+  errors: &mut Vec<ParseError>,
+) {
+  let item = message.trim();
+
+  if let Some(section) = item.strip_prefix("* ") {
+    job.item = Item::Other;
+    job.section = if section.starts_with("source") {
+      Section::Source
+    } else if section.starts_with("destination") {
+      Section::Destination
+    } else if section.starts_with("video track") {
+      Section::Video
+    } else if section.starts_with("audio track") && job.audio.is_none() {
+      Section::Audio
+    } else {
+      Section::Other
+    };
+
+    // older releases wrote `* destination: /path/to/output.mp4`
+    if let Some(position) = section.find(": ") {
+      set_filename(job, &section[position + 2..]);
+    }
+    return;
+  }
+
+  let item = match item.strip_prefix("+ ") {
+    Some(item) => item,
+    None => {
+      if item.to_lowercase().contains("error") {
+        job.messages.push(item.to_string());
+      }
+      return;
+    }
+  };
+
+  if item.starts_with("decoder:") {
+    job.item = Item::Decoder;
+  } else if item.starts_with("encoder:") {
+    job.item = Item::Encoder;
+  }
+
+  match job.section {
+    Section::Source | Section::Destination => set_filename(job, item),
+    Section::Video => {
+      if let Some(dimensions) = item.strip_prefix("storage dimensions: ") {
+        match parse_dimensions(dimensions) {
+          Some((width, height)) => {
+            job.video = Some(Video {
+              width,
+              height,
+              pixel_aspect_ratio: None,
+              frame_rate: None,
+              scan_type: None,
+              timecode: None,
+              duration: None,
+            })
+          }
+          None => errors.push(ParseError::new(number, line, ParseErrorKind::InvalidVideo)),
+        }
+      }
+      if let Some(ratio) = item.strip_prefix("pixel aspect ratio: ") {
+        job.pixel_aspect_ratio = parse_ratio(ratio);
+      }
+      if let Some(frame_rate) = item.strip_prefix("frame rate: ") {
+        job.frame_rate = parse_frame_rate(frame_rate);
+      }
+      if job.item == Item::Encoder {
+        if let Some(bitrate) = item.strip_prefix("bitrate: ") {
+          job.bitrate = parse_bitrate(bitrate);
+          if job.bitrate.is_none() {
+            errors.push(ParseError::new(number, line, ParseErrorKind::InvalidBitrate));
+          }
+        }
+        if item.starts_with("quality: ") {
+          job.bitrate = Some(Bitrate {
+            mode: Some("Constant Quality".to_string()),
+            passes: None,
+            target: None,
+            max: None,
+          });
+        }
+      }
+    }
+    Section::Audio => {
+      let audio = job.audio.get_or_insert(Audio {
+        codec: None,
+        bitrate: None,
+        sample_rate: None,
+        channel_layout: None,
+        bit_depth: None,
+      });
+      if let Some(codec) = item.strip_prefix("encoder: ") {
+        audio.codec = codec.split(" (").next().map(|codec| codec.trim().to_string());
+      }
+      if let Some(mixdown) = item.strip_prefix("mixdown: ") {
+        audio.channel_layout = Some(mixdown.trim().to_string());
+      }
+      if job.item == Item::Encoder {
+        // `bitrate: 160 kbps, samplerate: 48000 Hz`
+        for part in item.split(", ") {
+          if let Some(bitrate) = part.strip_prefix("bitrate: ") {
+            audio.bitrate = bitrate.trim_end_matches(" kbps").trim().parse().ok();
+          }
+          if let Some(sample_rate) = part.strip_prefix("samplerate: ") {
+            audio.sample_rate = sample_rate.trim_end_matches(" Hz").trim().parse().ok();
+          }
+        }
+      }
+    }
+    Section::Other => {}
+  }
+}
+
+/// The first `+` item of the source and destination sections is the path
+fn set_filename(job: &mut Job, path: &str) {
+  match job.section {
+    Section::Source if job.source_filename.is_none() => {
+      job.source_filename = Some(path.trim().to_string())
+    }
+    Section::Destination if job.output_filename.is_none() => {
+      job.output_filename = Some(path.trim().to_string())
+    }
+    _ => {}
+  }
+}
+
+/// Parse `1280 x 720`, also written `1280 * 720, mod 2` by some releases
+fn parse_dimensions(dimensions: &str) -> Option<(u32, u32)> {
+  let mut values = dimensions
+    .split(|c: char| !c.is_ascii_digit())
+    .filter(|value| !value.is_empty())
+    .map(|value| value.parse::<u32>());
+
+  match (values.next(), values.next()) {
+    (Some(Ok(width)), Some(Ok(height))) => Some((width, height)),
+    _ => None,
+  }
+}
+
+/// Parse `1 : 1` or `64:45`
+fn parse_ratio(ratio: &str) -> Option<f64> {
+  let mut parts = ratio.splitn(2, ':');
+  let numerator: f64 = parts.next()?.trim().parse().ok()?;
+  let denominator: f64 = parts.next()?.trim().parse().ok()?;
+  if denominator == 0.0 {
+    return None;
+  }
+  Some(numerator / denominator)
+}
+
+/// Parse `25.000 fps -> constant 25.000 fps`, the output rate is the last one
+fn parse_frame_rate(frame_rate: &str) -> Option<f64> {
+  frame_rate
+    .rsplit("->")
+    .next()?
+    .split_whitespace()
+    .filter_map(|value| value.trim_start_matches('(').parse::<f64>().ok())
+    .next_back()
+}
+
+/// Parse `5000 kbps, pass: 2`, the pass is 0 for a single pass encode
+fn parse_bitrate(bitrate: &str) -> Option<Bitrate> {
+  let mut parts = bitrate.split(", ");
+  let target: f64 = parts.next()?.trim_end_matches(" kbps").trim().parse().ok()?;
+  let passes = parts
+    .find_map(|part| part.strip_prefix("pass: "))
+    .and_then(|pass| pass.trim().parse::<u32>().ok())
+    .map(|pass| if pass == 0 { 1 } else { 2 });
+
+  Some(Bitrate {
+    mode: Some("ABR".to_string()),
+    passes,
+    target: Some(target / 1000.0),
+    max: None,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const LOG: &str = r#"[22:11:56] hb_init: starting libhb thread
+[22:11:56] Starting work at: Fri Feb 16 22:11:56 2018
+[22:11:57] 1 job(s) to process
+[22:11:57] job configuration:
+[22:11:57]  * source
+[22:11:57]    + C:\Rushes\interview.mov
+[22:11:57]    + title 1, chapter(s) 1 to 1
+[22:11:57]  * destination
+[22:11:57]    + C:\Renders\interview.mp4
+[22:11:57]    + container: MPEG-4 (libavformat)
+[22:11:57]  * video track
+[22:11:57]    + decoder: prores
+[22:11:57]    + frame rate: 25.000 fps -> constant 25.000 fps
+[22:11:57]    + storage dimensions: 1280 x 720, mod 2
+[22:11:57]    + pixel aspect ratio: 1 : 1
+[22:11:57]    + encoder: H.264 (libx264)
+[22:11:57]      + bitrate: 5000 kbps, pass: 2
+[22:11:57]  * audio track 1
+[22:11:57]    + decoder: English (PCM) (track 1, id 0x2)
+[22:11:57]    + mixdown: Stereo
+[22:11:57]    + encoder: AAC (avcodec)
+[22:11:57]      + bitrate: 160 kbps, samplerate: 48000 Hz
+[22:14:06] libhb: work result = 0
+[23:59:50] job configuration:
+[23:59:50]  * source
+[23:59:50]    + C:\Rushes\missing.mov
+[00:00:10] ERROR: C:\Rushes\missing.mov: No such file or directory
+[00:00:10] libhb: work result = 2
+"#;
+
+  #[test]
+  fn jobs() {
+    let mut parser = HandBrakeLog::new(ParseMode::Strict, LogTimezone::Utc);
+    assert_eq!(parser.update(LOG).unwrap(), 2);
+
+    let entry = &parser.entries[0];
+    assert_eq!(entry.status, Status::Success);
+    assert_eq!(entry.date_time.to_rfc3339(), "2018-02-16T22:14:06+00:00");
+    assert_eq!(entry.encoding_time, Some(129));
+    assert_eq!(entry.source_filename, Some("C:/Rushes/interview.mov".to_owned()));
+    assert_eq!(entry.output_filename, Some("C:/Renders/interview.mp4".to_owned()));
+
+    let video = entry.video.as_ref().unwrap();
+    assert_eq!((video.width, video.height), (1280, 720));
+    assert_eq!((video.pixel_aspect_ratio, video.frame_rate), (Some(1.0), Some(25.0)));
+
+    let bitrate = entry.bitrate.as_ref().unwrap();
+    assert_eq!(bitrate.mode, Some("ABR".to_owned()));
+    assert_eq!((bitrate.passes, bitrate.target), (Some(2), Some(5.0)));
+
+    let audio = entry.audio.as_ref().unwrap();
+    assert_eq!(audio.codec, Some("AAC".to_owned()));
+    assert_eq!((audio.bitrate, audio.sample_rate), (Some(160), Some(48000)));
+    assert_eq!(audio.channel_layout, Some("Stereo".to_owned()));
+  }
+
+  #[test]
+  fn failed_job_after_midnight() {
+    let mut parser = HandBrakeLog::new(ParseMode::Strict, LogTimezone::Utc);
+    parser.update(LOG).unwrap();
+
+    let entry = &parser.entries[1];
+    assert_eq!(entry.status, Status::Failed);
+    assert_eq!(entry.date_time.to_rfc3339(), "2018-02-17T00:00:10+00:00");
+    assert_eq!(entry.encoding_time, Some(20));
+    assert_eq!(
+      entry.messages,
+      vec![
+        r"ERROR: C:\Rushes\missing.mov: No such file or directory".to_owned(),
+        "work result = 2, wrong input".to_owned(),
+      ]
+    );
+  }
+
+  #[test]
+  fn work_result_without_date() {
+    let mut parser = HandBrakeLog::new(ParseMode::Strict, LogTimezone::Utc);
+    let error = parser.update("[22:14:06] libhb: work result = 0\n").unwrap_err();
+    assert_eq!((error.line, error.kind), (1, ParseErrorKind::InvalidDate));
+    assert!(parser.entries.is_empty());
+  }
+
+  #[test]
+  fn job_values() {
+    assert_eq!(parse_dimensions("1280 * 720, mod 2"), Some((1280, 720)));
+    assert_eq!(parse_ratio("64:45"), Some(64.0 / 45.0));
+    assert_eq!(parse_ratio("1 : 0"), None);
+    let bitrate = parse_bitrate("2500 kbps, pass: 0").unwrap();
+    assert_eq!((bitrate.passes, bitrate.target), (Some(1), Some(2.5)));
+  }
+}
@@ -0,0 +1,345 @@
+use entry_filter::{matches_pattern, strings};
+use serde_json::{self, Value};
+use std::cmp::{min, Ordering};
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Entries per message of a streamed listing, unless the request sets it
+const DEFAULT_CHUNK_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+  Name,
+  Size,
+  Modified,
+  /// Directories first, then by extension
+  Type,
+}
+
+/// What is listed from a directory, read from a `file_system` request:
+/// `sort` (`name`, `size`, `modified` or `type`), `order` (`asc` or `desc`),
+/// `offset`, `limit`, `cursor` from a previous page, `filter` patterns on
+/// the filenames, `hidden` and `stream` with its `chunk_size`.
+#[derive(Debug)]
+pub struct ListingOptions {
+  pub sort: SortKey,
+  pub descending: bool,
+  pub offset: usize,
+  pub limit: Option<usize>,
+  /// The listing starts after this entry
+  pub cursor: Option<Position>,
+  /// Lowercase patterns with `*` and `?`, one of them must match the filename
+  pub filters: Vec<String>,
+  pub hidden: bool,
+  /// Entries per message when the listing is streamed
+  pub chunk_size: Option<usize>,
+}
+
+/// Fields of an entry its place in a listing depends on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+  pub name: String,
+  pub is_dir: bool,
+  pub size: u64,
+  /// Milliseconds since the epoch
+  pub modified: i64,
+}
+
+/// Entries selected from a listing, with what is needed to request the next
+/// ones
+#[derive(Debug)]
+pub struct Page<T> {
+  pub items: Vec<T>,
+  /// Entries matching the filters, in all the pages
+  pub total: usize,
+  /// Index of the first entry of the page among them
+  pub start: usize,
+  pub next_cursor: Option<String>,
+}
+
+impl ListingOptions {
+  pub fn from_payload(payload: &Value) -> Result<Self, String> {
+    let sort = match payload.get("sort").and_then(|sort| sort.as_str()) {
+      None | Some("name") => SortKey::Name,
+      Some("size") => SortKey::Size,
+      Some("modified") => SortKey::Modified,
+      Some("type") => SortKey::Type,
+      Some(sort) => return Err(format!("invalid sort {:?}, expected name, size, modified or type", sort)),
+    };
+    let descending = match payload.get("order").and_then(|order| order.as_str()) {
+      None | Some("asc") => false,
+      Some("desc") => true,
+      Some(order) => return Err(format!("invalid order {:?}, expected asc or desc", order)),
+    };
+    let cursor = match payload.get("cursor").and_then(|cursor| cursor.as_str()) {
+      Some(cursor) => Some(Position::decode(cursor)?),
+      None => None,
+    };
+    let stream = payload.get("stream").and_then(|stream| stream.as_bool()).unwrap_or(false);
+    let chunk_size = match count(payload, "chunk_size")? {
+      Some(0) => return Err("chunk_size must be positive".to_owned()),
+      Some(chunk_size) => Some(chunk_size),
+      None if stream => Some(DEFAULT_CHUNK_SIZE),
+      None => None,
+    };
+
+    Ok(ListingOptions {
+      sort,
+      descending,
+      offset: count(payload, "offset")?.unwrap_or(0),
+      limit: count(payload, "limit")?,
+      cursor,
+      filters: strings(payload.get("filter"))
+        .iter()
+        .map(|filter| filter.to_lowercase())
+        .collect(),
+      hidden: payload.get("hidden").and_then(|hidden| hidden.as_bool()).unwrap_or(true),
+      chunk_size: if stream { chunk_size } else { None },
+    })
+  }
+
+  /// Whether a filename is listed, before the pagination
+  pub fn matches(&self, name: &str) -> bool {
+    if !self.hidden && name.starts_with('.') {
+      return false;
+    }
+    if self.filters.is_empty() {
+      return true;
+    }
+    let name = name.to_lowercase();
+    self.filters.iter().any(|filter| matches_pattern(filter, &name))
+  }
+
+  fn compare(&self, a: &Position, b: &Position) -> Ordering {
+    let ordering = match self.sort {
+      SortKey::Name => Ordering::Equal,
+      SortKey::Size => a.size.cmp(&b.size),
+      SortKey::Modified => a.modified.cmp(&b.modified),
+      SortKey::Type => b.is_dir.cmp(&a.is_dir).then_with(|| extension(&a.name).cmp(&extension(&b.name))),
+    };
+    // the names break the ties, so that a cursor designates a single place
+    let ordering = ordering
+      .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+      .then_with(|| a.name.cmp(&b.name));
+    if self.descending {
+      ordering.reverse()
+    } else {
+      ordering
+    }
+  }
+
+  /// Sort the entries and keep the requested ones
+  pub fn select<T>(&self, mut entries: Vec<(Position, T)>) -> Page<T> {
+    entries.sort_by(|a, b| self.compare(&a.0, &b.0));
+    let total = entries.len();
+
+    let after_cursor = match self.cursor {
+      Some(ref cursor) => entries
+        .iter()
+        .position(|entry| self.compare(&entry.0, cursor) == Ordering::Greater)
+        .unwrap_or(total),
+      None => 0,
+    };
+    let start = min(after_cursor + self.offset, total);
+    let end = match self.limit {
+      Some(limit) => min(start + limit, total),
+      None => total,
+    };
+    let next_cursor = if start < end && end < total {
+      Some(entries[end - 1].0.encode())
+    } else {
+      None
+    };
+
+    Page {
+      items: entries.into_iter().skip(start).take(end - start).map(|entry| entry.1).collect(),
+      total,
+      start,
+      next_cursor,
+    }
+  }
+}
+
+impl Position {
+  /// The metadata of the entry is only read when the sort needs it
+  pub fn read(entry: &fs::DirEntry, sort: SortKey) -> Self {
+    let name = entry.file_name().to_string_lossy().into_owned();
+    let is_dir = entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false);
+    let (size, modified) = match sort {
+      SortKey::Size | SortKey::Modified => match entry.metadata() {
+        Ok(metadata) => {
+          let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_secs() as i64 * 1000 + i64::from(since.subsec_millis()))
+            .unwrap_or(0);
+          (metadata.len(), modified)
+        }
+        Err(_) => (0, 0),
+      },
+      _ => (0, 0),
+    };
+    Position {
+      name,
+      is_dir,
+      size,
+      modified,
+    }
+  }
+
+  /// Cursor given to the client, which sends it back for the next page
+  fn encode(&self) -> String {
+    json!([self.name, self.is_dir, self.size, self.modified]).to_string()
+  }
+
+  fn decode(cursor: &str) -> Result<Self, String> {
+    let invalid = || format!("invalid cursor {:?}", cursor);
+    let value: Value = serde_json::from_str(cursor).map_err(|_| invalid())?;
+    Ok(Position {
+      name: value[0].as_str().ok_or_else(invalid)?.to_owned(),
+      is_dir: value[1].as_bool().ok_or_else(invalid)?,
+      size: value[2].as_u64().ok_or_else(invalid)?,
+      modified: value[3].as_i64().ok_or_else(invalid)?,
+    })
+  }
+}
+
+fn count(payload: &Value, key: &str) -> Result<Option<usize>, String> {
+  match payload.get(key) {
+    None | Some(&Value::Null) => Ok(None),
+    Some(value) => match value.as_u64() {
+      Some(count) => Ok(Some(count as usize)),
+      None => Err(format!("{} must be a positive integer", key)),
+    },
+  }
+}
+
+fn extension(name: &str) -> String {
+  Path::new(name)
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_lowercase())
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn position(name: &str, is_dir: bool, size: u64, modified: i64) -> (Position, String) {
+    let position = Position {
+      name: name.to_owned(),
+      is_dir,
+      size,
+      modified,
+    };
+    (position, name.to_owned())
+  }
+
+  fn listing() -> Vec<(Position, String)> {
+    vec![
+      position("b.mp4", false, 300, 2000),
+      position("Archive", true, 0, 5000),
+      position("a.mov", false, 100, 3000),
+      position("c.mp4", false, 200, 1000),
+      position("B.mp4", false, 300, 4000),
+    ]
+  }
+
+  fn options(payload: Value) -> ListingOptions {
+    ListingOptions::from_payload(&payload).unwrap()
+  }
+
+  #[test]
+  fn sort_orders() {
+    let page = options(json!({})).select(listing());
+    assert_eq!(page.items, vec!["a.mov", "Archive", "B.mp4", "b.mp4", "c.mp4"]);
+    let page = options(json!({ "order": "desc" })).select(listing());
+    assert_eq!(page.items, vec!["c.mp4", "b.mp4", "B.mp4", "Archive", "a.mov"]);
+    // the sizes are equal, the names break the tie
+    let page = options(json!({ "sort": "size" })).select(listing());
+    assert_eq!(page.items, vec!["Archive", "a.mov", "c.mp4", "B.mp4", "b.mp4"]);
+    let page = options(json!({ "sort": "modified", "order": "desc" })).select(listing());
+    assert_eq!(page.items, vec!["Archive", "B.mp4", "a.mov", "b.mp4", "c.mp4"]);
+    let page = options(json!({ "sort": "type" })).select(listing());
+    assert_eq!(page.items, vec!["Archive", "a.mov", "B.mp4", "b.mp4", "c.mp4"]);
+  }
+
+  #[test]
+  fn offset_and_limit() {
+    let page = options(json!({ "offset": 1, "limit": 2 })).select(listing());
+    assert_eq!(page.items, vec!["Archive", "B.mp4"]);
+    assert_eq!((page.total, page.start), (5, 1));
+    assert!(page.next_cursor.is_some());
+
+    let page = options(json!({ "offset": 3, "limit": 5 })).select(listing());
+    assert_eq!(page.items, vec!["b.mp4", "c.mp4"]);
+    assert_eq!(page.next_cursor, None);
+
+    let page = options(json!({ "offset": 10 })).select(listing());
+    assert!(page.items.is_empty());
+    assert_eq!((page.total, page.start), (5, 5));
+  }
+
+  #[test]
+  fn cursors() {
+    let first = options(json!({ "sort": "size", "order": "desc", "limit": 2 })).select(listing());
+    assert_eq!(first.items, vec!["b.mp4", "B.mp4"]);
+    let cursor = first.next_cursor.unwrap();
+
+    let second = options(json!({ "sort": "size", "order": "desc", "limit": 2, "cursor": cursor })).select(listing());
+    assert_eq!(second.items, vec!["c.mp4", "a.mov"]);
+    assert_eq!(second.start, 2);
+    let cursor = second.next_cursor.unwrap();
+
+    // the offset is counted from the cursor
+    let payload = json!({ "sort": "size", "order": "desc", "offset": 1, "cursor": cursor });
+    let last = options(payload).select(listing());
+    assert!(last.items.is_empty());
+    assert_eq!(last.start, 5);
+
+    // the entry of a cursor may have been removed since
+    let mut entries = listing();
+    entries.retain(|entry| entry.1 != "B.mp4");
+    let cursor = json!(["B.mp4", false, 300, 4000]).to_string();
+    let page = options(json!({ "sort": "size", "order": "desc", "cursor": cursor })).select(entries);
+    assert_eq!(page.items, vec!["c.mp4", "a.mov", "Archive"]);
+    assert_eq!(page.start, 1);
+  }
+
+  #[test]
+  fn malformed_cursors() {
+    for &cursor in &["", "not json", "[]", "[\"a.mp4\", false, -1, 0]", "{\"name\": \"a.mp4\"}", "[1, false, 0, 0]"] {
+      let error = ListingOptions::from_payload(&json!({ "cursor": cursor })).unwrap_err();
+      assert!(error.starts_with("invalid cursor"), "{}", error);
+    }
+    let position = listing().remove(0).0;
+    assert_eq!(Position::decode(&position.encode()), Ok(position));
+  }
+
+  #[test]
+  fn invalid_options() {
+    for payload in &[
+      json!({ "sort": "date" }),
+      json!({ "order": "up" }),
+      json!({ "offset": -1 }),
+      json!({ "limit": "10" }),
+      json!({ "stream": true, "chunk_size": 0 }),
+    ] {
+      assert!(ListingOptions::from_payload(payload).is_err(), "{}", payload);
+    }
+    let streamed = options(json!({ "stream": true }));
+    assert_eq!(streamed.chunk_size, Some(DEFAULT_CHUNK_SIZE));
+    assert_eq!(options(json!({ "chunk_size": 10 })).chunk_size, None);
+  }
+
+  #[test]
+  fn filters() {
+    let options = options(json!({ "filter": ["*.MP4", "a?chive"], "hidden": false }));
+    assert!(options.matches("clip.mp4"));
+    assert!(options.matches("Archive"));
+    assert!(!options.matches("clip.mov"));
+    assert!(!options.matches(".clip.mp4"));
+  }
+}
@@ -0,0 +1,217 @@
+use clap::ArgMatches;
+use config;
+use encode_stats;
+use encoder_log::{self, Entry, ParseMode};
+use entry_filter::EntryFilter;
+use serde_json::{self, Value};
+use std::io::{self, BufWriter, ErrorKind, Write};
+use timezone::LogTimezone;
+
+pub const FORMATS: [&str; 3] = ["json", "ndjson", "csv"];
+
+/// Columns of the CSV output, as JSON pointers in the entry events
+const ENTRY_COLUMNS: [&str; 27] = [
+  "/id",
+  "/encoder",
+  "/date_time",
+  "/date_estimated",
+  "/status",
+  "/source_filename",
+  "/source_kind",
+  "/output_filename",
+  "/preset",
+  "/video/width",
+  "/video/height",
+  "/video/pixel_aspect_ratio",
+  "/video/frame_rate",
+  "/video/scan_type",
+  "/video/timecode",
+  "/video/duration",
+  "/audio/codec",
+  "/audio/bitrate",
+  "/audio/sample_rate",
+  "/audio/channel_layout",
+  "/audio/bit_depth",
+  "/bitrate/mode",
+  "/bitrate/passes",
+  "/bitrate/target",
+  "/bitrate/max",
+  "/encoding_time",
+  "/messages",
+];
+
+/// Columns of the CSV output of the statistics
+const STATS_COLUMNS: [&str; 11] = [
+  "/preset",
+  "/day",
+  "/encodes",
+  "/warnings",
+  "/failures",
+  "/interrupted",
+  "/media_duration",
+  "/encoding_time",
+  "/realtime_factor",
+  "/failure_rate",
+  "/warning_rate",
+];
+
+/// `skia parse <logfile>`: print the entries of a log without connecting to
+/// the backend, the parse errors are logged as the watcher does.
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+  let entries: Vec<Value> = read_entries(matches)?
+    .into_iter()
+    .map(|entry| entry.into())
+    .collect();
+  write(matches.value_of("format"), &ENTRY_COLUMNS, &entries)
+}
+
+/// `skia stats <logfile>`: print the statistics of the encodes of a log per
+/// preset and per day.
+pub fn run_stats(matches: &ArgMatches) -> Result<(), String> {
+  let entries = read_entries(matches)?;
+  let stats: Vec<Value> = encode_stats::aggregate(&entries)
+    .into_iter()
+    .map(|stats| stats.into())
+    .collect();
+  write(matches.value_of("format"), &STATS_COLUMNS, &stats)
+}
+
+/// Parse the whole log given on the command line and keep the entries
+/// selected by the filters.
+fn read_entries(matches: &ArgMatches) -> Result<Vec<Entry>, String> {
+  let filename = matches.value_of("logfile").ok_or("no log file given")?;
+  let parser_name = matches.value_of("parser").unwrap_or("adobe_media_encoder");
+  let date_format = config::get_adobe_media_encoder_date_format(matches.value_of("ame_date_format"));
+  let log_timezone = config::get_log_timezone(matches.value_of("log_timezone"));
+  let timezone = LogTimezone::from(&log_timezone)
+    .ok_or_else(|| format!("invalid log timezone {:?}", log_timezone))?;
+
+  let presets: Vec<&str> = matches.values_of("preset").map(|presets| presets.collect()).unwrap_or_default();
+  let outputs: Vec<&str> = matches.values_of("output").map(|outputs| outputs.collect()).unwrap_or_default();
+  let filter = EntryFilter::new(matches.value_of("from"), matches.value_of("to"), &presets, &outputs, timezone)?;
+
+  let parser = encoder_log::parse_file(parser_name, filename, ParseMode::Lenient, &date_format, timezone)?;
+  info!(
+    "{} entries and {} parse errors read in {}",
+    parser.entries().len(),
+    parser.diagnostics().len(),
+    filename
+  );
+
+  Ok(parser
+    .entries()
+    .iter()
+    .filter(|entry| filter.matches(entry))
+    .cloned()
+    .collect())
+}
+
+fn write(format: Option<&str>, columns: &[&str], rows: &[Value]) -> Result<(), String> {
+  let stdout = io::stdout();
+  let mut output = BufWriter::new(stdout.lock());
+  match format.unwrap_or("json") {
+    "ndjson" => write_ndjson(&mut output, rows),
+    "csv" => write_csv(&mut output, columns, rows),
+    _ => write_json(&mut output, rows),
+  }
+  .and_then(|_| output.flush())
+  .or_else(|error| match error.kind() {
+    // the output piped to a command like `head` which exited
+    ErrorKind::BrokenPipe => Ok(()),
+    _ => Err(error.to_string()),
+  })
+}
+
+fn write_json<W: Write>(output: &mut W, rows: &[Value]) -> io::Result<()> {
+  serde_json::to_writer_pretty(&mut *output, rows)?;
+  writeln!(output)
+}
+
+fn write_ndjson<W: Write>(output: &mut W, rows: &[Value]) -> io::Result<()> {
+  for row in rows {
+    serde_json::to_writer(&mut *output, row)?;
+    writeln!(output)?;
+  }
+  Ok(())
+}
+
+fn write_csv<W: Write>(output: &mut W, columns: &[&str], rows: &[Value]) -> io::Result<()> {
+  let header: Vec<String> = columns
+    .iter()
+    .map(|column| column[1..].replace("/", "_"))
+    .collect();
+  writeln!(output, "{}", header.join(","))?;
+
+  for row in rows {
+    let fields: Vec<String> = columns
+      .iter()
+      .map(|column| csv_field(row.pointer(column)))
+      .collect();
+    writeln!(output, "{}", fields.join(","))?;
+  }
+  Ok(())
+}
+
+/// Quote the fields with a separator, a quote or a line ending
+fn csv_field(value: Option<&Value>) -> String {
+  let text = match value {
+    None | Some(Value::Null) => return String::new(),
+    Some(Value::String(text)) => text.clone(),
+    Some(Value::Array(values)) => values
+      .iter()
+      .map(|value| match *value {
+        Value::String(ref text) => text.clone(),
+        ref value => value.to_string(),
+      })
+      .collect::<Vec<String>>()
+      .join("\n"),
+    Some(value) => value.to_string(),
+  };
+
+  if text.contains(&[',', '"', '\n', '\r'][..]) {
+    format!("\"{}\"", text.replace("\"", "\"\""))
+  } else {
+    text
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn entries_csv() {
+    let row = json!({
+      "id": "7",
+      "encoder": "adobe_media_encoder",
+      "date_time": "2018-02-16T22:12:01+01:00",
+      "date_estimated": false,
+      "status": "warning",
+      "source_filename": "D:/Rushes/interview, take 2.mov",
+      "output_filename": "D:/Renders/interview.mp4",
+      "preset": "H.264 1080p25",
+      "video": { "width": 1920, "height": 1080, "frame_rate": 25.0 },
+      "audio": { "codec": "pcm", "sample_rate": 48000, "channel_layout": "stereo", "bit_depth": 24 },
+      "encoding_time": 42,
+      "messages": ["audio \"A1\" resampled", "timecode missing"],
+    });
+    let mut output = vec![];
+    write_csv(&mut output, &ENTRY_COLUMNS, &[row]).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let mut lines = output.splitn(2, '\n');
+
+    assert_eq!(
+      lines.next().unwrap(),
+      "id,encoder,date_time,date_estimated,status,source_filename,source_kind,output_filename,preset,\
+       video_width,video_height,video_pixel_aspect_ratio,video_frame_rate,video_scan_type,video_timecode,video_duration,\
+       audio_codec,audio_bitrate,audio_sample_rate,audio_channel_layout,audio_bit_depth,\
+       bitrate_mode,bitrate_passes,bitrate_target,bitrate_max,encoding_time,messages"
+    );
+    assert_eq!(
+      lines.next().unwrap(),
+      "7,adobe_media_encoder,2018-02-16T22:12:01+01:00,false,warning,\"D:/Rushes/interview, take 2.mov\",,\
+       D:/Renders/interview.mp4,H.264 1080p25,1920,1080,,25.0,,,,pcm,,48000,stereo,24,,,,,42,\
+       \"audio \"\"A1\"\" resampled\ntimecode missing\"\n"
+    );
+  }
+}
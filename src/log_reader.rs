@@ -0,0 +1,126 @@
+use decoder::Decoder;
+use std::cmp::min;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+/// Number of bytes at the beginning of the file used to detect a replacement
+const FINGERPRINT_SIZE: usize = 256;
+
+/// Tail reader for encoder logs, which keeps the position of the last read
+/// and only decodes what has been appended since.
+#[derive(Debug)]
+pub struct LogReader {
+  pub filename: String,
+  offset: u64,
+  decoder: Decoder,
+  fingerprint: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum LogUpdate {
+  /// Text appended since the previous read
+  Appended(String),
+  /// The file has been truncated or replaced, the text is its whole content
+  Reset(String),
+}
+
+impl LogReader {
+  pub fn new(filename: &str) -> Self {
+    LogReader {
+      filename: filename.to_owned(),
+      offset: 0,
+      decoder: Decoder::new(),
+      fingerprint: vec![],
+    }
+  }
+
+  pub fn read(&mut self) -> Result<LogUpdate, String> {
+    let mut file = File::open(&self.filename).map_err(|e| e.to_string())?;
+    let length = file.metadata().map_err(|e| e.to_string())?.len();
+
+    let mut fingerprint = vec![0; min(length, FINGERPRINT_SIZE as u64) as usize];
+    file.read_exact(&mut fingerprint).map_err(|e| e.to_string())?;
+
+    let common = min(fingerprint.len(), self.fingerprint.len());
+    let reset = length < self.offset || fingerprint[..common] != self.fingerprint[..common];
+    if reset {
+      info!("log file {} has been truncated or replaced", self.filename);
+      self.offset = 0;
+      self.decoder = Decoder::new();
+    }
+    self.fingerprint = fingerprint;
+
+    file
+      .seek(SeekFrom::Start(self.offset))
+      .map_err(|e| e.to_string())?;
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    self.offset += bytes.len() as u64;
+
+    let replacements = self.decoder.replacements;
+    let text = self.decoder.decode(&bytes);
+    if self.decoder.replacements > replacements {
+      warn!(
+        "{} invalid sequences replaced while decoding {}",
+        self.decoder.replacements - replacements,
+        self.filename
+      );
+    }
+
+    if reset {
+      Ok(LogUpdate::Reset(text))
+    } else {
+      Ok(LogUpdate::Appended(text))
+    }
+  }
+
+  /// Text of the incomplete sequence left at the end of a file which is read
+  /// as a whole, and will not be appended to
+  pub fn finish(&mut self) -> String {
+    let text = self.decoder.finish();
+    if !text.is_empty() {
+      warn!("incomplete sequence replaced at the end of {}", self.filename);
+    }
+    text
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+  use std::fs::{self, OpenOptions};
+
+  #[test]
+  fn appended_and_replaced() {
+    let path = env::temp_dir().join(format!("skia_log_reader_{}.txt", ::std::process::id()));
+    let filename = path.to_string_lossy().into_owned();
+    fs::write(&path, "first line\n").unwrap();
+
+    let mut reader = LogReader::new(&filename);
+    match reader.read().unwrap() {
+      LogUpdate::Appended(text) => assert_eq!(text, "first line\n"),
+      update => panic!("unexpected {:?}", update),
+    }
+
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"second ").unwrap();
+    match reader.read().unwrap() {
+      LogUpdate::Appended(text) => assert_eq!(text, "second "),
+      update => panic!("unexpected {:?}", update),
+    }
+    match reader.read().unwrap() {
+      LogUpdate::Appended(text) => assert_eq!(text, ""),
+      update => panic!("unexpected {:?}", update),
+    }
+
+    fs::write(&path, "new log\n").unwrap();
+    let update = reader.read();
+    fs::remove_file(&path).unwrap();
+    match update.unwrap() {
+      LogUpdate::Reset(text) => assert_eq!(text, "new log\n"),
+      update => panic!("unexpected {:?}", update),
+    }
+  }
+}
@@ -0,0 +1,237 @@
+use encoder_log::{normalize_path, Entry};
+use serde_json;
+use std::collections::HashSet;
+use std::fs;
+use timezone::LogTimezone;
+
+/// Encoder log watched by the agent, with the settings of the workstation
+/// which writes it. They are listed in a JSON file like
+///
+/// ```json
+/// [
+///   {
+///     "label": "node-1 AME 2018",
+///     "parser": "adobe_media_encoder",
+///     "filename": "//node-1/logs/AMEEncodingLog.txt",
+///     "date_format": "us",
+///     "timezone": "+02:00",
+///     "mounted_path": "H:/",
+///     "root_path": "/mnt/node-1/",
+///     "polling": true
+///   }
+/// ]
+/// ```
+///
+/// where only `label` and `filename` are required, the other settings
+/// default to the ones of the agent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogSource {
+  /// Name of the log in the events, unique among the watched logs
+  pub label: String,
+  #[serde(default)]
+  pub parser: String,
+  pub filename: String,
+  #[serde(default)]
+  pub date_format: String,
+  #[serde(default)]
+  pub timezone: String,
+  /// Prefix of the output paths written in the log
+  #[serde(default)]
+  pub mounted_path: String,
+  /// Local path of `mounted_path`, the outputs are verified under it
+  #[serde(default)]
+  pub root_path: String,
+  /// Poll the log instead of waiting for filesystem notifications, for the
+  /// network mounts which do not deliver them
+  #[serde(default)]
+  pub polling: bool,
+}
+
+/// Settings of the agent, used by the logs which do not set their own
+#[derive(Debug)]
+pub struct Defaults {
+  pub parser: String,
+  pub date_format: String,
+  pub timezone: String,
+  pub mounted_path: String,
+  pub root_path: String,
+}
+
+impl LogSource {
+  /// Timezone of the log, local time when it is invalid
+  pub fn log_timezone(&self) -> LogTimezone {
+    LogTimezone::from(&self.timezone).unwrap_or_else(|| {
+      error!("invalid timezone {:?} of log {}, local time is used", self.timezone, self.label);
+      LogTimezone::Local
+    })
+  }
+
+  fn with_defaults(mut self, defaults: &Defaults) -> Self {
+    if self.parser.is_empty() {
+      self.parser = defaults.parser.clone();
+    }
+    if self.date_format.is_empty() {
+      self.date_format = defaults.date_format.clone();
+    }
+    if self.timezone.is_empty() {
+      self.timezone = defaults.timezone.clone();
+    }
+    if self.mounted_path.is_empty() {
+      self.mounted_path = defaults.mounted_path.clone();
+    }
+    if self.root_path.is_empty() {
+      self.root_path = defaults.root_path.clone();
+    }
+    self
+  }
+
+  /// Map an output path written in the log to the local filesystem, the
+  /// paths outside of `mounted_path` are kept as they are
+  pub fn map_path(&self, path: &str) -> String {
+    let mounted_path = normalize_path(&self.mounted_path);
+    let mounted_path = mounted_path.trim_end_matches('/');
+    let normalized = normalize_path(path);
+    if mounted_path.is_empty() || normalized.len() < mounted_path.len() || !normalized.is_char_boundary(mounted_path.len()) {
+      return path.to_owned();
+    }
+    // Windows paths are case insensitive
+    let (prefix, relative) = normalized.split_at(mounted_path.len());
+    if prefix.to_lowercase() != mounted_path.to_lowercase() || !(relative.is_empty() || relative.starts_with('/')) {
+      return path.to_owned();
+    }
+    format!("{}{}", self.root_path.trim_end_matches(&['/', '\\'][..]), relative)
+  }
+
+  /// The entry with its output mapped to the local filesystem, as it is
+  /// sent to the backend and selected by the filters
+  pub fn map_entry(&self, entry: &Entry) -> Entry {
+    let mut entry = entry.clone();
+    entry.output_filename = entry.output_filename.map(|output_filename| self.map_path(&output_filename));
+    entry
+  }
+}
+
+/// Read the list of watched logs, completed with the settings of the agent
+pub fn load(filename: &str, defaults: &Defaults) -> Result<Vec<LogSource>, String> {
+  let content = fs::read_to_string(filename).map_err(|e| format!("unable to read {}: {}", filename, e))?;
+  let sources: Vec<LogSource> =
+    serde_json::from_str(&content).map_err(|e| format!("unable to parse {}: {}", filename, e))?;
+
+  let mut labels = HashSet::new();
+  for source in &sources {
+    if !labels.insert(source.label.clone()) {
+      return Err(format!("log label {:?} is used more than once in {}", source.label, filename));
+    }
+  }
+  Ok(sources.into_iter().map(|source| source.with_defaults(defaults)).collect())
+}
+
+/// One log per parser name, with the filenames configured for each parser.
+/// The label of a log is the name of its parser.
+pub fn from_parsers<F>(parsers: &str, defaults: &Defaults, filename: F) -> Vec<LogSource>
+where
+  F: Fn(&str) -> String,
+{
+  parsers
+    .split(',')
+    .map(|name| name.trim())
+    .filter(|name| !name.is_empty())
+    .map(|name| {
+      LogSource {
+        label: name.to_owned(),
+        parser: name.to_owned(),
+        filename: filename(name),
+        date_format: String::new(),
+        timezone: String::new(),
+        mounted_path: String::new(),
+        root_path: String::new(),
+        polling: false,
+      }
+      .with_defaults(defaults)
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::FixedOffset;
+  use std::env;
+  use std::process;
+
+  fn defaults() -> Defaults {
+    Defaults {
+      parser: "adobe_media_encoder".to_owned(),
+      date_format: "us".to_owned(),
+      timezone: "local".to_owned(),
+      mounted_path: "H:/".to_owned(),
+      root_path: "/mnt/node-1/".to_owned(),
+    }
+  }
+
+  fn sources(content: &str) -> Result<Vec<LogSource>, String> {
+    let path = env::temp_dir().join(format!("skia_log_sources_{}.json", process::id()));
+    fs::write(&path, content).unwrap();
+    let sources = load(&path.to_string_lossy(), &defaults());
+    fs::remove_file(&path).unwrap();
+    sources
+  }
+
+  #[test]
+  fn completed_with_defaults() {
+    let sources = sources(
+      r#"[
+        { "label": "node-1", "filename": "//node-1/logs/AMEEncodingLog.txt" },
+        { "label": "node-2", "parser": "ffmpeg", "filename": "/logs/ffmpeg.log", "timezone": "+02:00",
+          "mounted_path": "D:\\Renders", "root_path": "/mnt/node-2", "polling": true }
+      ]"#,
+    )
+    .unwrap();
+
+    assert_eq!(sources[0].parser, "adobe_media_encoder");
+    assert_eq!(sources[0].timezone, "local");
+    assert_eq!(sources[0].root_path, "/mnt/node-1/");
+    assert!(!sources[0].polling);
+    assert_eq!(sources[1].parser, "ffmpeg");
+    assert_eq!(sources[1].date_format, "us");
+    assert_eq!(sources[1].log_timezone(), LogTimezone::Fixed(FixedOffset::east_opt(2 * 3600).unwrap()));
+    assert!(sources[1].polling);
+  }
+
+  #[test]
+  fn mapped_paths() {
+    let sources = sources(
+      r#"[
+        { "label": "node-1", "filename": "node-1.txt" },
+        { "label": "node-2", "filename": "node-2.txt", "mounted_path": "D:\\Renders", "root_path": "/mnt/node-2" }
+      ]"#,
+    )
+    .unwrap();
+
+    // the trailing separators of the prefixes are optional
+    assert_eq!(sources[0].map_path("H:/2018/clip.mp4"), "/mnt/node-1/2018/clip.mp4");
+    assert_eq!(sources[0].map_path("h:\\2018\\clip.mp4"), "/mnt/node-1/2018/clip.mp4");
+    assert_eq!(sources[1].map_path("D:/Renders/clip.mp4"), "/mnt/node-2/clip.mp4");
+    assert_eq!(sources[1].map_path("D:/Renders"), "/mnt/node-2");
+
+    // the paths outside of the mounted path are not mapped
+    assert_eq!(sources[1].map_path("D:/Renders 2/clip.mp4"), "D:/Renders 2/clip.mp4");
+    assert_eq!(sources[1].map_path("E:/Renders/clip.mp4"), "E:/Renders/clip.mp4");
+    assert_eq!(sources[1].map_path("//nas/D:/Renders/clip.mp4"), "//nas/D:/Renders/clip.mp4");
+
+    let mut unmapped = sources[0].clone();
+    unmapped.mounted_path = String::new();
+    assert_eq!(unmapped.map_path("H:/2018/clip.mp4"), "H:/2018/clip.mp4");
+  }
+
+  #[test]
+  fn invalid_files() {
+    assert!(sources("{").unwrap_err().starts_with("unable to parse"));
+    assert!(sources(r#"[{ "label": "node-1" }]"#).unwrap_err().contains("missing field `filename`"));
+    let duplicated = sources(r#"[{ "label": "node-1", "filename": "a.txt" }, { "label": "node-1", "filename": "b.txt" }]"#);
+    assert!(duplicated.unwrap_err().contains("\"node-1\" is used more than once"));
+
+    let missing = load("/nonexistent/skia_log_sources.json", &defaults());
+    assert!(missing.unwrap_err().starts_with("unable to read /nonexistent/skia_log_sources.json"));
+  }
+}
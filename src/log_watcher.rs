@@ -0,0 +1,200 @@
+use notify::{self, Op, RawEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Intervals of the watcher, see `config::get_log_polling_interval`
+#[derive(Debug, Clone, Copy)]
+pub struct Intervals {
+  /// Logs are read at this interval, whether their changes are notified or not
+  pub polling: Duration,
+  /// A log which could not be read is read again after this delay
+  pub read_error: Duration,
+  /// A log is read once it has not changed for this delay
+  pub debounce: Duration,
+}
+
+/// Parse a configured interval. A value below `minimum`, like `0`, would
+/// make the agent read the logs without pause, the minimum is used instead,
+/// and the default when the value is not a number.
+pub fn parse_interval(name: &str, value: &str, default: u64, minimum: u64) -> u64 {
+  match value.trim().parse::<u64>() {
+    Ok(interval) if interval < minimum => {
+      warn!("{} {} is too short, {} is used", name, interval, minimum);
+      minimum
+    }
+    Ok(interval) => interval,
+    Err(_) => {
+      warn!("invalid {} {:?}, {} is used", name, value, default);
+      default
+    }
+  }
+}
+
+/// Change notified by the filesystem
+#[derive(Debug)]
+pub enum Change {
+  Path(PathBuf),
+  /// Notifications have been lost, every log may have changed
+  Unknown,
+}
+
+/// Filesystem notifications on the watched logs. The directory of a log is
+/// watched rather than the log itself, so that a log which is replaced or
+/// created later is still followed.
+pub struct LogWatcher {
+  watcher: RecommendedWatcher,
+  directories: HashSet<PathBuf>,
+}
+
+impl LogWatcher {
+  /// The changes are sent wrapped by `wrap`, so that they share the channel
+  /// of the other events of the caller.
+  pub fn new<T: Send + 'static>(sender: Sender<T>, wrap: fn(Change) -> T) -> Result<Self, String> {
+    let (raw_sender, raw_events) = channel();
+    let watcher = notify::raw_watcher(raw_sender).map_err(|e| e.to_string())?;
+
+    thread::spawn(move || {
+      for event in raw_events {
+        let change = match event {
+          RawEvent {
+            path: Some(path),
+            op: Ok(op),
+            ..
+          } if !op.contains(Op::RESCAN) => Change::Path(path),
+          _ => Change::Unknown,
+        };
+        if sender.send(wrap(change)).is_err() {
+          break;
+        }
+      }
+    });
+
+    Ok(LogWatcher {
+      watcher,
+      directories: HashSet::new(),
+    })
+  }
+
+  /// Watch the directory of a log, returns the path of the log in the
+  /// notifications.
+  pub fn watch(&mut self, filename: &str) -> Result<PathBuf, String> {
+    let path = Path::new(filename);
+    let name = path
+      .file_name()
+      .ok_or_else(|| format!("{} is not a file", filename))?;
+    let directory = match path.parent() {
+      Some(directory) if directory != Path::new("") => directory,
+      _ => Path::new("."),
+    };
+    let directory = fs::canonicalize(directory).map_err(|e| format!("unable to access {}: {}", directory.display(), e))?;
+
+    if !self.directories.contains(&directory) {
+      self
+        .watcher
+        .watch(&directory, RecursiveMode::NonRecursive)
+        .map_err(|e| format!("unable to watch {}: {}", directory.display(), e))?;
+      self.directories.insert(directory.clone());
+    }
+    Ok(directory.join(name))
+  }
+}
+
+/// When a log has to be read: on its changes when the filesystem notifies
+/// them, and at the polling interval in any case, since notifications may be
+/// lost or never delivered for some mounts.
+#[derive(Debug)]
+pub struct ReadSchedule {
+  /// Next read which does not wait for a change
+  next_read: Instant,
+  /// First and last changes not read yet
+  changes: Option<(Instant, Instant)>,
+}
+
+impl Default for ReadSchedule {
+  /// The log is read right away
+  fn default() -> Self {
+    ReadSchedule {
+      next_read: Instant::now(),
+      changes: None,
+    }
+  }
+}
+
+impl ReadSchedule {
+  pub fn new() -> Self {
+    ReadSchedule::default()
+  }
+
+  pub fn changed(&mut self, now: Instant) {
+    self.changes = match self.changes {
+      Some((first, _)) => Some((first, now)),
+      None => Some((now, now)),
+    };
+  }
+
+  /// Schedule the next read once the log has been read
+  pub fn read(&mut self, now: Instant, success: bool, intervals: &Intervals) {
+    self.changes = None;
+    self.next_read = if success {
+      now + intervals.polling
+    } else {
+      now + intervals.read_error
+    };
+  }
+
+  pub fn is_due(&self, now: Instant, intervals: &Intervals) -> bool {
+    self.next_wakeup(intervals) <= now
+  }
+
+  /// Time of the next read, a burst of changes is read when the log stops
+  /// changing or at the latest a polling interval after its first change.
+  pub fn next_wakeup(&self, intervals: &Intervals) -> Instant {
+    match self.changes {
+      Some((first, last)) => self.next_read.min((last + intervals.debounce).min(first + intervals.polling)),
+      None => self.next_read,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const INTERVALS: Intervals = Intervals {
+    polling: Duration::from_secs(10),
+    read_error: Duration::from_secs(30),
+    debounce: Duration::from_millis(500),
+  };
+
+  #[test]
+  fn configured_intervals() {
+    assert_eq!(parse_interval("polling interval", "60", 10, 1), 60);
+    assert_eq!(parse_interval("polling interval", " 5 ", 10, 1), 5);
+    assert_eq!(parse_interval("polling interval", "0", 10, 1), 1);
+    assert_eq!(parse_interval("debounce delay", "10", 500, 50), 50);
+    assert_eq!(parse_interval("polling interval", "", 10, 1), 10);
+    assert_eq!(parse_interval("polling interval", "-1", 10, 1), 10);
+    assert_eq!(parse_interval("polling interval", "1.5", 10, 1), 10);
+  }
+
+  #[test]
+  fn notified_logs_are_still_polled() {
+    let mut schedule = ReadSchedule::new();
+    let now = Instant::now();
+    assert!(schedule.is_due(now, &INTERVALS));
+
+    schedule.read(now, true, &INTERVALS);
+    assert!(!schedule.is_due(now + Duration::from_secs(9), &INTERVALS));
+    assert_eq!(schedule.next_wakeup(&INTERVALS), now + INTERVALS.polling);
+
+    schedule.changed(now + Duration::from_secs(1));
+    assert_eq!(schedule.next_wakeup(&INTERVALS), now + Duration::from_millis(1500));
+
+    schedule.read(now + Duration::from_secs(2), false, &INTERVALS);
+    assert_eq!(schedule.next_wakeup(&INTERVALS), now + Duration::from_secs(32));
+  }
+}
@@ -1,8 +1,12 @@
 extern crate chrono;
 extern crate clap;
 extern crate env_logger;
+#[cfg(unix)]
+extern crate libc;
 #[macro_use]
 extern crate log;
+extern crate md5;
+extern crate notify;
 extern crate phoenix;
 extern crate reqwest;
 extern crate serde;
@@ -14,17 +18,51 @@ extern crate tokio_core;
 extern crate websocket;
 
 mod adobe_media_encoder_log;
+mod ame_preset;
 mod browser;
 mod config;
+mod decoder;
+mod encode_monitor;
+mod encode_stats;
+mod encoder_log;
+mod entry_filter;
+mod ffmpeg_log;
+mod handbrake_log;
+mod listing;
+mod log_dump;
+mod log_reader;
+mod log_source;
+mod log_watcher;
+mod mp4_probe;
+mod path_resolver;
+mod reported_entries;
+mod resolve_log;
 mod socket;
+mod timezone;
 mod uploader;
+mod verifier;
+mod volume;
 
-use adobe_media_encoder_log::AdobeMediaEncoderLog;
-use chrono::NaiveDateTime;
-use clap::{Arg, App};
+use ame_preset::PresetCatalog;
+use chrono::{DateTime, Utc};
+use clap::{Arg, App, SubCommand};
+use encode_monitor::EncodeMonitor;
+use encoder_log::{EncoderLogParser, Entry, ParseMode, QueueState, Status};
+use entry_filter::EntryFilter;
+use log_reader::{LogReader, LogUpdate};
+use log_source::LogSource;
+use log_watcher::{Change, LogWatcher, ReadSchedule};
+use path_resolver::SymlinkPolicy;
+use reported_entries::ReportedEntries;
 use phoenix::{Event, PhoenixEvent};
+use phoenix::message::Message;
 use serde_json::Value;
-use std::{thread, time};
+use std::{process, thread, time};
+use std::path::PathBuf;
+use std::sync::mpsc as std_mpsc;
+use timezone::LogTimezone;
+use verifier::{Failure, Verification, Verifier};
+use volume::Volumes;
 use websocket::futures::sync::mpsc;
 use websocket::futures::Stream;
 use tokio_core::reactor::Core;
@@ -69,6 +107,95 @@ fn main() {
       .long("ame-log-filename")
       .help("Configure Adobe Media Encoder log filename.")
       .takes_value(true))
+    .arg(Arg::with_name("ame_date_format")
+      .short("d")
+      .long("ame-date-format")
+      .help("Configure the date format of the Adobe Media Encoder log (auto, us, european or a chrono format).")
+      .takes_value(true))
+    .arg(Arg::with_name("ame_preset_directory")
+      .long("ame-preset-directory")
+      .help("Configure the directory of the Adobe Media Encoder presets (.epr) published to the backend.")
+      .takes_value(true))
+    .arg(Arg::with_name("parsers")
+      .long("parsers")
+      .help("Configure the encoder log parsers to run, separated by commas (adobe_media_encoder, ffmpeg, ffmpeg_progress, handbrake, davinci_resolve).")
+      .takes_value(true))
+    .arg(Arg::with_name("watched_logs_filename")
+      .long("watched-logs-filename")
+      .help("Configure the JSON file listing the watched logs, each with its label, parser, filename, date format, timezone and path mapping.")
+      .takes_value(true))
+    .arg(Arg::with_name("ffmpeg_log_filename")
+      .long("ffmpeg-log-filename")
+      .help("Configure FFmpeg log filename (stderr or -report output).")
+      .takes_value(true))
+    .arg(Arg::with_name("ffmpeg_progress_filename")
+      .long("ffmpeg-progress-filename")
+      .help("Configure FFmpeg -progress output filename.")
+      .takes_value(true))
+    .arg(Arg::with_name("handbrake_log_filename")
+      .long("handbrake-log-filename")
+      .help("Configure HandBrake activity log filename.")
+      .takes_value(true))
+    .arg(Arg::with_name("resolve_log_filename")
+      .long("resolve-log-filename")
+      .help("Configure DaVinci Resolve render log filename.")
+      .takes_value(true))
+    .arg(Arg::with_name("log_timezone")
+      .long("log-timezone")
+      .help("Configure the timezone of the encoder logs (local, utc or an offset like +02:00).")
+      .takes_value(true))
+    .arg(Arg::with_name("reported_entries_filename")
+      .long("reported-entries-filename")
+      .help("Configure the file keeping the encodes already reported to the backend.")
+      .takes_value(true))
+    .arg(Arg::with_name("verify_outputs")
+      .long("verify-outputs")
+      .help("Check the outputs on disk before announcing the encodes (true or false).")
+      .takes_value(true))
+    .arg(Arg::with_name("verification_delay")
+      .long("verification-delay")
+      .help("Configure the milliseconds during which the size of an output must not change.")
+      .takes_value(true))
+    .arg(Arg::with_name("verification_timeout")
+      .long("verification-timeout")
+      .help("Configure the seconds after which an output still missing or being written fails its verification.")
+      .takes_value(true))
+    .arg(Arg::with_name("encode_slow_factor")
+      .long("encode-slow-factor")
+      .help("Configure how many times its usual encoding time an encode takes to be reported as slow.")
+      .takes_value(true))
+    .arg(Arg::with_name("encode_slow_min_samples")
+      .long("encode-slow-min-samples")
+      .help("Configure the number of encodes of a preset needed to know its usual encoding time.")
+      .takes_value(true))
+    .arg(Arg::with_name("queue_stalled_timeout")
+      .long("queue-stalled-timeout")
+      .help("Configure the seconds without progress after which an encoding queue is stalled.")
+      .takes_value(true))
+    .arg(Arg::with_name("log_notifications")
+      .long("log-notifications")
+      .help("Follow the encoder logs with filesystem notifications instead of polling them (true or false).")
+      .takes_value(true))
+    .arg(Arg::with_name("log_polling_interval")
+      .long("log-polling-interval")
+      .help("Configure the seconds between two reads of an encoder log, also read on its notifications.")
+      .takes_value(true))
+    .arg(Arg::with_name("log_read_error_delay")
+      .long("log-read-error-delay")
+      .help("Configure the seconds before reading again an encoder log which could not be read.")
+      .takes_value(true))
+    .arg(Arg::with_name("log_debounce_delay")
+      .long("log-debounce-delay")
+      .help("Configure the milliseconds without change after which a notified encoder log is read.")
+      .takes_value(true))
+    .arg(Arg::with_name("browsing_symlinks")
+      .long("browsing-symlinks")
+      .help("Configure the symbolic links followed while browsing (within_root, follow or deny).")
+      .takes_value(true))
+    .arg(Arg::with_name("browsing_volumes_filename")
+      .long("browsing-volumes-filename")
+      .help("Configure the JSON file of the volumes offered to the browser, instead of the browsing root.")
+      .takes_value(true))
     .arg(Arg::with_name("mounted_browsing_path")
       .short("m")
       .long("mounted-browsing-path")
@@ -83,6 +210,10 @@ fn main() {
       .short("v")
       .multiple(true)
       .help("Sets the level of verbosity (warn, info, debug, trace)"))
+    .subcommand(log_file_args(SubCommand::with_name("parse")
+      .about("Print the entries of an encoder log, without connecting to the backend")))
+    .subcommand(log_file_args(SubCommand::with_name("stats")
+      .about("Print the statistics of the encodes of an encoder log per preset and per day")))
     .get_matches();
 
   let log_level =
@@ -98,7 +229,44 @@ fn main() {
  
   env_logger::Builder::from_env(env).init();
 
+  let command =
+    match matches.subcommand() {
+      ("parse", Some(matches)) => Some(log_dump::run(matches)),
+      ("stats", Some(matches)) => Some(log_dump::run_stats(matches)),
+      _ => None,
+    };
+  if let Some(result) = command {
+    if let Err(msg) = result {
+      error!("{}", msg);
+      process::exit(1);
+    }
+    return;
+  }
+
   let root_path_browsing = config::get_root_path_browsing(matches.value_of("root_path_browsing"));
+  let browsing_symlinks = config::get_browsing_symlinks(matches.value_of("browsing_symlinks"));
+  let symlink_policy =
+    match SymlinkPolicy::from(&browsing_symlinks) {
+      Some(symlink_policy) => symlink_policy,
+      None => {
+        error!("invalid browsing symlinks policy {:?}, within_root is used", browsing_symlinks);
+        SymlinkPolicy::WithinRoot
+      }
+    };
+  let volumes_filename = config::get_browsing_volumes_filename(matches.value_of("browsing_volumes_filename"));
+  let volumes =
+    if volumes_filename.is_empty() {
+      let mounted_path = config::get_mounted_name_path_browsing(matches.value_of("mounted_browsing_path"));
+      Volumes::single(&root_path_browsing, &mounted_path, symlink_policy)
+    } else {
+      match Volumes::load(&volumes_filename, symlink_policy) {
+        Ok(volumes) => volumes,
+        Err(msg) => {
+          error!("{}", msg);
+          process::exit(1);
+        }
+      }
+    };
 
   let hostname = config::get_backend_hostname(matches.value_of("hostname"));
   let identifier = config::get_identifier(matches.value_of("identifier"));
@@ -108,6 +276,8 @@ fn main() {
   let username = config::get_backend_username(matches.value_of("username"));
 
   let m = matches.clone();
+  let upload_volumes = volumes.clone();
+  let verified_volumes = volumes.clone();
 
   thread::spawn(move || {
     let hostname = config::get_backend_hostname(m.value_of("hostname"));
@@ -167,7 +337,7 @@ fn main() {
                 debug!("{:?}", message);
                 match message.topic.as_ref() {
                   "transfer:upload" => {
-                    match uploader::process(&upload_ws, message) {
+                    match uploader::process(&upload_ws, message, &upload_volumes) {
                       Ok(msg) => {
                         if msg.message.is_none() {
                           let _ = s.send("upload_completed", msg.into());
@@ -237,6 +407,7 @@ fn main() {
 
             let runner =
               messages
+              .by_ref()
               .filter_map(|message| {
                 debug!("{:?}", message);
 
@@ -248,10 +419,8 @@ fn main() {
                         if let Value::Object(ref map) = message.payload {
                           if let Some(value) = map.get("last_event") {
                             if let Value::String(datetime) = value {
-                              if let Ok(date_time) =
-                                NaiveDateTime::parse_from_str(&datetime, "%Y-%m-%dT%H:%M:%S%.fZ")
-                              {
-                                return Some(Some(date_time));
+                              if let Ok(date_time) = DateTime::parse_from_rfc3339(datetime) {
+                                return Some(Some(date_time.with_timezone(&Utc)));
                               } else {
                                 return Some(None);
                               }
@@ -282,7 +451,7 @@ fn main() {
                 error!("{:?}", msg);
               }
               Ok(database_recorded_time) => {
-                let mut last_time =
+                let last_time =
                   if let Some(first) = database_recorded_time.first() {
                     first.clone()
                   } else {
@@ -290,41 +459,379 @@ fn main() {
                   };
                 info!("start watching with last time: {:?}", last_time);
 
-                loop {
-                  let filename = config::get_adobe_media_encoder_log_filename(matches.value_of("ame_log_filename"));
-                  //debug!("watching file {}", filename);
-
-                  match AdobeMediaEncoderLog::open(&filename) {
-                    Ok(logs_content) =>
-                      for mut entry in logs_content.entries {
-                        if let Some(lt) = last_time {
-                          if entry.date_time <= lt {
-                            continue;
-                          }
+                let reported_filename = config::get_reported_entries_filename(matches.value_of("reported_entries_filename"));
+                let mut reported = ReportedEntries::load(&reported_filename);
+                let last_time =
+                  match last_time {
+                    Some(lt) if lt > Utc::now() => {
+                      warn!("ignore last event in the future: {}", lt);
+                      None
+                    }
+                    lt => lt,
+                  };
+
+                let mode = ParseMode::Lenient;
+                let date_format = config::get_adobe_media_encoder_date_format(matches.value_of("ame_date_format"));
+                let timezone = config::get_log_timezone(matches.value_of("log_timezone"));
+                let log_timezone =
+                  match LogTimezone::from(&timezone) {
+                    Some(log_timezone) => log_timezone,
+                    None => {
+                      error!("invalid log timezone {:?}, local time is used", timezone);
+                      LogTimezone::Local
+                    }
+                  };
+                let verify_outputs = config::get_verify_outputs(matches.value_of("verify_outputs")) == "true";
+                let verification_settings = verifier::Settings {
+                  delay: time::Duration::from_millis(
+                    config::get_verification_delay(matches.value_of("verification_delay"))
+                      .parse()
+                      .unwrap_or(2000)
+                  ),
+                  timeout: time::Duration::from_secs(
+                    config::get_verification_timeout(matches.value_of("verification_timeout"))
+                      .parse()
+                      .unwrap_or(300)
+                  ),
+                };
+
+                let slow_factor = config::get_encode_slow_factor(matches.value_of("encode_slow_factor"))
+                  .parse()
+                  .unwrap_or(3.0);
+                let min_samples = config::get_encode_slow_min_samples(matches.value_of("encode_slow_min_samples"))
+                  .parse()
+                  .unwrap_or(5);
+                let stalled_timeout = config::get_queue_stalled_timeout(matches.value_of("queue_stalled_timeout"))
+                  .parse()
+                  .unwrap_or(7200);
+                let mut monitor = EncodeMonitor::new(encode_monitor::Thresholds {
+                  slow_factor,
+                  min_samples,
+                  stalled_timeout: chrono::Duration::seconds(stalled_timeout),
+                });
+
+                // the requests of the backend are answered while watching the logs
+                let (wakeup_sender, wakeups) = std_mpsc::channel();
+                let request_sender = wakeup_sender.clone();
+                let verifier =
+                  if verify_outputs {
+                    Some(Verifier::new(wakeup_sender.clone(), Wakeup::Verified, verification_settings, verified_volumes.clone()))
+                  } else {
+                    None
+                  };
+                thread::spawn(move || {
+                  for message in messages.wait() {
+                    match message {
+                      Ok(message) => {
+                        if request_sender.send(Wakeup::Request(message)).is_err() {
+                          break;
                         }
+                      }
+                      Err(_) => break,
+                    }
+                  }
+                });
+
+                let settings = WatchSettings {
+                  mode,
+                  timezone: log_timezone,
+                };
+
+                let defaults = log_source::Defaults {
+                  parser: "adobe_media_encoder".to_owned(),
+                  date_format,
+                  timezone,
+                  mounted_path: config::get_mounted_name_path_browsing(matches.value_of("mounted_browsing_path")),
+                  root_path: config::get_root_path_browsing(matches.value_of("root_path_browsing")),
+                };
+                let watched_logs_filename = config::get_watched_logs_filename(matches.value_of("watched_logs_filename"));
+                let sources =
+                  if watched_logs_filename.is_empty() {
+                    let parsers = config::get_encoder_log_parsers(matches.value_of("parsers"));
+                    log_source::from_parsers(&parsers, &defaults, |name| {
+                      config::get_encoder_log_filename(name, matches.value_of(filename_arg(name)))
+                    })
+                  } else {
+                    match log_source::load(&watched_logs_filename, &defaults) {
+                      Ok(sources) => sources,
+                      Err(msg) => {
+                        error!("{}", msg);
+                        vec![]
+                      }
+                    }
+                  };
 
-                        let new_time = Some(entry.date_time);
-                        let mounted_path = config::get_mounted_name_path_browsing(matches.value_of("mounted_browsing_path"));
-                        let root_path = config::get_root_path_browsing(matches.value_of("root_path_browsing"));
-                        entry.output_filename = match entry.output_filename {
-                          Some(ref output_filename) => {
-                            Some(output_filename.replace(&mounted_path, &root_path))
+                let intervals = log_watcher::Intervals {
+                  polling: time::Duration::from_secs(log_watcher::parse_interval(
+                    "log polling interval",
+                    &config::get_log_polling_interval(matches.value_of("log_polling_interval")),
+                    10,
+                    1,
+                  )),
+                  read_error: time::Duration::from_secs(log_watcher::parse_interval(
+                    "log read error delay",
+                    &config::get_log_read_error_delay(matches.value_of("log_read_error_delay")),
+                    30,
+                    1,
+                  )),
+                  debounce: time::Duration::from_millis(log_watcher::parse_interval(
+                    "log debounce delay",
+                    &config::get_log_debounce_delay(matches.value_of("log_debounce_delay")),
+                    500,
+                    50,
+                  )),
+                };
+                let mut log_watcher =
+                  if config::get_log_notifications(matches.value_of("log_notifications")) == "true" {
+                    match LogWatcher::new(wakeup_sender, Wakeup::Log) {
+                      Ok(log_watcher) => Some(log_watcher),
+                      Err(msg) => {
+                        warn!("filesystem notifications unavailable, the logs are polled: {}", msg);
+                        None
+                      }
+                    }
+                  } else {
+                    None
+                  };
+
+                let mut watched_logs = vec![];
+                for source in sources {
+                  let timezone = source.log_timezone();
+                  match encoder_log::new_parser(&source.parser, mode, &source.date_format, timezone) {
+                    Ok(parser) => {
+                      info!("watching {} log {} as {:?}", parser.name(), source.filename, source.label);
+                      // a log read before resumes after its last entry, the last
+                      // event of the backend is only used for a log never read,
+                      // to not report again the encodes sent by an older agent
+                      let bootstrap_time =
+                        match reported.position(&source.label) {
+                          Some(position) => Some(position.with_timezone(&Utc)),
+                          None => last_time,
+                        };
+                      let watched_path =
+                        match log_watcher {
+                          Some(ref mut log_watcher) if !source.polling => {
+                            match log_watcher.watch(&source.filename) {
+                              Ok(watched_path) => Some(watched_path),
+                              Err(msg) => {
+                                warn!("log {:?} is polled: {}", source.label, msg);
+                                None
+                              }
+                            }
                           }
-                          None => None,
+                          _ => None,
                         };
+                      watched_logs.push(WatchedLog {
+                        reader: LogReader::new(&source.filename),
+                        parser,
+                        schedule: ReadSchedule::new(),
+                        watched_path,
+                        source,
+                        timezone,
+                        bootstrap_time,
+                        backlog: None,
+                        next_entry: 0,
+                        next_diagnostic: 0,
+                        last_queue_state: None,
+                      });
+                    }
+                    Err(msg) => error!("log {:?}: {}", source.label, msg),
+                  }
+                }
 
-                        if let Err(_) = s.send("new_item", entry.into()) {
-                          break;
-                        } else {
-                          last_time = new_time;
+                let preset_directory = config::get_adobe_media_encoder_preset_directory(matches.value_of("ame_preset_directory"));
+                let mut catalog = PresetCatalog::new(&preset_directory);
+                if !preset_directory.is_empty() {
+                  catalog.scan();
+                  send_preset_catalog(&mut s, &catalog);
+                }
+
+                loop {
+                  for log in watched_logs.iter_mut() {
+                    let name = log.parser.name();
+                    let label = log.source.label.clone();
+                    if log.schedule.is_due(time::Instant::now(), &intervals) {
+                      let result = log.reader.read();
+                      log.schedule.read(time::Instant::now(), result.is_ok(), &intervals);
+                      match result {
+                        Ok(update) => {
+                          match update {
+                            LogUpdate::Appended(text) => {
+                              let _ = log.parser.update(&text);
+                            }
+                            LogUpdate::Reset(text) => {
+                              if let Ok(parser) = encoder_log::new_parser(name, mode, &log.source.date_format, log.timezone) {
+                                log.parser = parser;
+                              }
+                              let _ = log.parser.update(&text);
+                              log.next_entry = 0;
+                              log.next_diagnostic = 0;
+                              // a new log is written after the start of the agent
+                              log.backlog = log.backlog.map(|_| 0);
+                              // the reported entries older than the position are forgotten
+                              if let Some(position) = reported.position(&label) {
+                                log.bootstrap_time = Some(position.with_timezone(&Utc));
+                              }
+                            }
+                          }
+                          let backlog = *log.backlog.get_or_insert(log.parser.entries().len());
+
+                          let diagnostics = log.parser.diagnostics();
+                          if log.next_diagnostic < diagnostics.len() {
+                            let errors: Vec<Value> =
+                              diagnostics[log.next_diagnostic..]
+                              .iter()
+                              .map(|error| error.into())
+                              .collect();
+                            let payload = json!({ "log": label, "filename": log.reader.filename, "encoder": name, "errors": errors });
+                            if s.send("parse_errors", payload).is_ok() {
+                              log.next_diagnostic = diagnostics.len();
+                            }
+                          }
+
+                          if let Some((queue_state, mut payload)) = log.parser.queue_status() {
+                            if Some(queue_state) != log.last_queue_state {
+                              payload["log"] = label.clone().into();
+                              if s.send("queue_state", payload).is_err() {
+                                error!("unable to send {} queue state", name);
+                              } else {
+                                log.last_queue_state = Some(queue_state);
+                              }
+                            }
+                          }
+
+                          for entry in &log.parser.entries()[log.next_entry..] {
+                            // the encodes already reported still teach the usual encoding times
+                            let slow_encode = monitor.observe(entry);
+                            if reported.contains(&entry.id) {
+                              reported.advance(&label, &entry.id, entry.known_date_time());
+                              log.next_entry += 1;
+                              continue;
+                            }
+                            // an entry still verified when the agent stopped is
+                            // verified again, whatever the position of its log
+                            let pending = reported.is_pending(&entry.id);
+                            if let (Some(bt), false) = (log.bootstrap_time, pending) {
+                              // an entry without a date is only known to be recent
+                              // when it is written after the start of the agent
+                              let before_bootstrap =
+                                match entry.known_date_time() {
+                                  Some(date_time) => date_time.with_timezone(&Utc) < bt,
+                                  None => log.next_entry < backlog,
+                                };
+                              if before_bootstrap {
+                                reported.advance(&label, &entry.id, entry.known_date_time());
+                                log.next_entry += 1;
+                                continue;
+                              }
+                            }
+
+                            if let (Some(slow_encode), false) = (slow_encode, pending) {
+                              warn!("entry {} took {} seconds, {:.0} expected", entry.id, slow_encode.encoding_time, slow_encode.expected_encoding_time);
+                              let mut payload: Value = slow_encode.into();
+                              payload["log"] = label.clone().into();
+                              if s.send("encode_slow", payload).is_err() {
+                                error!("unable to send slow encode {}", entry.id);
+                              }
+                            }
+
+                            let entry = log.source.map_entry(entry);
+
+                            // the outputs of the encodes logged before the bootstrap
+                            // of the agent are not verified, they may have moved since,
+                            // unless their verification did not end
+                            let announced = entry.status == Status::Success || entry.status == Status::Warning;
+                            let verification =
+                              match verifier {
+                                Some(ref verifier) if announced && (log.next_entry >= backlog || pending) => {
+                                  match entry.output_filename.clone() {
+                                    Some(output_filename) => {
+                                      // recorded until the result, so that a restart
+                                      // of the agent does not skip the entry
+                                      reported.hold(&label, &entry.id, entry.known_date_time());
+                                      let waiting = Box::new(PendingEntry { label: label.clone(), entry });
+                                      if let Err(msg) = verifier.verify(&output_filename, waiting) {
+                                        error!("unable to verify {}: {}", output_filename, msg);
+                                      }
+                                      log.next_entry += 1;
+                                      continue;
+                                    }
+                                    None => Some(Err(Failure::Invalid("no output filename".to_owned()))),
+                                  }
+                                }
+                                _ => None,
+                              };
+
+                            if announce(&mut s, &label, entry, verification, &catalog, &mut reported).is_err() {
+                              break;
+                            }
+                            log.next_entry += 1;
+                          }
+                          // written once per read, not for each entry
+                          if let Err(msg) = reported.save() {
+                            error!("unable to write reported entries: {}", msg);
+                          }
                         }
-                      },
-                    Err(msg) => {
-                      error!("unable to read Adobe Media Encoder: {}", msg);
-                      thread::sleep(time::Duration::from_millis(120000));
+                        Err(msg) => error!("unable to read {} log {:?}: {}", name, label, msg),
+                      }
+                    }
+
+                    if let Some(stalled) = monitor.check_queue(&log.reader.filename, name, log.parser.queue_activity()) {
+                      warn!("{} queue stalled for {} seconds", name, stalled.stalled_for);
+                      let mut payload: Value = stalled.into();
+                      payload["log"] = label.clone().into();
+                      if s.send("queue_stalled", payload).is_err() {
+                        error!("unable to send {} queue stall", name);
+                      }
+                    }
+                  }
+                  monitor.prune();
+
+                  // wait for the next read, the queues are checked at least
+                  // every polling interval
+                  let mut deadline =
+                    watched_logs
+                    .iter()
+                    .map(|log| log.schedule.next_wakeup(&intervals))
+                    .fold(time::Instant::now() + intervals.polling, |deadline, wakeup| deadline.min(wakeup));
+                  loop {
+                    let now = time::Instant::now();
+                    if now >= deadline {
+                      break;
+                    }
+                    match wakeups.recv_timeout(deadline - now) {
+                      Ok(Wakeup::Request(message)) => reply_request(&mut s, message, &watched_logs, &mut reported, &mut catalog, &settings),
+                      Ok(Wakeup::Verified(pending, verification)) => {
+                        let PendingEntry { label, entry } = *pending;
+                        let id = entry.id.clone();
+                        if let Err(msg) = announce(&mut s, &label, entry, Some(verification), &catalog, &mut reported) {
+                          error!("unable to send entry {}: {}", id, msg);
+                        }
+                        if let Err(msg) = reported.save() {
+                          error!("unable to write reported entries: {}", msg);
+                        }
+                      }
+                      Ok(Wakeup::Log(change)) => {
+                        for log in watched_logs.iter_mut() {
+                          let changed =
+                            match (&change, &log.watched_path) {
+                              (Change::Path(path), Some(watched_path)) => path == watched_path,
+                              (Change::Unknown, _) => true,
+                              _ => false,
+                            };
+                          if changed {
+                            log.schedule.changed(now);
+                            deadline = deadline.min(log.schedule.next_wakeup(&intervals));
+                          }
+                        }
+                      }
+                      Err(std_mpsc::RecvTimeoutError::Timeout) => break,
+                      Err(std_mpsc::RecvTimeoutError::Disconnected) => {
+                        thread::sleep(deadline - now);
+                        break;
+                      }
                     }
                   }
-                  thread::sleep(time::Duration::from_millis(10000));
                 }
               }
             }
@@ -365,10 +872,11 @@ fn main() {
               match message.topic.as_ref() {
                 "browser:all" => {
                   debug!("browser:all: {:?}", message);
-                  let files = browser::process(message, &root_path_browsing);
-                  if let Err(msg) = s.send("response", files.into()) {
-                    error!("{:?}", msg);
-                  }
+                  browser::process(message, &volumes, |response| {
+                    if let Err(msg) = s.send("response", response) {
+                      error!("{:?}", msg);
+                    }
+                  });
                 }
                 "phoenix" => {
                   if message.event == Event::Defined(PhoenixEvent::Close) {
@@ -393,3 +901,299 @@ fn main() {
     warn!("retry to connect ...");
   }
 }
+
+/// Encoder log followed by the watcher, with what has already been sent
+struct WatchedLog {
+  reader: LogReader,
+  parser: Box<dyn EncoderLogParser>,
+  source: LogSource,
+  timezone: LogTimezone,
+  /// Path of the log in the filesystem notifications, `None` when it is polled
+  watched_path: Option<PathBuf>,
+  schedule: ReadSchedule,
+  /// Entries older than this are not reported, see `ReportedEntries::position`
+  bootstrap_time: Option<DateTime<Utc>>,
+  /// Entries in the log when it was first read, `None` before that
+  backlog: Option<usize>,
+  next_entry: usize,
+  next_diagnostic: usize,
+  last_queue_state: Option<QueueState>,
+}
+
+/// Events waking the watcher up
+enum Wakeup {
+  Request(Message),
+  Log(Change),
+  Verified(Box<PendingEntry>, Result<Verification, Failure>),
+}
+
+/// Entry waiting for the verification of its output
+struct PendingEntry {
+  label: String,
+  entry: Entry,
+}
+
+/// Configuration of the watcher, also used to answer the requests
+struct WatchSettings {
+  mode: ParseMode,
+  /// Timezone of the dates in the requests
+  timezone: LogTimezone,
+}
+
+/// Answer a request of the backend received on the notification channel
+fn reply_request(
+  s: &mut socket::Socket,
+  message: Message,
+  watched_logs: &[WatchedLog],
+  reported: &mut ReportedEntries,
+  catalog: &mut PresetCatalog,
+  settings: &WatchSettings,
+) {
+  if message.topic != "browser:notification" {
+    return;
+  }
+  if let Event::Custom(ref event_name) = message.event {
+    match event_name.as_str() {
+      "get_stats" => {
+        let payload =
+          match EntryFilter::from_payload(&message.payload, settings.timezone) {
+            Ok(filter) => {
+              // the entries are selected on the paths of their outputs as in a resync
+              let entries: Vec<Entry> =
+                watched_logs
+                .iter()
+                .flat_map(|log| log.parser.entries().iter().map(move |entry| log.source.map_entry(entry)))
+                .filter(|entry| filter.matches(entry))
+                .collect();
+              let stats: Vec<Value> = encode_stats::aggregate(&entries).into_iter().map(|stats| stats.into()).collect();
+              json!({ "stats": stats })
+            }
+            Err(msg) => json!({ "error": msg }),
+          };
+        if let Err(msg) = s.send("stats", payload) {
+          error!("unable to send stats: {}", msg);
+        }
+      }
+      "resync" => {
+        let summary =
+          match EntryFilter::from_payload(&message.payload, settings.timezone) {
+            Ok(filter) => resync(s, &filter, watched_logs, reported, catalog, settings),
+            Err(msg) => json!({ "error": msg }),
+          };
+        if let Err(msg) = s.send("resync_done", summary) {
+          error!("unable to send resync summary: {}", msg);
+        }
+      }
+      "get_presets" => {
+        if catalog.directory.is_empty() {
+          if let Err(msg) = s.send("preset_catalog", json!({ "error": "no preset directory configured" })) {
+            error!("unable to send preset catalog: {}", msg);
+          }
+        } else {
+          catalog.scan();
+          send_preset_catalog(s, catalog);
+        }
+      }
+      _ => debug!("unhandled request: {:?}", message),
+    }
+  }
+}
+
+/// Parse the watched logs again and send the entries selected by the filter,
+/// marked with `resync`, whether they have already been reported or not.
+/// Returns the summary of the resync.
+fn resync(
+  s: &mut socket::Socket,
+  filter: &EntryFilter,
+  watched_logs: &[WatchedLog],
+  reported: &mut ReportedEntries,
+  catalog: &PresetCatalog,
+  settings: &WatchSettings,
+) -> Value {
+  let mut matched = 0;
+  let mut sent = 0;
+  let mut errors = vec![];
+
+  for log in watched_logs {
+    let label = &log.source.label;
+    let filename = &log.reader.filename;
+    let parser =
+      match encoder_log::parse_file(log.parser.name(), filename, settings.mode, &log.source.date_format, log.timezone) {
+        Ok(parser) => parser,
+        Err(msg) => {
+          error!("unable to resync {}: {}", filename, msg);
+          errors.push(json!({ "log": label, "filename": filename, "error": msg }));
+          continue;
+        }
+      };
+
+    for entry in parser.entries() {
+      let entry = log.source.map_entry(entry);
+      if !filter.matches(&entry) {
+        continue;
+      }
+      matched += 1;
+
+      let id = entry.id.clone();
+      let date_time = entry.known_date_time();
+      let preset = entry.preset.as_ref().and_then(|preset| catalog.find(preset));
+      let event_name = entry.status.event_name();
+      let mut payload: Value = entry.into();
+      payload["log"] = label.as_str().into();
+      if let Some(preset) = preset {
+        payload["preset_definition"] = preset.into();
+      }
+      payload["resync"] = true.into();
+      if let Err(msg) = s.send(event_name, payload) {
+        error!("unable to resync entry {}: {}", id, msg);
+        errors.push(json!({ "log": label, "filename": filename, "error": msg }));
+        break;
+      }
+      sent += 1;
+      reported.advance(label, &id, date_time);
+    }
+  }
+  if let Err(msg) = reported.save() {
+    error!("unable to write reported entries: {}", msg);
+  }
+
+  info!("resync sent {} of {} matching entries", sent, matched);
+  json!({ "matched": matched, "sent": sent, "errors": errors })
+}
+
+/// Send the event of an entry, with the verification and the probe of its
+/// output, then record it as reported. An entry whose output is missing stays
+/// pending, a resync or a restart of the agent announces it again.
+fn announce(
+  s: &mut socket::Socket,
+  label: &str,
+  entry: Entry,
+  verification: Option<Result<Verification, Failure>>,
+  catalog: &PresetCatalog,
+  reported: &mut ReportedEntries,
+) -> Result<(), String> {
+  let id = entry.id.clone();
+  let date_time = entry.known_date_time();
+  let preset = entry.preset.as_ref().and_then(|preset| catalog.find(preset));
+  let announced = entry.status == Status::Success || entry.status == Status::Warning;
+
+  // the outputs announced are compared with what the log claims
+  let verified = verification.as_ref().and_then(|verification| verification.as_ref().err()).is_none();
+  let probe =
+    match entry.output_filename {
+      Some(ref output_filename) if announced && verified && mp4_probe::is_supported(output_filename) => {
+        match mp4_probe::probe(output_filename) {
+          Ok(media) => {
+            let mismatches = mp4_probe::compare(&entry, &media);
+            for mismatch in &mismatches {
+              warn!("entry {} logs {} {} but the output has {}", id, mismatch.field, mismatch.logged, mismatch.probed);
+            }
+            Some((media, mismatches))
+          }
+          Err(msg) => {
+            warn!("unable to probe output of entry {}: {}", id, msg);
+            None
+          }
+        }
+      }
+      _ => None,
+    };
+
+  let missing = matches!(verification, Some(Err(Failure::Missing(_))));
+  let event_name = entry.status.event_name();
+  let (event_name, mut payload) =
+    match verification {
+      Some(Ok(verification)) => {
+        let mut payload: Value = entry.into();
+        payload["verification"] = verification.into();
+        (event_name, payload)
+      }
+      Some(Err(failure)) => {
+        warn!("verification of entry {} failed: {}", id, failure);
+        let mut payload: Value = entry.into();
+        payload["reason"] = failure.to_string().into();
+        ("verification_failed", payload)
+      }
+      None => (event_name, entry.into()),
+    };
+  payload["log"] = label.into();
+  if let Some(preset) = preset {
+    payload["preset_definition"] = preset.into();
+  }
+  if let Some((media, mismatches)) = probe {
+    payload["probe"] = media.into();
+    let mismatches: Vec<Value> = mismatches.into_iter().map(|mismatch| mismatch.into()).collect();
+    payload["mismatches"] = mismatches.into();
+  }
+  s.send(event_name, payload)?;
+  if !missing {
+    reported.advance(label, &id, date_time);
+  }
+  Ok(())
+}
+
+/// Publish the presets of the catalog to the backend
+fn send_preset_catalog(s: &mut socket::Socket, catalog: &PresetCatalog) {
+  if let Err(msg) = s.send("preset_catalog", catalog.into()) {
+    error!("unable to send preset catalog: {}", msg);
+  }
+}
+
+/// Command line argument configuring the log filename of a parser
+fn filename_arg(parser: &str) -> &'static str {
+  match parser {
+    "ffmpeg" => "ffmpeg_log_filename",
+    "ffmpeg_progress" => "ffmpeg_progress_filename",
+    "handbrake" => "handbrake_log_filename",
+    "davinci_resolve" => "resolve_log_filename",
+    _ => "ame_log_filename",
+  }
+}
+
+/// Arguments of the subcommands reading an encoder log
+fn log_file_args<'a, 'b>(subcommand: App<'a, 'b>) -> App<'a, 'b> {
+  subcommand
+    .arg(Arg::with_name("logfile")
+      .help("Encoder log to parse.")
+      .required(true))
+    .arg(Arg::with_name("format")
+      .short("f")
+      .long("format")
+      .help("Output format.")
+      .possible_values(&log_dump::FORMATS)
+      .default_value("json"))
+    .arg(Arg::with_name("parser")
+      .long("parser")
+      .help("Encoder log parser to use.")
+      .possible_values(&encoder_log::PARSER_NAMES)
+      .default_value("adobe_media_encoder"))
+    .arg(Arg::with_name("from")
+      .long("from")
+      .help("Only use the entries since this date (YYYY-MM-DD or RFC 3339).")
+      .takes_value(true))
+    .arg(Arg::with_name("to")
+      .long("to")
+      .help("Only use the entries until this date, included (YYYY-MM-DD or RFC 3339).")
+      .takes_value(true))
+    .arg(Arg::with_name("preset")
+      .long("preset")
+      .help("Only use the entries with a preset containing this text, ignoring the case. Can be repeated.")
+      .takes_value(true)
+      .multiple(true)
+      .number_of_values(1))
+    .arg(Arg::with_name("output")
+      .long("output")
+      .help("Only use the entries with an output matching this pattern, where * is any text and ? any character. Can be repeated.")
+      .takes_value(true)
+      .multiple(true)
+      .number_of_values(1))
+    .arg(Arg::with_name("ame_date_format")
+      .short("d")
+      .long("ame-date-format")
+      .help("Configure the date format of the Adobe Media Encoder log (auto, us, european or a chrono format).")
+      .takes_value(true))
+    .arg(Arg::with_name("log_timezone")
+      .long("log-timezone")
+      .help("Configure the timezone of the encoder log (local, utc or an offset like +02:00).")
+      .takes_value(true))
+}
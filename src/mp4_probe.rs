@@ -0,0 +1,617 @@
+use encoder_log::Entry;
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+/// The `moov` box is read in memory, bigger ones are not probed
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+const EXTENSIONS: [&str; 4] = ["mp4", "m4v", "m4a", "mov"];
+/// Difference of duration, in seconds, still matching the log
+const DURATION_TOLERANCE: f64 = 0.5;
+const FRAME_RATE_TOLERANCE: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackKind {
+  Video,
+  Audio,
+  Other,
+}
+
+impl TrackKind {
+  fn from(handler: &[u8]) -> Self {
+    match handler {
+      b"vide" => TrackKind::Video,
+      b"soun" => TrackKind::Audio,
+      _ => TrackKind::Other,
+    }
+  }
+
+  fn as_str(&self) -> &'static str {
+    match *self {
+      TrackKind::Video => "video",
+      TrackKind::Audio => "audio",
+      TrackKind::Other => "other",
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Track {
+  pub id: u32,
+  pub kind: TrackKind,
+  /// FourCC of the sample description, like `avc1` or `mp4a`
+  pub codec: Option<String>,
+  /// Duration in seconds
+  pub duration: Option<f64>,
+  pub width: Option<u32>,
+  pub height: Option<u32>,
+  pub frame_rate: Option<f64>,
+  /// Sample rate in Hz
+  pub sample_rate: Option<u32>,
+  pub channels: Option<u32>,
+}
+
+impl From<Track> for Value {
+  fn from(track: Track) -> Self {
+    let mut m = Map::new();
+    m.insert("id".to_owned(), track.id.into());
+    m.insert("kind".to_owned(), track.kind.as_str().into());
+    if let Some(codec) = track.codec {
+      m.insert("codec".to_owned(), codec.into());
+    }
+    if let Some(duration) = track.duration {
+      m.insert("duration".to_owned(), duration.into());
+    }
+    if let Some(width) = track.width {
+      m.insert("width".to_owned(), width.into());
+    }
+    if let Some(height) = track.height {
+      m.insert("height".to_owned(), height.into());
+    }
+    if let Some(frame_rate) = track.frame_rate {
+      m.insert("frame_rate".to_owned(), frame_rate.into());
+    }
+    if let Some(sample_rate) = track.sample_rate {
+      m.insert("sample_rate".to_owned(), sample_rate.into());
+    }
+    if let Some(channels) = track.channels {
+      m.insert("channels".to_owned(), channels.into());
+    }
+    m.into()
+  }
+}
+
+/// Technical metadata read in the `moov` box of a MP4 or QuickTime file
+#[derive(Debug, Clone)]
+pub struct MediaInfo {
+  /// Duration in seconds, from the movie header
+  pub duration: Option<f64>,
+  pub tracks: Vec<Track>,
+}
+
+impl MediaInfo {
+  fn track(&self, kind: TrackKind) -> Option<&Track> {
+    self.tracks.iter().find(|track| track.kind == kind)
+  }
+}
+
+impl From<MediaInfo> for Value {
+  fn from(media: MediaInfo) -> Self {
+    let mut m = Map::new();
+    if let Some(duration) = media.duration {
+      m.insert("duration".to_owned(), duration.into());
+    }
+    let tracks: Vec<Value> = media.tracks.into_iter().map(|track| track.into()).collect();
+    m.insert("tracks".to_owned(), tracks.into());
+    m.into()
+  }
+}
+
+/// Value written in the log which differs from the one read in the file
+#[derive(Debug)]
+pub struct Mismatch {
+  pub field: &'static str,
+  pub logged: Value,
+  pub probed: Value,
+}
+
+impl From<Mismatch> for Value {
+  fn from(mismatch: Mismatch) -> Self {
+    let mut m = Map::new();
+    m.insert("field".to_owned(), mismatch.field.into());
+    m.insert("logged".to_owned(), mismatch.logged);
+    m.insert("probed".to_owned(), mismatch.probed);
+    m.into()
+  }
+}
+
+/// Whether the extension of the file is one of a MP4 or QuickTime file
+pub fn is_supported(filename: &str) -> bool {
+  match filename.rsplit('.').next() {
+    Some(extension) if extension.len() < filename.len() => {
+      EXTENSIONS.contains(&extension.to_lowercase().as_str())
+    }
+    _ => false,
+  }
+}
+
+/// Read the `moov` box of the file, skipping the other top level boxes
+/// without reading them.
+pub fn probe(filename: &str) -> Result<MediaInfo, String> {
+  let file = File::open(filename).map_err(|e| format!("unable to read {}: {}", filename, e))?;
+  read_moov(file, filename)
+}
+
+fn read_moov<R: Read + Seek>(mut file: R, filename: &str) -> Result<MediaInfo, String> {
+  let mut position = 0;
+
+  loop {
+    let mut header = [0; 8];
+    match file.read_exact(&mut header) {
+      Ok(()) => {}
+      Err(ref error) if error.kind() == ErrorKind::UnexpectedEof => break,
+      Err(error) => return Err(format!("unable to read {}: {}", filename, error)),
+    }
+
+    let mut header_size = 8;
+    let mut size = u64::from(read_u32(&header, 0).unwrap_or(0));
+    if size == 1 {
+      let mut large_size = [0; 8];
+      file
+        .read_exact(&mut large_size)
+        .map_err(|e| format!("unable to read {}: {}", filename, e))?;
+      size = read_u64(&large_size, 0).unwrap_or(0);
+      header_size = 16;
+    }
+    // a size of 0 is a box running to the end of the file
+    let body_size = if size == 0 { None } else { Some(size.saturating_sub(header_size)) };
+    if size != 0 && size < header_size {
+      return Err(format!("invalid box size {} in {}", size, filename));
+    }
+
+    if &header[4..] == b"moov" {
+      let mut moov = vec![];
+      let limit = body_size.unwrap_or(MAX_MOOV_SIZE + 1);
+      if limit > MAX_MOOV_SIZE {
+        return Err(format!("moov box of {} is too large", filename));
+      }
+      (&mut file)
+        .take(limit)
+        .read_to_end(&mut moov)
+        .map_err(|e| format!("unable to read {}: {}", filename, e))?;
+      return Ok(parse_moov(&moov));
+    }
+
+    let body_size = match body_size {
+      Some(body_size) if body_size > i64::MAX as u64 => return Err(format!("invalid box size {} in {}", size, filename)),
+      Some(body_size) => body_size,
+      None => break,
+    };
+    let next = file
+      .seek(SeekFrom::Current(body_size as i64))
+      .map_err(|e| format!("unable to read {}: {}", filename, e))?;
+    // the position only moves forward, otherwise the same boxes would be read forever
+    if next <= position {
+      return Err(format!("invalid box size {} in {}", size, filename));
+    }
+    position = next;
+  }
+
+  Err(format!("no moov box in {}", filename))
+}
+
+/// Values of the log which are not found in the file
+pub fn compare(entry: &Entry, media: &MediaInfo) -> Vec<Mismatch> {
+  let mut mismatches = vec![];
+
+  if let Some(ref video) = entry.video {
+    match media.track(TrackKind::Video) {
+      Some(track) => {
+        compare_exact(&mut mismatches, "width", Some(video.width), track.width);
+        compare_exact(&mut mismatches, "height", Some(video.height), track.height);
+        compare_float(&mut mismatches, "frame_rate", video.frame_rate, track.frame_rate, FRAME_RATE_TOLERANCE);
+        let duration = track.duration.or(media.duration);
+        compare_float(&mut mismatches, "duration", video.duration, duration, DURATION_TOLERANCE);
+      }
+      None => mismatches.push(Mismatch {
+        field: "video_track",
+        logged: true.into(),
+        probed: false.into(),
+      }),
+    }
+  }
+
+  if let Some(ref audio) = entry.audio {
+    match media.track(TrackKind::Audio) {
+      Some(track) => {
+        compare_exact(&mut mismatches, "sample_rate", audio.sample_rate, track.sample_rate);
+        let channels = audio.channel_layout.as_ref().and_then(|layout| channel_count(layout));
+        compare_exact(&mut mismatches, "channels", channels, track.channels);
+      }
+      None => mismatches.push(Mismatch {
+        field: "audio_track",
+        logged: true.into(),
+        probed: false.into(),
+      }),
+    }
+  }
+
+  mismatches
+}
+
+fn compare_exact(mismatches: &mut Vec<Mismatch>, field: &'static str, logged: Option<u32>, probed: Option<u32>) {
+  if let (Some(logged), Some(probed)) = (logged, probed) {
+    if logged != probed {
+      mismatches.push(Mismatch {
+        field,
+        logged: logged.into(),
+        probed: probed.into(),
+      });
+    }
+  }
+}
+
+fn compare_float(
+  mismatches: &mut Vec<Mismatch>,
+  field: &'static str,
+  logged: Option<f64>,
+  probed: Option<f64>,
+  tolerance: f64,
+) {
+  if let (Some(logged), Some(probed)) = (logged, probed) {
+    if (logged - probed).abs() > tolerance {
+      mismatches.push(Mismatch {
+        field,
+        logged: logged.into(),
+        probed: probed.into(),
+      });
+    }
+  }
+}
+
+/// Number of channels of a layout written in the logs, like `Stereo` or `5.1`
+fn channel_count(layout: &str) -> Option<u32> {
+  let layout = layout.to_lowercase();
+  if layout.starts_with("mono") {
+    return Some(1);
+  }
+  if layout.starts_with("stereo") {
+    return Some(2);
+  }
+  // `5.1`, `7.1(wide)`
+  let mut parts = layout.splitn(2, '.');
+  let main: u32 = parts.next()?.parse().ok()?;
+  let lfe: u32 = parts
+    .next()?
+    .chars()
+    .take_while(|c| c.is_ascii_digit())
+    .collect::<String>()
+    .parse()
+    .ok()?;
+  Some(main + lfe)
+}
+
+fn parse_moov(moov: &[u8]) -> MediaInfo {
+  let mut media = MediaInfo {
+    duration: None,
+    tracks: vec![],
+  };
+
+  for (kind, body) in children(moov) {
+    match &kind {
+      b"mvhd" => media.duration = parse_media_header(body).and_then(|(timescale, duration)| seconds(duration, timescale)),
+      b"trak" => media.tracks.push(parse_track(body)),
+      _ => {}
+    }
+  }
+  media
+}
+
+fn parse_track(trak: &[u8]) -> Track {
+  let mut track = Track {
+    id: 0,
+    kind: TrackKind::Other,
+    codec: None,
+    duration: None,
+    width: None,
+    height: None,
+    frame_rate: None,
+    sample_rate: None,
+    channels: None,
+  };
+
+  let mut timescale = None;
+  let mut sample_description = None;
+  let mut time_to_sample = None;
+
+  for (kind, body) in children(trak) {
+    match &kind {
+      b"tkhd" => {
+        let offset = if body.first() == Some(&1) { 20 } else { 12 };
+        track.id = read_u32(body, offset).unwrap_or(0);
+      }
+      b"mdia" => {
+        for (kind, body) in children(body) {
+          match &kind {
+            b"mdhd" => {
+              if let Some((scale, duration)) = parse_media_header(body) {
+                timescale = Some(scale);
+                track.duration = seconds(duration, scale);
+              }
+            }
+            b"hdlr" => {
+              if let Some(handler) = body.get(8..12) {
+                track.kind = TrackKind::from(handler);
+              }
+            }
+            b"minf" => {
+              for (kind, body) in children(body) {
+                if &kind == b"stbl" {
+                  for (kind, body) in children(body) {
+                    match &kind {
+                      b"stsd" => sample_description = Some(body),
+                      b"stts" => time_to_sample = Some(body),
+                      _ => {}
+                    }
+                  }
+                }
+              }
+            }
+            _ => {}
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+
+  // the first description is the one used by the samples of an encoded output
+  let description = sample_description.and_then(|stsd| children(stsd.get(8..)?).into_iter().next());
+  if let Some((codec, body)) = description {
+    track.codec = Some(String::from_utf8_lossy(&codec).trim().to_string());
+    match track.kind {
+      TrackKind::Video => {
+        track.width = read_u16(body, 24).map(u32::from);
+        track.height = read_u16(body, 26).map(u32::from);
+      }
+      TrackKind::Audio => parse_sound_description(&mut track, body),
+      TrackKind::Other => {}
+    }
+  }
+
+  if track.kind == TrackKind::Video {
+    track.frame_rate = match (timescale, time_to_sample) {
+      (Some(timescale), Some(stts)) => frame_rate(stts, timescale),
+      _ => None,
+    };
+  }
+  track
+}
+
+/// Sound sample description, in its ISO form or in one of the versions of
+/// QuickTime, the version 2 storing the sample rate as a float.
+fn parse_sound_description(track: &mut Track, body: &[u8]) {
+  if read_u16(body, 8) == Some(2) {
+    track.sample_rate = read_u64(body, 32).map(|rate| f64::from_bits(rate).round() as u32);
+    track.channels = read_u32(body, 40);
+  } else {
+    track.channels = read_u16(body, 16).map(u32::from);
+    track.sample_rate = read_u32(body, 24).map(|rate| rate >> 16);
+  }
+}
+
+/// Frame rate computed from the number of samples and their total duration
+fn frame_rate(stts: &[u8], timescale: u32) -> Option<f64> {
+  let count = read_u32(stts, 4)? as usize;
+  let mut samples = 0u64;
+  let mut duration = 0u64;
+  for index in 0..count {
+    let sample_count = u64::from(read_u32(stts, 8 + index * 8)?);
+    let sample_delta = u64::from(read_u32(stts, 12 + index * 8)?);
+    samples += sample_count;
+    duration += sample_count * sample_delta;
+  }
+  if duration == 0 {
+    return None;
+  }
+  let rate = samples as f64 * f64::from(timescale) / duration as f64;
+  Some((rate * 1000.0).round() / 1000.0)
+}
+
+/// Timescale and duration of a `mvhd` or `mdhd` box, a duration with all
+/// its bits set is unknown and given as 0
+fn parse_media_header(body: &[u8]) -> Option<(u32, u64)> {
+  let (timescale, duration, unknown) = if body.first() == Some(&1) {
+    (read_u32(body, 20)?, read_u64(body, 24)?, !0u64)
+  } else {
+    let duration = read_u32(body, 16)?;
+    (read_u32(body, 12)?, u64::from(duration), u64::from(!0u32))
+  };
+  if duration == unknown {
+    Some((timescale, 0))
+  } else {
+    Some((timescale, duration))
+  }
+}
+
+fn seconds(duration: u64, timescale: u32) -> Option<f64> {
+  if timescale == 0 || duration == 0 {
+    return None;
+  }
+  let seconds = duration as f64 / f64::from(timescale);
+  Some((seconds * 1000.0).round() / 1000.0)
+}
+
+/// Boxes contained in the body of a box, a truncated box ends the list
+fn children(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+  let mut boxes = vec![];
+  let mut offset = 0;
+
+  while offset + 8 <= data.len() {
+    let mut header_size = 8;
+    let mut size = match read_u32(data, offset) {
+      Some(size) => size as usize,
+      None => break,
+    };
+    if size == 1 {
+      size = match read_u64(data, offset + 8) {
+        Some(size) => size as usize,
+        None => break,
+      };
+      header_size = 16;
+    } else if size == 0 {
+      size = data.len() - offset;
+    }
+    if size < header_size || size > data.len() - offset {
+      break;
+    }
+
+    let mut kind = [0; 4];
+    kind.copy_from_slice(&data[offset + 4..offset + 8]);
+    boxes.push((kind, &data[offset + header_size..offset + size]));
+    offset += size;
+  }
+  boxes
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+  let bytes = data.get(offset..offset + 2)?;
+  Some(u16::from(bytes[0]) << 8 | u16::from(bytes[1]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+  let bytes = data.get(offset..offset + 4)?;
+  Some(bytes.iter().fold(0, |value, &byte| value << 8 | u32::from(byte)))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+  let bytes = data.get(offset..offset + 8)?;
+  Some(bytes.iter().fold(0, |value, &byte| value << 8 | u64::from(byte)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use encoder_log::{Audio, Status, Video};
+  use std::io::Cursor;
+  use timezone::LogTimezone;
+
+  fn boxed(kind: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut data = (body.len() as u32 + 8).to_be_bytes().to_vec();
+    data.extend_from_slice(kind);
+    data.extend_from_slice(body);
+    data
+  }
+
+  /// Version 0 `mvhd` or `mdhd` body
+  fn media_header(timescale: u32, duration: u32) -> Vec<u8> {
+    let mut body = vec![0; 12];
+    body.extend_from_slice(&timescale.to_be_bytes());
+    body.extend_from_slice(&duration.to_be_bytes());
+    body.resize(24, 0);
+    body
+  }
+
+  fn track(id: u32, handler: &[u8], timescale: u32, duration: u32, description: Vec<u8>, stts: &[(u32, u32)]) -> Vec<u8> {
+    let mut tkhd = vec![0; 12];
+    tkhd.extend_from_slice(&id.to_be_bytes());
+    tkhd.resize(84, 0);
+
+    let mut hdlr = vec![0; 8];
+    hdlr.extend_from_slice(handler);
+    hdlr.resize(24, 0);
+
+    let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+    stsd.extend(description);
+    let mut time_to_sample = vec![0; 4];
+    time_to_sample.extend_from_slice(&(stts.len() as u32).to_be_bytes());
+    for &(count, delta) in stts {
+      time_to_sample.extend_from_slice(&count.to_be_bytes());
+      time_to_sample.extend_from_slice(&delta.to_be_bytes());
+    }
+    let stbl = boxed(b"stbl", &[boxed(b"stsd", &stsd), boxed(b"stts", &time_to_sample)].concat());
+    let mdia = [
+      boxed(b"mdhd", &media_header(timescale, duration)),
+      boxed(b"hdlr", &hdlr),
+      boxed(b"minf", &stbl),
+    ]
+    .concat();
+    boxed(b"trak", &[boxed(b"tkhd", &tkhd), boxed(b"mdia", &mdia)].concat())
+  }
+
+  /// MP4 of 10 seconds, 1280x720 at 25 fps with stereo audio at 48 kHz
+  fn fixture() -> Vec<u8> {
+    let mut avc1 = vec![0; 78];
+    avc1[24..26].copy_from_slice(&1280u16.to_be_bytes());
+    avc1[26..28].copy_from_slice(&720u16.to_be_bytes());
+    let mut mp4a = vec![0; 28];
+    mp4a[16..18].copy_from_slice(&2u16.to_be_bytes());
+    mp4a[24..28].copy_from_slice(&(48000u32 << 16).to_be_bytes());
+
+    let moov = [
+      boxed(b"mvhd", &media_header(1000, 10000)),
+      track(1, b"vide", 25000, 250_000, boxed(b"avc1", &avc1), &[(250, 1000)]),
+      track(2, b"soun", 48000, 480_000, boxed(b"mp4a", &mp4a), &[]),
+    ]
+    .concat();
+    [boxed(b"ftyp", b"isom\0\0\0\0"), boxed(b"free", &[]), boxed(b"mdat", &[0; 100]), boxed(b"moov", &moov)].concat()
+  }
+
+  #[test]
+  fn moov_box() {
+    let media = read_moov(Cursor::new(fixture()), "fixture.mp4").unwrap();
+    assert_eq!(media.duration, Some(10.0));
+    assert_eq!(media.tracks.len(), 2);
+
+    let video = media.track(TrackKind::Video).unwrap();
+    assert_eq!((video.id, video.codec.as_deref()), (1, Some("avc1")));
+    assert_eq!((video.width, video.height), (Some(1280), Some(720)));
+    assert_eq!((video.duration, video.frame_rate), (Some(10.0), Some(25.0)));
+
+    let audio = media.track(TrackKind::Audio).unwrap();
+    assert_eq!(audio.codec.as_deref(), Some("mp4a"));
+    assert_eq!((audio.sample_rate, audio.channels), (Some(48000), Some(2)));
+  }
+
+  #[test]
+  fn invalid_box_sizes() {
+    // a 64 bits size which does not fit the offsets of the file
+    let mut data = vec![0, 0, 0, 1];
+    data.extend_from_slice(b"mdat");
+    data.extend_from_slice(&u64::MAX.to_be_bytes());
+    data.extend(fixture());
+    let error = read_moov(Cursor::new(data), "fixture.mp4").unwrap_err();
+    assert_eq!(error, format!("invalid box size {} in fixture.mp4", u64::MAX));
+
+    let error = read_moov(Cursor::new(vec![0, 0, 0, 4, b'f', b'r', b'e', b'e']), "fixture.mp4").unwrap_err();
+    assert_eq!(error, "invalid box size 4 in fixture.mp4");
+
+    let error = read_moov(Cursor::new(boxed(b"mdat", &[0; 16])), "fixture.mp4").unwrap_err();
+    assert_eq!(error, "no moov box in fixture.mp4");
+  }
+
+  #[test]
+  fn mismatches() {
+    let media = read_moov(Cursor::new(fixture()), "fixture.mp4").unwrap();
+    let mut entry = Entry::new("ffmpeg", LogTimezone::Utc.now(), Status::Success);
+    entry.video = Some(Video {
+      width: 1920,
+      height: 720,
+      pixel_aspect_ratio: None,
+      frame_rate: Some(25.0),
+      scan_type: None,
+      timecode: None,
+      duration: Some(10.2),
+    });
+    entry.audio = Some(Audio {
+      codec: None,
+      bitrate: None,
+      sample_rate: Some(48000),
+      channel_layout: Some("5.1".to_owned()),
+      bit_depth: None,
+    });
+
+    let fields: Vec<&str> = compare(&entry, &media).iter().map(|mismatch| mismatch.field).collect();
+    assert_eq!(fields, vec!["width", "channels"]);
+  }
+}
@@ -0,0 +1,270 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+
+/// How the symbolic links met while resolving a path are handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymlinkPolicy {
+  /// Symbolic links are followed when their target is under the root
+  WithinRoot,
+  /// Symbolic links under the root are followed wherever they lead, like
+  /// links to other mounts
+  Follow,
+  /// A path through a symbolic link is rejected
+  Deny,
+}
+
+impl SymlinkPolicy {
+  pub fn from(value: &str) -> Option<Self> {
+    match value {
+      "within_root" => Some(SymlinkPolicy::WithinRoot),
+      "follow" => Some(SymlinkPolicy::Follow),
+      "deny" => Some(SymlinkPolicy::Deny),
+      _ => None,
+    }
+  }
+}
+
+/// Reason a requested path is refused
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathError {
+  /// The path leads outside of the root, with `..` or a symbolic link
+  OutsideRoot(String),
+  /// The path goes through a symbolic link while they are denied
+  SymlinkDenied(String),
+  NotFound(String),
+  PermissionDenied(String),
+  /// The path can not be a filename, like a path with a NUL character
+  Invalid(String),
+  /// The path or the root could not be read
+  Io(String, String),
+}
+
+impl PathError {
+  /// Identifier of the error for the backend
+  pub fn code(&self) -> &'static str {
+    match *self {
+      PathError::OutsideRoot(_) => "outside_root",
+      PathError::SymlinkDenied(_) => "symlink_denied",
+      PathError::NotFound(_) => "not_found",
+      PathError::PermissionDenied(_) => "permission_denied",
+      PathError::Invalid(_) => "invalid_path",
+      PathError::Io(_, _) => "io_error",
+    }
+  }
+
+  /// Error met while reading a requested path
+  pub fn from_io(path: &str, error: &io::Error) -> Self {
+    match error.kind() {
+      ErrorKind::NotFound => PathError::NotFound(path.to_owned()),
+      ErrorKind::PermissionDenied => PathError::PermissionDenied(path.to_owned()),
+      _ => PathError::Io(path.to_owned(), error.to_string()),
+    }
+  }
+}
+
+impl fmt::Display for PathError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      PathError::OutsideRoot(ref path) => write!(f, "{} is outside of the browsing root", path),
+      PathError::SymlinkDenied(ref path) => write!(f, "{} goes through a symbolic link", path),
+      PathError::NotFound(ref path) => write!(f, "{} does not exist", path),
+      PathError::PermissionDenied(ref path) => write!(f, "permission denied on {}", path),
+      PathError::Invalid(ref path) => write!(f, "{:?} is not a valid path", path),
+      PathError::Io(ref path, ref reason) => write!(f, "unable to access {}: {}", path, reason),
+    }
+  }
+}
+
+/// Resolution of the paths requested by the backend, which must stay under
+/// the browsing root.
+#[derive(Debug, Clone)]
+pub struct PathResolver {
+  pub root_path: String,
+  pub policy: SymlinkPolicy,
+}
+
+impl PathResolver {
+  pub fn new(root_path: &str, policy: SymlinkPolicy) -> Self {
+    PathResolver {
+      root_path: root_path.to_owned(),
+      policy,
+    }
+  }
+
+  /// Resolve a path relative to the root, a leading `/` is ignored
+  pub fn resolve(&self, path: &str) -> Result<PathBuf, PathError> {
+    let relative = path.trim_start_matches(&['/', '\\'][..]);
+    self.confine(path, Path::new(&self.root_path).join(relative))
+  }
+
+  /// Resolve an absolute path, which must be under the root, a relative path
+  /// is relative to the root
+  pub fn resolve_absolute(&self, path: &str) -> Result<PathBuf, PathError> {
+    if Path::new(path).is_absolute() {
+      self.confine(path, PathBuf::from(path))
+    } else {
+      self.resolve(path)
+    }
+  }
+
+  fn confine(&self, requested: &str, path: PathBuf) -> Result<PathBuf, PathError> {
+    if requested.contains('\0') {
+      return Err(PathError::Invalid(requested.to_owned()));
+    }
+    let root = fs::canonicalize(&self.root_path).map_err(|e| PathError::Io(self.root_path.clone(), e.to_string()))?;
+    // the `..` are removed before the filesystem is read, so that the
+    // symbolic links met on the way can not be used to climb up
+    let outside = || PathError::OutsideRoot(requested.to_owned());
+    let path = normalize(&path).ok_or_else(outside)?;
+    let path = match normalize(Path::new(&self.root_path)) {
+      Some(ref root_path) if path.starts_with(root_path) => {
+        root.join(path.strip_prefix(root_path).unwrap_or(&path)).components().collect()
+      }
+      _ => path,
+    };
+    if !path.starts_with(&root) {
+      return Err(outside());
+    }
+
+    match self.policy {
+      SymlinkPolicy::WithinRoot => {
+        let resolved = fs::canonicalize(&path).map_err(|e| PathError::from_io(requested, &e))?;
+        if !resolved.starts_with(&root) {
+          return Err(outside());
+        }
+        Ok(resolved)
+      }
+      SymlinkPolicy::Follow => {
+        fs::metadata(&path).map_err(|e| PathError::from_io(requested, &e))?;
+        Ok(path)
+      }
+      SymlinkPolicy::Deny => {
+        let mut current = root.clone();
+        for component in path.strip_prefix(&root).unwrap_or(&path).components() {
+          current.push(component);
+          let metadata = fs::symlink_metadata(&current).map_err(|e| PathError::from_io(requested, &e))?;
+          if metadata.file_type().is_symlink() {
+            return Err(PathError::SymlinkDenied(requested.to_owned()));
+          }
+        }
+        Ok(current)
+      }
+    }
+  }
+}
+
+/// Remove the `.` and `..` components without reading the filesystem,
+/// `None` when a `..` climbs above the beginning of the path.
+fn normalize(path: &Path) -> Option<PathBuf> {
+  let mut normalized = PathBuf::new();
+  for component in path.components() {
+    match component {
+      Component::CurDir => {}
+      Component::ParentDir => {
+        match normalized.components().next_back() {
+          Some(Component::Normal(_)) => {
+            normalized.pop();
+          }
+          _ => return None,
+        }
+      }
+      component => normalized.push(component),
+    }
+  }
+  Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+  use std::process;
+
+  /// A root with `inside/file.txt`, a `secret.txt` next to the root and links
+  /// to both from the root
+  fn tree(name: &str) -> (PathBuf, String) {
+    let directory = env::temp_dir().join(format!("skia_path_resolver_{}_{}", name, process::id()));
+    let root = directory.join("root");
+    fs::create_dir_all(root.join("inside")).unwrap();
+    fs::write(root.join("inside").join("file.txt"), "inside").unwrap();
+    fs::write(directory.join("secret.txt"), "secret").unwrap();
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::symlink;
+      symlink(root.join("inside"), root.join("link_inside")).unwrap();
+      symlink(directory.join("secret.txt"), root.join("link_outside")).unwrap();
+    }
+    let root_path = root.to_string_lossy().into_owned();
+    (directory, root_path)
+  }
+
+  #[test]
+  fn parent_directories() {
+    let (directory, root_path) = tree("parent");
+    for &policy in &[SymlinkPolicy::WithinRoot, SymlinkPolicy::Follow, SymlinkPolicy::Deny] {
+      let resolver = PathResolver::new(&root_path, policy);
+      for &path in &["../secret.txt", "inside/../../secret.txt", "/../secret.txt", "..", "inside/../.."] {
+        assert_eq!(resolver.resolve(path), Err(PathError::OutsideRoot(path.to_owned())), "{}", path);
+      }
+      assert!(resolver.resolve("inside/../inside/./file.txt").unwrap().ends_with("inside/file.txt"));
+      assert_eq!(resolver.resolve("missing.txt"), Err(PathError::NotFound("missing.txt".to_owned())));
+    }
+    fs::remove_dir_all(&directory).unwrap();
+  }
+
+  #[test]
+  fn absolute_paths() {
+    let (directory, root_path) = tree("absolute");
+    let resolver = PathResolver::new(&root_path, SymlinkPolicy::WithinRoot);
+    let secret = directory.join("secret.txt").to_string_lossy().into_owned();
+    assert_eq!(resolver.resolve_absolute(&secret), Err(PathError::OutsideRoot(secret.clone())));
+    let climbing = format!("{}/../secret.txt", root_path);
+    assert_eq!(resolver.resolve_absolute(&climbing), Err(PathError::OutsideRoot(climbing.clone())));
+
+    let file = format!("{}/inside/file.txt", root_path);
+    assert!(resolver.resolve_absolute(&file).unwrap().ends_with("inside/file.txt"));
+    // an absolute path requested by the browser stays relative to the root
+    assert_eq!(resolver.resolve(&secret), Err(PathError::NotFound(secret.clone())));
+    assert!(resolver.resolve_absolute("inside/file.txt").is_ok());
+    fs::remove_dir_all(&directory).unwrap();
+  }
+
+  #[test]
+  fn nul_characters() {
+    let (directory, root_path) = tree("nul");
+    let resolver = PathResolver::new(&root_path, SymlinkPolicy::Follow);
+    let path = "inside/file.txt\0.mp4";
+    assert_eq!(resolver.resolve(path), Err(PathError::Invalid(path.to_owned())));
+    let absolute = format!("{}/{}", root_path, path);
+    assert_eq!(resolver.resolve_absolute(&absolute), Err(PathError::Invalid(absolute.clone())));
+    fs::remove_dir_all(&directory).unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn symbolic_links() {
+    let (directory, root_path) = tree("symlinks");
+    let outside = || Err(PathError::OutsideRoot("link_outside".to_owned()));
+
+    let resolver = PathResolver::new(&root_path, SymlinkPolicy::WithinRoot);
+    assert_eq!(resolver.resolve("link_outside"), outside());
+    let resolved = resolver.resolve("link_inside/file.txt").unwrap();
+    assert_eq!(resolved, fs::canonicalize(format!("{}/inside/file.txt", root_path)).unwrap());
+    // the `..` are applied to the requested path, not to the target of the link
+    assert!(resolver.resolve("link_inside/../secret.txt").is_err());
+
+    let resolver = PathResolver::new(&root_path, SymlinkPolicy::Follow);
+    let resolved = resolver.resolve("link_outside").unwrap();
+    assert_eq!(fs::read_to_string(resolved).unwrap(), "secret");
+    assert!(resolver.resolve("link_inside/file.txt").is_ok());
+
+    let resolver = PathResolver::new(&root_path, SymlinkPolicy::Deny);
+    for &path in &["link_outside", "link_inside/file.txt"] {
+      assert_eq!(resolver.resolve(path), Err(PathError::SymlinkDenied(path.to_owned())));
+    }
+    assert!(resolver.resolve("inside/file.txt").is_ok());
+    fs::remove_dir_all(&directory).unwrap();
+  }
+}
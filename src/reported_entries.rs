@@ -0,0 +1,254 @@
+use chrono::{DateTime, Duration, FixedOffset};
+use serde_json::{self, Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use timezone::{format_date_time, LogTimezone};
+
+/// The entries older than the resume position of their log by more than this
+/// are forgotten, the position alone keeps them from being sent again
+const PRUNE_MARGIN_HOURS: i64 = 24;
+
+/// Log and date of a reported entry
+#[derive(Debug)]
+struct Report {
+  label: String,
+  /// Date of the entry, or the time it was reported when it has none
+  date_time: DateTime<FixedOffset>,
+}
+
+/// Identities of the entries already sent to the backend, kept in a JSON
+/// file so that a restart of the agent neither drops nor repeats an encode.
+/// The file also keeps the resume position of each watched log, the date of
+/// the last entry handled in that log, and the entries whose output is not
+/// verified yet, which the position alone would skip after a restart.
+#[derive(Debug)]
+pub struct ReportedEntries {
+  pub filename: String,
+  ids: HashMap<String, Report>,
+  /// Entries waiting for their output, they are not reported yet
+  pending: HashMap<String, Report>,
+  positions: HashMap<String, DateTime<FixedOffset>>,
+  /// Changes not written to the file yet
+  dirty: bool,
+}
+
+impl ReportedEntries {
+  /// Read `{"ids": {"id": {"log": "label", "date_time": "date"}}, "pending":
+  /// {"id": {"log": "label", "date_time": "date"}}, "positions": {"label":
+  /// "date"}}`
+  pub fn load(filename: &str) -> Self {
+    let value = match fs::read_to_string(filename) {
+      Ok(content) => match serde_json::from_str::<Value>(&content) {
+        Ok(value) => value,
+        Err(msg) => {
+          error!("unable to parse reported entries {}: {}", filename, msg);
+          Value::Null
+        }
+      },
+      Err(ref error) if error.kind() == ErrorKind::NotFound => Value::Null,
+      Err(msg) => {
+        error!("unable to read reported entries {}: {}", filename, msg);
+        Value::Null
+      }
+    };
+
+    ReportedEntries {
+      filename: filename.to_owned(),
+      ids: parse_reports(value.get("ids")),
+      pending: parse_reports(value.get("pending")),
+      positions: parse_positions(value.get("positions")),
+      dirty: false,
+    }
+  }
+
+  pub fn contains(&self, id: &str) -> bool {
+    self.ids.contains_key(id)
+  }
+
+  /// The output of the entry was not verified before the agent stopped, the
+  /// entry has to be verified again even when it is older than the position
+  pub fn is_pending(&self, id: &str) -> bool {
+    self.pending.contains_key(id)
+  }
+
+  /// Date of the last entry handled in the log, `None` for a log never read
+  pub fn position(&self, label: &str) -> Option<DateTime<FixedOffset>> {
+    self.positions.get(label).cloned()
+  }
+
+  /// Record an entry of a log whose output is being verified, it is kept
+  /// until it is reported. The file is written by `save`.
+  pub fn hold(&mut self, label: &str, id: &str, date_time: Option<DateTime<FixedOffset>>) {
+    if !self.pending.contains_key(id) {
+      let report = Report {
+        label: label.to_owned(),
+        date_time: date_time.unwrap_or_else(|| LogTimezone::Utc.now()),
+      };
+      self.pending.insert(id.to_owned(), report);
+      self.dirty = true;
+    }
+  }
+
+  /// Record an entry of a log as reported, moving the resume position of
+  /// the log to its date when it is known. The file is written by `save`.
+  pub fn advance(&mut self, label: &str, id: &str, date_time: Option<DateTime<FixedOffset>>) {
+    if self.pending.remove(id).is_some() {
+      self.dirty = true;
+    }
+    if let Some(date_time) = date_time {
+      if self.positions.get(label).map(|position| date_time > *position).unwrap_or(true) {
+        self.positions.insert(label.to_owned(), date_time);
+        self.dirty = true;
+      }
+    }
+    if !self.ids.contains_key(id) {
+      let report = Report {
+        label: label.to_owned(),
+        date_time: date_time.unwrap_or_else(|| LogTimezone::Utc.now()),
+      };
+      self.ids.insert(id.to_owned(), report);
+      self.dirty = true;
+    }
+  }
+
+  /// Write the file when entries were recorded since it was last written,
+  /// without the entries now covered by the resume positions
+  pub fn save(&mut self) -> Result<(), String> {
+    if !self.dirty {
+      return Ok(());
+    }
+    self.prune();
+
+    let mut positions = Map::new();
+    for (label, position) in &self.positions {
+      positions.insert(label.clone(), format_date_time(position).into());
+    }
+    let content = json!({
+      "ids": reports_value(&self.ids),
+      "pending": reports_value(&self.pending),
+      "positions": positions,
+    });
+    let content = serde_json::to_string(&content).map_err(|e| e.to_string())?;
+
+    // write a temporary file first, the list is never left half written
+    let temporary = format!("{}.tmp", self.filename);
+    fs::write(&temporary, content).map_err(|e| e.to_string())?;
+    fs::rename(&temporary, &self.filename).map_err(|e| e.to_string())?;
+    self.dirty = false;
+    Ok(())
+  }
+
+  /// Forget the entries older than the position of their log, the margin
+  /// keeps the entries of a log which are not written in order. The pending
+  /// entries are kept whatever their date.
+  fn prune(&mut self) {
+    let margin = Duration::hours(PRUNE_MARGIN_HOURS);
+    let positions = &self.positions;
+    self.ids.retain(|_, report| match positions.get(&report.label) {
+      Some(position) => report.date_time > *position - margin,
+      None => true,
+    });
+  }
+}
+
+fn reports_value(reports: &HashMap<String, Report>) -> Value {
+  let mut m = Map::new();
+  for (id, report) in reports {
+    let mut r = Map::new();
+    r.insert("log".to_owned(), report.label.clone().into());
+    r.insert("date_time".to_owned(), format_date_time(&report.date_time).into());
+    m.insert(id.clone(), r.into());
+  }
+  m.into()
+}
+
+fn parse_reports(value: Option<&Value>) -> HashMap<String, Report> {
+  value
+    .and_then(|reports| reports.as_object())
+    .map(|reports| {
+      reports
+        .iter()
+        .filter_map(|(id, report)| {
+          let label = report.get("log")?.as_str()?.to_owned();
+          let date_time = DateTime::parse_from_rfc3339(report.get("date_time")?.as_str()?).ok()?;
+          Some((id.clone(), Report { label, date_time }))
+        })
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+fn parse_positions(value: Option<&Value>) -> HashMap<String, DateTime<FixedOffset>> {
+  value
+    .and_then(|positions| positions.as_object())
+    .map(|positions| {
+      positions
+        .iter()
+        .filter_map(|(label, position)| {
+          let position = DateTime::parse_from_rfc3339(position.as_str()?).ok()?;
+          Some((label.clone(), position))
+        })
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+
+  fn date(date_time: &str) -> Option<DateTime<FixedOffset>> {
+    Some(DateTime::parse_from_rfc3339(date_time).unwrap())
+  }
+
+  #[test]
+  fn saved_once_and_pruned() {
+    let path = env::temp_dir().join(format!("skia_reported_entries_{}.json", ::std::process::id()));
+    let filename = path.to_string_lossy().into_owned();
+    let _ = fs::remove_file(&path);
+
+    let mut reported = ReportedEntries::load(&filename);
+    reported.advance("ame", "old", date("2018-02-16T08:00:00+01:00"));
+    reported.advance("ame", "recent", date("2018-02-17T09:00:00+01:00"));
+    reported.advance("ame", "undated", None);
+    reported.advance("ffmpeg", "other log", date("2018-01-01T00:00:00Z"));
+    assert!(!path.exists());
+
+    reported.save().unwrap();
+    let reported = ReportedEntries::load(&filename);
+    fs::remove_file(&path).unwrap();
+    assert!(!reported.contains("old"));
+    assert!(reported.contains("recent"));
+    assert!(reported.contains("undated"));
+    assert!(reported.contains("other log"));
+    assert_eq!(reported.position("ame"), date("2018-02-17T09:00:00+01:00"));
+  }
+
+  #[test]
+  fn pending_after_restart() {
+    let path = env::temp_dir().join(format!("skia_reported_pending_{}.json", ::std::process::id()));
+    let filename = path.to_string_lossy().into_owned();
+    let _ = fs::remove_file(&path);
+
+    let mut reported = ReportedEntries::load(&filename);
+    reported.hold("ame", "verifying", date("2018-02-10T09:00:00+01:00"));
+    reported.advance("ame", "later", date("2018-02-17T10:00:00+01:00"));
+    reported.save().unwrap();
+
+    // the agent stops before the verification ends
+    let mut reported = ReportedEntries::load(&filename);
+    assert!(reported.is_pending("verifying"));
+    assert!(!reported.contains("verifying"));
+    assert_eq!(reported.position("ame"), date("2018-02-17T10:00:00+01:00"));
+
+    reported.advance("ame", "verifying", date("2018-02-10T09:00:00+01:00"));
+    reported.save().unwrap();
+    let reported = ReportedEntries::load(&filename);
+    fs::remove_file(&path).unwrap();
+    assert!(!reported.is_pending("verifying"));
+    assert!(reported.contains("later"));
+    assert_eq!(reported.position("ame"), date("2018-02-17T10:00:00+01:00"));
+  }
+}
@@ -0,0 +1,306 @@
+use chrono::NaiveDateTime;
+use encoder_log::{
+  normalize_path, EncoderLogParser, Entry, LineBuffer, ParseError, ParseErrorKind, ParseMode,
+  Status, Video,
+};
+use timezone::LogTimezone;
+
+const ENCODER: &str = "davinci_resolve";
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S,%3f";
+
+/// Encode being read, from the start of the render job to its end
+#[derive(Debug)]
+struct Job {
+  line: usize,
+  text: String,
+  started: NaiveDateTime,
+  output_filename: Option<String>,
+  preset: Option<String>,
+  video: Option<Video>,
+  frame_rate: Option<f64>,
+  messages: Vec<String>,
+  errors: Vec<ParseError>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum JobEvent {
+  Started,
+  Finished(Status),
+}
+
+/// Parser of the render log of DaVinci Resolve, which has lines like
+///
+/// ```text
+/// [0x70000d2a1000] | Render | INFO  | 2018-02-16 22:11:56,123 | Render job "Job 1" started
+/// ```
+///
+/// A job starts and ends with a `Render job "<name>" <event>` message, see
+/// `job_event`, and its settings are read from `Output file:`, `Preset:`,
+/// `Resolution:` and `Frame rate:`.
+#[derive(Debug)]
+pub struct ResolveLog {
+  pub mode: ParseMode,
+  pub entries: Vec<Entry>,
+  pub diagnostics: Vec<ParseError>,
+  timezone: LogTimezone,
+  lines: LineBuffer,
+  job: Option<Job>,
+}
+
+impl ResolveLog {
+  pub fn new(mode: ParseMode, timezone: LogTimezone) -> Self {
+    ResolveLog {
+      mode,
+      entries: vec![],
+      diagnostics: vec![],
+      timezone,
+      lines: LineBuffer::new(),
+      job: None,
+    }
+  }
+
+  fn parse_line(&mut self, number: usize, line: &str) {
+    let fields: Vec<&str> = line.splitn(5, " | ").map(|field| field.trim()).collect();
+    if fields.len() != 5 {
+      return;
+    }
+    let (level, message) = (fields[2], fields[4]);
+    let date_time = match NaiveDateTime::parse_from_str(fields[3], DATE_FORMAT) {
+      Ok(date_time) => date_time,
+      Err(_) => {
+        self.push_error(ParseError::new(number, line, ParseErrorKind::InvalidDate));
+        return;
+      }
+    };
+
+    match job_event(message) {
+      Some(JobEvent::Started) => {
+        if let Some(job) = self.job.take() {
+          self.push_error(ParseError::new(job.line, &job.text, ParseErrorKind::MissingOutcome));
+        }
+        self.job = Some(Job {
+          line: number,
+          text: line.to_string(),
+          started: date_time,
+          output_filename: None,
+          preset: None,
+          video: None,
+          frame_rate: None,
+          messages: vec![],
+          errors: vec![],
+        });
+        return;
+      }
+      Some(JobEvent::Finished(status)) => {
+        self.finish_job(number, date_time, status);
+        return;
+      }
+      None => {}
+    }
+
+    let mut errors = vec![];
+    if let Some(ref mut job) = self.job {
+      parse_job_message(job, number, line, message, &mut errors);
+      if level.starts_with("ERROR") || level.starts_with("WARN") {
+        job.messages.push(message.to_string());
+      }
+    }
+    for error in errors {
+      self.push_error(error);
+    }
+  }
+
+  fn finish_job(&mut self, number: usize, date_time: NaiveDateTime, status: Status) {
+    let job = match self.job.take() {
+      Some(job) => job,
+      None => return,
+    };
+
+    let status = if status == Status::Success && !job.messages.is_empty() {
+      Status::Warning
+    } else {
+      status
+    };
+
+    let date_time = self.timezone.localize(date_time);
+    let mut entry = Entry::new(ENCODER, date_time, status);
+    entry.messages = job.messages;
+    entry.output_filename = job.output_filename.map(|filename| normalize_path(&filename));
+    entry.preset = job.preset;
+    let frame_rate = job.frame_rate;
+    entry.video = job.video.map(|mut video| {
+      video.frame_rate = frame_rate;
+      video
+    });
+    let started = self.timezone.localize(job.started);
+    let encoding_time = date_time.signed_duration_since(started).num_seconds();
+    entry.encoding_time = Some(encoding_time.max(0) as u64);
+    entry.identify(number, &date_time.naive_local().to_string());
+
+    if self.mode == ParseMode::Lenient || job.errors.is_empty() {
+      self.entries.push(entry);
+    }
+  }
+
+  fn push_error(&mut self, error: ParseError) {
+    warn!("DaVinci Resolve log, {}", error);
+    if let Some(ref mut job) = self.job {
+      job.errors.push(error.clone());
+    }
+    self.diagnostics.push(error);
+  }
+}
+
+impl EncoderLogParser for ResolveLog {
+  fn name(&self) -> &'static str {
+    ENCODER
+  }
+
+  fn update(&mut self, text: &str) -> Result<usize, ParseError> {
+    let count = self.entries.len();
+    let diagnostics = self.diagnostics.len();
+
+    for (number, line) in self.lines.push(text) {
+      self.parse_line(number, &line);
+    }
+
+    if self.mode == ParseMode::Strict {
+      if let Some(error) = self.diagnostics.get(diagnostics) {
+        return Err(error.clone());
+      }
+    }
+    Ok(self.entries.len() - count)
+  }
+
+  fn entries(&self) -> &[Entry] {
+    &self.entries
+  }
+
+  fn diagnostics(&self) -> &[ParseError] {
+    &self.diagnostics
+  }
+}
+
+/// Event of a render job, from the word following its name. The name is
+/// quoted, and may contain any word like `Restart`.
+fn job_event(message: &str) -> Option<JobEvent> {
+  let lowercase = message.to_lowercase();
+  let rest = lowercase.strip_prefix("render job ")?;
+  let rest = match rest.chars().next() {
+    Some(quote) if quote == '"' || quote == '\'' => {
+      let end = rest[1..].find(quote)?;
+      &rest[end + 2..]
+    }
+    _ => &rest[rest.find(' ')? + 1..],
+  };
+  // the releases write `completed` or `has completed`, `cancelled` or `was cancelled`
+  let rest = rest.trim_start();
+  let rest = rest.strip_prefix("has ").or_else(|| rest.strip_prefix("was ")).unwrap_or(rest);
+  let word = rest.split(|c: char| !c.is_alphabetic()).next()?;
+
+  match word {
+    "started" | "starting" => Some(JobEvent::Started),
+    "completed" | "finished" => Some(JobEvent::Finished(Status::Success)),
+    "failed" => Some(JobEvent::Finished(Status::Failed)),
+    "cancelled" | "canceled" | "aborted" => Some(JobEvent::Finished(Status::Interrupted)),
+    _ => None,
+  }
+}
+
+fn parse_job_message(
+  job: &mut Job,
+  number: usize,
+  line: &str,
+  message: &str,
+  errors: &mut Vec<ParseError>,
+) {
+  let (key, value) = match message.find(": ") {
+    Some(position) => (message[..position].to_lowercase(), message[position + 2..].trim()),
+    None => return,
+  };
+
+  match key.as_str() {
+    "output file" | "output path" | "render file" => {
+      job.output_filename = Some(value.trim_matches('"').to_string())
+    }
+    "preset" | "render preset" => job.preset = Some(value.trim_matches('"').to_string()),
+    "resolution" => {
+      let mut size = value.splitn(2, 'x');
+      match (
+        size.next().map(|width| width.trim().parse::<u32>()),
+        size.next().map(|height| height.trim().parse::<u32>()),
+      ) {
+        (Some(Ok(width)), Some(Ok(height))) => {
+          job.video = Some(Video {
+            width,
+            height,
+            pixel_aspect_ratio: None,
+            frame_rate: None,
+            scan_type: None,
+            timecode: None,
+            duration: None,
+          });
+        }
+        _ => errors.push(ParseError::new(number, line, ParseErrorKind::InvalidVideo)),
+      }
+    }
+    "frame rate" => {
+      job.frame_rate = value
+        .split_whitespace()
+        .next()
+        .and_then(|rate| rate.parse::<f64>().ok());
+      if job.frame_rate.is_none() {
+        errors.push(ParseError::new(number, line, ParseErrorKind::InvalidVideo));
+      }
+    }
+    _ => {}
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const LOG: &str = r#"[0x7000] | Render | INFO  | 2018-02-16 22:11:56,000 | Render job "Restart" started
+[0x7000] | Render | INFO  | 2018-02-16 22:11:56,200 | Output file: "D:\Renders\restart.mov"
+[0x7000] | Render | INFO  | 2018-02-16 22:11:56,210 | Preset: "YouTube 1080p"
+[0x7000] | Render | INFO  | 2018-02-16 22:11:56,220 | Resolution: 1920x1080
+[0x7000] | Render | INFO  | 2018-02-16 22:11:56,230 | Frame rate: 25 fps
+[0x7000] | Render | INFO  | 2018-02-16 22:13:06,000 | Render job "Restart" completed with 0 failed frames
+[0x7000] | Render | INFO  | 2018-02-16 22:14:00,000 | Render job "Job 2" started
+[0x7000] | Render | ERROR | 2018-02-16 22:14:30,000 | Disk is almost full
+[0x7000] | Render | INFO  | 2018-02-16 22:15:00,000 | Render job "Job 2" completed, restarted
+[0x7000] | Render | INFO  | 2018-02-16 22:16:00,000 | Render job 3 has started
+[0x7000] | Render | ERROR | 2018-02-16 22:16:10,000 | Render job 3 failed
+[0x7000] | Render | INFO  | 2018-02-16 22:17:00,000 | Render job "Job 4" started
+[0x7000] | Render | INFO  | 2018-02-16 22:17:10,000 | Render job "Job 4" was cancelled
+"#;
+
+  #[test]
+  fn render_jobs() {
+    let mut parser = ResolveLog::new(ParseMode::Strict, LogTimezone::Utc);
+    assert_eq!(parser.update(LOG).unwrap(), 4);
+
+    let statuses: Vec<Status> = parser.entries.iter().map(|entry| entry.status).collect();
+    assert_eq!(statuses, vec![Status::Success, Status::Warning, Status::Failed, Status::Interrupted]);
+
+    let entry = &parser.entries[0];
+    assert_eq!(entry.output_filename, Some("D:/Renders/restart.mov".to_owned()));
+    assert_eq!(entry.preset, Some("YouTube 1080p".to_owned()));
+    assert_eq!(entry.encoding_time, Some(70));
+    let video = entry.video.as_ref().unwrap();
+    assert_eq!((video.width, video.height, video.frame_rate), (1920, 1080, Some(25.0)));
+
+    assert_eq!(parser.entries[1].messages, vec!["Disk is almost full".to_owned()]);
+  }
+
+  #[test]
+  fn job_events() {
+    assert_eq!(job_event(r#"Render job "Restart" started"#), Some(JobEvent::Started));
+    assert_eq!(job_event(r#"Render job "Failed takes" completed"#), Some(JobEvent::Finished(Status::Success)));
+    assert_eq!(job_event(r#"Render job "X" completed with 0 failed frames"#), Some(JobEvent::Finished(Status::Success)));
+    assert_eq!(job_event("Render job 2 finished"), Some(JobEvent::Finished(Status::Success)));
+    assert_eq!(job_event(r#"Render job "X" progress 50%"#), None);
+    assert_eq!(job_event("Starting render job"), None);
+  }
+}
@@ -0,0 +1,186 @@
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDateTime, Offset, TimeZone, Utc};
+use std::fmt;
+
+/// Timezone of the timestamps written by the encoders, which only write the
+/// local time of the workstation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogTimezone {
+  /// Timezone of the workstation, with its daylight saving time changes
+  Local,
+  Utc,
+  /// Offset which never changes, like `+02:00`
+  Fixed(FixedOffset),
+}
+
+impl LogTimezone {
+  /// Parse `local`, `utc` or an offset like `+02:00`, `-0530`
+  pub fn from(value: &str) -> Option<Self> {
+    match value.trim() {
+      "" | "local" => return Some(LogTimezone::Local),
+      "utc" | "UTC" | "Z" => return Some(LogTimezone::Utc),
+      _ => {}
+    }
+
+    let value = value.trim();
+    let sign = match value.chars().next() {
+      Some('+') => 1,
+      Some('-') => -1,
+      _ => return None,
+    };
+    let digits: String = value[1..].chars().filter(|&c| c != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+      return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    if minutes >= 60 {
+      return None;
+    }
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).map(LogTimezone::Fixed)
+  }
+
+  /// Attach the offset of the timezone to a timestamp read in a log.
+  /// A local time repeated when the clocks go back is taken as the first one,
+  /// a local time skipped when they go forward is moved one hour later.
+  pub fn localize(&self, date_time: NaiveDateTime) -> DateTime<FixedOffset> {
+    let localized = match *self {
+      LogTimezone::Local => localize_in(&Local, date_time),
+      LogTimezone::Utc => None,
+      LogTimezone::Fixed(offset) => localize_in(&offset, date_time),
+    };
+    localized.unwrap_or_else(|| with_fixed_offset(Utc.from_utc_datetime(&date_time)))
+  }
+
+  /// Current time, with the offset of the timezone
+  pub fn now(&self) -> DateTime<FixedOffset> {
+    let now = Utc::now();
+    match *self {
+      LogTimezone::Local => with_fixed_offset(now.with_timezone(&Local)),
+      LogTimezone::Utc => with_fixed_offset(now),
+      LogTimezone::Fixed(offset) => now.with_timezone(&offset),
+    }
+  }
+}
+
+fn localize_in<Tz: TimeZone>(timezone: &Tz, date_time: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+  timezone
+    .from_local_datetime(&date_time)
+    .earliest()
+    .or_else(|| timezone.from_local_datetime(&(date_time + Duration::hours(1))).earliest())
+    .map(with_fixed_offset)
+}
+
+fn with_fixed_offset<Tz: TimeZone>(date_time: DateTime<Tz>) -> DateTime<FixedOffset> {
+  let offset = date_time.offset().fix();
+  date_time.with_timezone(&offset)
+}
+
+/// Format used in the events sent to the backend, RFC 3339 with the offset
+pub fn format_date_time<Tz: TimeZone>(date_time: &DateTime<Tz>) -> String
+where
+  Tz::Offset: fmt::Display,
+{
+  format!("{}", date_time.format("%Y-%m-%dT%H:%M:%S%:z"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{LocalResult, NaiveDate};
+
+  fn offset(seconds: i32) -> FixedOffset {
+    FixedOffset::east_opt(seconds).unwrap()
+  }
+
+  fn naive(date_time: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M:%S").unwrap()
+  }
+
+  /// Central European Time of 2018, whose clocks go forward on March 25 at
+  /// 02:00 and back on October 28 at 03:00
+  #[derive(Debug, Clone, Copy)]
+  struct Paris;
+
+  impl Paris {
+    fn offset_at(utc: &NaiveDateTime) -> FixedOffset {
+      let summer_start = naive("2018-03-25 01:00:00");
+      let summer_end = naive("2018-10-28 01:00:00");
+      if *utc >= summer_start && *utc < summer_end {
+        offset(2 * 3600)
+      } else {
+        offset(3600)
+      }
+    }
+  }
+
+  impl TimeZone for Paris {
+    type Offset = FixedOffset;
+
+    fn from_offset(_offset: &FixedOffset) -> Self {
+      Paris
+    }
+
+    fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+      self.offset_from_local_datetime(&local.and_hms_opt(12, 0, 0).unwrap())
+    }
+
+    fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+      let offsets: Vec<FixedOffset> = [offset(2 * 3600), offset(3600)]
+        .iter()
+        .cloned()
+        .filter(|offset| Paris::offset_at(&(*local - Duration::seconds(offset.local_minus_utc().into()))) == *offset)
+        .collect();
+      match offsets.len() {
+        0 => LocalResult::None,
+        1 => LocalResult::Single(offsets[0]),
+        _ => LocalResult::Ambiguous(offsets[0], offsets[1]),
+      }
+    }
+
+    fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+      Paris::offset_at(&utc.and_hms_opt(12, 0, 0).unwrap())
+    }
+
+    fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+      Paris::offset_at(utc)
+    }
+  }
+
+  fn localize(date_time: &str) -> String {
+    format_date_time(&localize_in(&Paris, naive(date_time)).unwrap())
+  }
+
+  #[test]
+  fn parsed_timezones() {
+    assert_eq!(LogTimezone::from(""), Some(LogTimezone::Local));
+    assert_eq!(LogTimezone::from("local"), Some(LogTimezone::Local));
+    assert_eq!(LogTimezone::from(" UTC "), Some(LogTimezone::Utc));
+    assert_eq!(LogTimezone::from("+02:00"), Some(LogTimezone::Fixed(offset(2 * 3600))));
+    assert_eq!(LogTimezone::from("-0530"), Some(LogTimezone::Fixed(offset(-(5 * 3600 + 30 * 60)))));
+    assert_eq!(LogTimezone::from("+02:60"), None);
+    assert_eq!(LogTimezone::from("02:00"), None);
+    assert_eq!(LogTimezone::from("Europe/Paris"), None);
+  }
+
+  #[test]
+  fn daylight_saving_time_changes() {
+    assert_eq!(localize("2018-03-25 01:30:00"), "2018-03-25T01:30:00+01:00");
+    // skipped when the clocks go forward, moved one hour later
+    assert_eq!(localize("2018-03-25 02:30:00"), "2018-03-25T03:30:00+02:00");
+    assert_eq!(localize("2018-03-25 03:30:00"), "2018-03-25T03:30:00+02:00");
+
+    // repeated when the clocks go back, the first one is taken
+    assert_eq!(localize("2018-10-28 01:30:00"), "2018-10-28T01:30:00+02:00");
+    assert_eq!(localize("2018-10-28 02:30:00"), "2018-10-28T02:30:00+02:00");
+    assert_eq!(localize("2018-10-28 03:30:00"), "2018-10-28T03:30:00+01:00");
+  }
+
+  #[test]
+  fn fixed_offsets() {
+    let date_time = naive("2018-03-25 02:30:00");
+    let timezone = LogTimezone::from("+02:00").unwrap();
+    assert_eq!(format_date_time(&timezone.localize(date_time)), "2018-03-25T02:30:00+02:00");
+    assert_eq!(format_date_time(&LogTimezone::Utc.localize(date_time)), "2018-03-25T02:30:00+00:00");
+  }
+}
@@ -8,6 +8,7 @@ use std::cmp::min;
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
+use volume::Volumes;
 
 use websocket::futures::future::Future;
 use websocket::futures::sink::Sink;
@@ -90,13 +91,24 @@ impl UploadOrder {
   }
 }
 
-pub fn process(upload_ws: &str, message: Message) -> Result<UploadResponse, UploadResponse> {
+pub fn process(upload_ws: &str, message: Message, volumes: &Volumes) -> Result<UploadResponse, UploadResponse> {
   if let Event::Custom(ref event) = message.event {
     match event.as_str() {
       "start" => {
         if let Some(order) = UploadOrder::from(message.payload) {
           let job_id = order.job_id;
-          let full_path = format!("{}", order.path);
+          // only the files of the volumes can be uploaded
+          let full_path =
+            match volumes.resolve_absolute(&order.path) {
+              Ok((_, full_path)) => full_path.to_string_lossy().into_owned(),
+              Err(error) => {
+                warn!("rejected upload of {}: {}", order.path, error);
+                return Ok(UploadResponse{
+                  job_id: Some(job_id),
+                  message: Some(error.to_string())
+                });
+              }
+            };
           let ws = upload_ws.to_string();
           let t = thread::spawn(move || {
             if let Err(msg) = upload_file(ws.as_str(), &full_path, &order.destination) {
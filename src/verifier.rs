@@ -0,0 +1,307 @@
+use chrono::{DateTime, Utc};
+use md5;
+use path_resolver::PathError;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use timezone::format_date_time;
+use volume::Volumes;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Properties of an output file, checked before the encode is announced
+#[derive(Debug)]
+pub struct Verification {
+  pub size: u64,
+  pub modified: DateTime<Utc>,
+  /// MD5 of the content, in hexadecimal
+  pub md5: String,
+}
+
+impl From<Verification> for Value {
+  fn from(verification: Verification) -> Self {
+    let mut m = Map::new();
+    m.insert("size".to_owned(), verification.size.into());
+    m.insert("modified".to_owned(), format_date_time(&verification.modified).into());
+    m.insert("md5".to_owned(), verification.md5.into());
+    m.into()
+  }
+}
+
+/// Why an output failed its verification
+#[derive(Debug)]
+pub enum Failure {
+  /// The output is not there, it may still be moved in place later
+  Missing(String),
+  /// The output is not the file the log announces
+  Invalid(String),
+}
+
+impl fmt::Display for Failure {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Failure::Missing(ref reason) | Failure::Invalid(ref reason) => write!(f, "{}", reason),
+    }
+  }
+}
+
+/// Delays of the verifications, see `config::get_verification_delay`
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+  /// The size and modification time of an output must not change for this delay
+  pub delay: Duration,
+  /// An output still missing or written after this time fails its verification
+  pub timeout: Duration,
+}
+
+/// Verifications done in their own thread, so that the logs are still read
+/// while an output is being written or its checksum computed. The outputs
+/// which are not ready are checked again every `Settings::delay`.
+pub struct Verifier<T> {
+  sender: Sender<(String, T)>,
+}
+
+impl<T: Send + 'static> Verifier<T> {
+  /// The results are sent with the context of their output, wrapped by
+  /// `wrap`, so that they share the channel of the other events of the caller.
+  pub fn new<W: Send + 'static>(
+    sender: Sender<W>,
+    wrap: fn(T, Result<Verification, Failure>) -> W,
+    settings: Settings,
+    volumes: Volumes,
+  ) -> Self {
+    let (request_sender, requests) = channel::<(String, T)>();
+
+    thread::spawn(move || {
+      let mut jobs: Vec<Job<T>> = vec![];
+      loop {
+        let mut waiting = vec![];
+        for mut job in jobs.drain(..) {
+          if job.due > Instant::now() {
+            waiting.push(job);
+            continue;
+          }
+          match job.attempt(&settings, &volumes) {
+            Some(result) => {
+              if sender.send(wrap(job.context, result)).is_err() {
+                return;
+              }
+            }
+            None => {
+              job.due = Instant::now() + settings.delay;
+              waiting.push(job);
+            }
+          }
+        }
+        jobs = waiting;
+
+        let request = match jobs.iter().map(|job| job.due).min() {
+          Some(due) => match requests.recv_timeout(due.saturating_duration_since(Instant::now())) {
+            Ok(request) => request,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return,
+          },
+          None => match requests.recv() {
+            Ok(request) => request,
+            Err(_) => return,
+          },
+        };
+        let (filename, context) = request;
+        jobs.push(Job::new(filename, context));
+      }
+    });
+
+    Verifier {
+      sender: request_sender,
+    }
+  }
+
+  /// Check that the output is a non empty file of the volumes,
+  /// whose size and modification time have not changed for the delay, then
+  /// compute its checksum.
+  pub fn verify(&self, filename: &str, context: T) -> Result<(), String> {
+    self
+      .sender
+      .send((filename.to_owned(), context))
+      .map_err(|_| "the verifier has stopped".to_owned())
+  }
+}
+
+/// Output being verified
+struct Job<T> {
+  filename: String,
+  context: T,
+  started: Instant,
+  /// Next attempt
+  due: Instant,
+  /// Size and modification time at the previous attempt
+  previous: Option<(u64, SystemTime)>,
+}
+
+impl<T> Job<T> {
+  fn new(filename: String, context: T) -> Self {
+    let now = Instant::now();
+    Job {
+      filename,
+      context,
+      started: now,
+      due: now,
+      previous: None,
+    }
+  }
+
+  /// Returns the result of the verification, or `None` when the output has
+  /// to be checked again later
+  fn attempt(&mut self, settings: &Settings, volumes: &Volumes) -> Option<Result<Verification, Failure>> {
+    let timed_out = self.started.elapsed() > settings.timeout;
+    let (path, current) = match self.check(volumes) {
+      Ok(checked) => checked,
+      Err(Failure::Missing(_)) if !timed_out => return None,
+      Err(failure) => return Some(Err(failure)),
+    };
+    if self.previous != Some(current) {
+      if timed_out {
+        return Some(Err(Failure::Invalid(format!("{} is still being written", self.filename))));
+      }
+      self.previous = Some(current);
+      return None;
+    }
+
+    let (size, modified) = current;
+    if size == 0 {
+      return Some(Err(Failure::Invalid(format!("{} is empty", self.filename))));
+    }
+    let md5 = match checksum(&path, &self.filename) {
+      Ok(md5) => md5,
+      Err(failure) => return Some(Err(failure)),
+    };
+    match stat(&path, &self.filename) {
+      Ok(after) if after == current => {}
+      Ok(_) => {
+        let reason = format!("{} has been modified while computing its checksum", self.filename);
+        return Some(Err(Failure::Invalid(reason)));
+      }
+      Err(failure) => return Some(Err(failure)),
+    }
+
+    Some(Ok(Verification {
+      size,
+      modified: DateTime::<Utc>::from(modified),
+      md5,
+    }))
+  }
+
+  /// Resolved path, size and modification time of the output, which must be
+  /// in one of the volumes, with the symbolic links policy of the browser
+  fn check(&self, volumes: &Volumes) -> Result<(PathBuf, (u64, SystemTime)), Failure> {
+    let (_, path) = volumes.resolve_absolute(&self.filename).map_err(|error| match error {
+      PathError::NotFound(_) => Failure::Missing(error.to_string()),
+      PathError::OutsideRoot(_) => Failure::Invalid(format!("{} is outside of the browsing volumes", self.filename)),
+      _ => Failure::Invalid(error.to_string()),
+    })?;
+    let current = stat(&path, &self.filename)?;
+    Ok((path, current))
+  }
+}
+
+fn access_failure(filename: &str, error: &::std::io::Error) -> Failure {
+  let reason = format!("unable to access {}: {}", filename, error);
+  if error.kind() == ErrorKind::NotFound {
+    Failure::Missing(reason)
+  } else {
+    Failure::Invalid(reason)
+  }
+}
+
+fn stat(path: &Path, filename: &str) -> Result<(u64, SystemTime), Failure> {
+  let metadata = fs::metadata(path).map_err(|e| access_failure(filename, &e))?;
+  if !metadata.is_file() {
+    return Err(Failure::Invalid(format!("{} is not a file", filename)));
+  }
+  let modified = metadata.modified().map_err(|e| Failure::Invalid(e.to_string()))?;
+  Ok((metadata.len(), modified))
+}
+
+fn checksum(path: &Path, filename: &str) -> Result<String, Failure> {
+  let unreadable = |e: ::std::io::Error| Failure::Invalid(format!("unable to read {}: {}", filename, e));
+  let mut file = File::open(path).map_err(unreadable)?;
+  let mut context = md5::Context::new();
+  let mut buffer = vec![0; BUFFER_SIZE];
+
+  loop {
+    let length = file.read(&mut buffer).map_err(unreadable)?;
+    if length == 0 {
+      break;
+    }
+    context.consume(&buffer[..length]);
+  }
+  Ok(format!("{:x}", context.compute()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use path_resolver::SymlinkPolicy;
+  use std::collections::HashMap;
+  use std::env;
+  use std::process;
+
+  #[test]
+  fn verified_in_background() {
+    let directory = env::temp_dir().join(format!("skia_verifier_{}", process::id()));
+    let root = directory.join("root");
+    fs::create_dir_all(&root).unwrap();
+    let volumes = Volumes::single(&root.to_string_lossy(), "", SymlinkPolicy::WithinRoot);
+    let filename = |name: &str, under_root: bool| {
+      let parent = if under_root { &root } else { &directory };
+      parent.join(name).to_string_lossy().into_owned()
+    };
+    fs::write(filename("output.mp4", true), "content").unwrap();
+    fs::write(filename("empty.mp4", true), "").unwrap();
+    fs::write(filename("outside.mp4", false), "content").unwrap();
+
+    let (sender, results) = channel();
+    let settings = Settings {
+      delay: Duration::from_millis(20),
+      timeout: Duration::from_millis(500),
+    };
+    let verifier = Verifier::new(sender, |name, result| (name, result), settings, volumes);
+    for &name in &["output.mp4", "empty.mp4", "late.mp4", "missing.mp4"] {
+      verifier.verify(&filename(name, true), name).unwrap();
+    }
+    verifier.verify(&filename("outside.mp4", false), "outside.mp4").unwrap();
+    // an output moved in place after its encode is logged
+    fs::write(filename("late.mp4", true), "late content").unwrap();
+
+    let mut verifications = HashMap::new();
+    for _ in 0..5 {
+      let (name, result) = results.recv_timeout(Duration::from_secs(5)).unwrap();
+      verifications.insert(name, result);
+    }
+    fs::remove_dir_all(&directory).unwrap();
+
+    match verifications["output.mp4"] {
+      Ok(ref verification) => {
+        assert_eq!(verification.size, 7);
+        assert_eq!(verification.md5, "9a0364b9e99bb480dd25e1f0284c8555");
+      }
+      ref failure => panic!("unexpected {:?}", failure),
+    }
+    assert!(verifications["late.mp4"].is_ok());
+    match verifications["missing.mp4"] {
+      Err(Failure::Missing(_)) => {}
+      ref result => panic!("unexpected {:?}", result),
+    }
+    for &name in &["empty.mp4", "outside.mp4"] {
+      match verifications[name] {
+        Err(Failure::Invalid(_)) => {}
+        ref result => panic!("unexpected {:?}", result),
+      }
+    }
+  }
+}
@@ -0,0 +1,334 @@
+use encoder_log::normalize_path;
+use path_resolver::{PathError, PathResolver, SymlinkPolicy};
+use serde_json::{self, Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the volume of the browsing root, when no volumes are configured
+pub const DEFAULT_VOLUME: &str = "default";
+
+/// Storage area offered to the browser, as written in the volumes file:
+///
+/// ```json
+/// [
+///   {
+///     "name": "ingest",
+///     "display_name": "Ingest",
+///     "root_path": "/mnt/ingest/",
+///     "read_only": true,
+///     "mounted_path": "I:/"
+///   }
+/// ]
+/// ```
+///
+/// where only `name` and `root_path` are required.
+#[derive(Debug, Deserialize)]
+struct VolumeSettings {
+  name: String,
+  #[serde(default)]
+  display_name: String,
+  root_path: String,
+  #[serde(default)]
+  read_only: bool,
+  #[serde(default)]
+  mounted_path: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Volume {
+  /// Identifier of the volume in the paths, `volume:relative/path`
+  pub name: String,
+  pub display_name: String,
+  /// The files of the volume are not to be modified
+  pub read_only: bool,
+  /// Path of the root on the Windows hosts which mount the volume, like `I:/`
+  pub mounted_path: String,
+  pub resolver: PathResolver,
+}
+
+/// Free and total space of a volume, in bytes
+#[derive(Debug)]
+pub struct Space {
+  pub free: u64,
+  pub total: u64,
+}
+
+impl Volume {
+  fn new(settings: VolumeSettings, policy: SymlinkPolicy) -> Self {
+    let display_name =
+      if settings.display_name.is_empty() {
+        settings.name.clone()
+      } else {
+        settings.display_name
+      };
+    Volume {
+      name: settings.name,
+      display_name,
+      read_only: settings.read_only,
+      mounted_path: settings.mounted_path,
+      resolver: PathResolver::new(&settings.root_path, policy),
+    }
+  }
+
+  /// The path relative to the root when it is written with the mounted path
+  fn unmount(&self, path: &str) -> Option<String> {
+    let mounted_path = self.mounted_path.trim_end_matches(&['/', '\\'][..]);
+    if mounted_path.is_empty() || path.len() < mounted_path.len() || !path.is_char_boundary(mounted_path.len()) {
+      return None;
+    }
+    // Windows paths are case insensitive and use both separators
+    let (prefix, relative) = path.split_at(mounted_path.len());
+    if prefix.replace('\\', "/").to_lowercase() != mounted_path.replace('\\', "/").to_lowercase() {
+      return None;
+    }
+    if relative.is_empty() || relative.starts_with(&['/', '\\'][..]) {
+      Some(normalize_path(relative))
+    } else {
+      None
+    }
+  }
+
+  pub fn space(&self) -> Option<Space> {
+    space(Path::new(&self.resolver.root_path))
+  }
+}
+
+impl<'a> From<&'a Volume> for Value {
+  fn from(volume: &'a Volume) -> Self {
+    let mut m = Map::new();
+    m.insert("name".to_string(), volume.name.clone().into());
+    m.insert("display_name".to_string(), volume.display_name.clone().into());
+    m.insert("root_path".to_string(), volume.resolver.root_path.clone().into());
+    m.insert("read_only".to_string(), volume.read_only.into());
+    if !volume.mounted_path.is_empty() {
+      m.insert("mounted_path".to_string(), volume.mounted_path.clone().into());
+    }
+    match volume.space() {
+      Some(space) => {
+        m.insert("available".to_string(), true.into());
+        m.insert("free_space".to_string(), space.free.into());
+        m.insert("total_space".to_string(), space.total.into());
+      }
+      None => {
+        m.insert("available".to_string(), false.into());
+      }
+    }
+    m.into()
+  }
+}
+
+/// The volumes of the agent. A path is addressed as `volume:relative/path`,
+/// by the mounted path of a volume, or relative to the first volume.
+#[derive(Debug, Clone)]
+pub struct Volumes {
+  pub volumes: Vec<Volume>,
+}
+
+impl Volumes {
+  /// The browsing root as the only volume
+  pub fn single(root_path: &str, mounted_path: &str, policy: SymlinkPolicy) -> Self {
+    let settings = VolumeSettings {
+      name: DEFAULT_VOLUME.to_owned(),
+      display_name: String::new(),
+      root_path: root_path.to_owned(),
+      read_only: false,
+      mounted_path: mounted_path.to_owned(),
+    };
+    Volumes {
+      volumes: vec![Volume::new(settings, policy)],
+    }
+  }
+
+  /// Read the volumes file, see `VolumeSettings`
+  pub fn load(filename: &str, policy: SymlinkPolicy) -> Result<Self, String> {
+    let content = fs::read_to_string(filename).map_err(|e| format!("unable to read {}: {}", filename, e))?;
+    let settings: Vec<VolumeSettings> =
+      serde_json::from_str(&content).map_err(|e| format!("unable to parse {}: {}", filename, e))?;
+    if settings.is_empty() {
+      return Err(format!("no volume in {}", filename));
+    }
+
+    let mut names = HashSet::new();
+    for volume in &settings {
+      if volume.name.is_empty() || volume.name.contains(&[':', '/', '\\'][..]) {
+        return Err(format!("invalid volume name {:?} in {}", volume.name, filename));
+      }
+      if !names.insert(volume.name.clone()) {
+        return Err(format!("volume {} is defined twice in {}", volume.name, filename));
+      }
+    }
+
+    Ok(Volumes {
+      volumes: settings.into_iter().map(|settings| Volume::new(settings, policy)).collect(),
+    })
+  }
+
+  pub fn find(&self, name: &str) -> Option<&Volume> {
+    self.volumes.iter().find(|volume| volume.name == name)
+  }
+
+  /// Resolve a path requested by the backend
+  pub fn resolve(&self, address: &str) -> Result<(&Volume, PathBuf), PathError> {
+    let (volume, relative) = self.locate(address);
+    let path = volume.resolver.resolve(&relative)?;
+    Ok((volume, path))
+  }
+
+  /// Resolve the source of an upload, which may also be an absolute path
+  /// under the root of a volume
+  pub fn resolve_absolute(&self, address: &str) -> Result<(&Volume, PathBuf), PathError> {
+    // `//server/share` of a workstation is also absolute on unix
+    for volume in &self.volumes {
+      if let Some(relative) = volume.unmount(address) {
+        return volume.resolver.resolve(&relative).map(|path| (volume, path));
+      }
+    }
+    if !Path::new(address).is_absolute() {
+      return self.resolve(address);
+    }
+    for volume in &self.volumes {
+      match volume.resolver.resolve_absolute(address) {
+        Ok(path) => return Ok((volume, path)),
+        Err(PathError::OutsideRoot(_)) => {}
+        Err(error) => return Err(error),
+      }
+    }
+    Err(PathError::OutsideRoot(address.to_owned()))
+  }
+
+  /// Volume of a path and the path relative to its root
+  fn locate(&self, address: &str) -> (&Volume, String) {
+    // the mounted paths are checked first, they may contain a `:`
+    for volume in &self.volumes {
+      if let Some(relative) = volume.unmount(address) {
+        return (volume, relative);
+      }
+    }
+    // a `:` is only a volume prefix when a volume has that name, otherwise
+    // it is part of the filename, like in `clip 10:30.mp4`
+    if let Some(position) = address.find(':') {
+      let (name, relative) = address.split_at(position);
+      if let Some(volume) = self.find(name) {
+        return (volume, relative[1..].to_owned());
+      }
+    }
+    (&self.volumes[0], address.to_owned())
+  }
+}
+
+#[cfg(unix)]
+fn space(path: &Path) -> Option<Space> {
+  use libc;
+  use std::ffi::CString;
+  use std::mem;
+  use std::os::unix::ffi::OsStrExt;
+
+  let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+  let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+  if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+    return None;
+  }
+  // the sizes of the fields depend on the platform
+  let block_size = stat.f_frsize as u64;
+  Some(Space {
+    free: stat.f_bavail as u64 * block_size,
+    total: stat.f_blocks as u64 * block_size,
+  })
+}
+
+#[cfg(windows)]
+fn space(path: &Path) -> Option<Space> {
+  use std::os::windows::ffi::OsStrExt;
+
+  #[link(name = "kernel32")]
+  extern "system" {
+    fn GetDiskFreeSpaceExW(directory: *const u16, available: *mut u64, total: *mut u64, free: *mut u64) -> i32;
+  }
+
+  let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+  let (mut available, mut total, mut free) = (0, 0, 0);
+  if unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut available, &mut total, &mut free) } == 0 {
+    return None;
+  }
+  Some(Space {
+    free: available,
+    total,
+  })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn space(_path: &Path) -> Option<Space> {
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+  use std::process;
+
+  fn volumes(content: &str) -> Result<Volumes, String> {
+    let path = env::temp_dir().join(format!("skia_volumes_{}.json", process::id()));
+    fs::write(&path, content).unwrap();
+    let volumes = Volumes::load(&path.to_string_lossy(), SymlinkPolicy::WithinRoot);
+    fs::remove_file(&path).unwrap();
+    volumes
+  }
+
+  #[test]
+  fn volume_prefixes() {
+    let volumes = volumes(
+      r#"[
+        { "name": "ingest", "root_path": "/mnt/ingest/", "mounted_path": "I:/" },
+        { "name": "archive", "display_name": "Archive", "root_path": "/mnt/archive/", "read_only": true }
+      ]"#,
+    )
+    .unwrap();
+    let locate = |address| {
+      let (volume, relative) = volumes.locate(address);
+      (volume.name.clone(), relative)
+    };
+    let located = |name: &str, relative: &str| (name.to_owned(), relative.to_owned());
+
+    assert_eq!(locate("archive:2018/clip.mp4"), located("archive", "2018/clip.mp4"));
+    assert_eq!(locate("ingest:"), located("ingest", ""));
+    assert_eq!(locate("i:\\2018\\clip.mp4"), located("ingest", "/2018/clip.mp4"));
+    assert_eq!(locate("I:\\2018\\\\clip.mp4"), located("ingest", "/2018/clip.mp4"));
+    // the `:` of a filename is not a volume prefix
+    assert_eq!(locate("clip 10:30.mp4"), located("ingest", "clip 10:30.mp4"));
+    assert_eq!(locate("2018/clip 10:30.mp4"), located("ingest", "2018/clip 10:30.mp4"));
+    assert_eq!(locate("other:clip.mp4"), located("ingest", "other:clip.mp4"));
+
+    let archive = volumes.find("archive").unwrap();
+    assert!(archive.read_only);
+    assert_eq!(archive.display_name, "Archive");
+    assert_eq!(volumes.find("ingest").unwrap().display_name, "ingest");
+  }
+
+  #[test]
+  fn windows_aliases() {
+    let root = env::temp_dir().join(format!("skia_volume_alias_{}", process::id()));
+    fs::create_dir_all(root.join("2018")).unwrap();
+    fs::write(root.join("2018").join("clip.mp4"), "clip").unwrap();
+    let content = json!([{ "name": "ingest", "root_path": root.to_string_lossy(), "mounted_path": "\\\\nas\\ingest" }]);
+    let volumes = volumes(&content.to_string()).unwrap();
+
+    let (volume, path) = volumes.resolve("\\\\NAS\\ingest\\2018\\clip.mp4").unwrap();
+    assert_eq!(volume.name, "ingest");
+    assert_eq!(path, root.join("2018").join("clip.mp4").canonicalize().unwrap());
+    let (_, path) = volumes.resolve_absolute("//nas/ingest/2018\\clip.mp4").unwrap();
+    assert_eq!(path, root.join("2018").join("clip.mp4").canonicalize().unwrap());
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn invalid_files() {
+    assert!(volumes("[]").is_err());
+    assert!(volumes("{}").is_err());
+    assert!(volumes(r#"[{ "name": "ingest" }]"#).is_err());
+    assert!(volumes(r#"[{ "name": "in:gest", "root_path": "/mnt/ingest/" }]"#).is_err());
+    let twice = r#"[{ "name": "ingest", "root_path": "/a/" }, { "name": "ingest", "root_path": "/b/" }]"#;
+    assert!(volumes(twice).is_err());
+  }
+}